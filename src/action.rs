@@ -0,0 +1,29 @@
+use crate::{data::Location, filter::RelayFilter, random::Scope, rotation::Plan};
+
+/// Something the app can be asked to do, by a keypress or a control client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Connect(Location),
+    Disconnect,
+    Reconnect,
+    /// `wireguard`, `openvpn` or `any`.
+    SetProtocol(String),
+    /// Connect to a random relay other than the current one.
+    Random(Scope),
+    /// Replace the filter the random actions honor.
+    SetFilter(RelayFilter),
+    /// Change relays on a schedule, replacing any running rotation.
+    Rotate(Plan),
+    StopRotation,
+    /// Pause (`true`) or resume the rotation.
+    PauseRotation(bool),
+    /// Ask an outside endpoint whether traffic really goes through Mullvad.
+    CheckLeaks,
+    /// Select the location in the country, city or relay list.
+    Navigate(Location),
+    /// Ask the daemon for a fresh relay list.
+    RefreshRelays,
+    /// Apply every setting of a profile from the config.
+    ApplyProfile(String),
+}
@@ -0,0 +1,135 @@
+use std::net::IpAddr;
+
+use crate::error::AppError;
+
+/// A way for the daemon to reach the Mullvad API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessMethod {
+    /// Position in `mullvad api-access list`, which the CLI uses to refer to it.
+    pub index: usize,
+    pub name: String,
+    pub enabled: bool,
+    /// Indented `Key: value` lines, e.g. the protocol and peer of custom methods.
+    pub details: Vec<(String, String)>,
+}
+
+/// Parses the output of `mullvad api-access list`:
+///
+/// ```text
+/// 1. Direct
+/// 2. Mullvad Bridges
+/// 3. Encrypted DNS proxy (disabled)
+/// 4. office
+///     Protocol: Socks5 (remote)
+///     Peer: 192.0.2.10:1080
+/// ```
+pub fn parse_list(output: &str) -> Vec<AccessMethod> {
+    let mut methods: Vec<AccessMethod> = Vec::new();
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            if let (Some(method), Some((key, value))) = (methods.last_mut(), line.split_once(':')) {
+                method
+                    .details
+                    .push((key.trim().to_string(), value.trim().to_string()));
+            }
+            continue;
+        }
+        let Some((index, rest)) = line.split_once(". ") else {
+            continue;
+        };
+        let Ok(index) = index.trim().parse() else {
+            continue;
+        };
+        let (name, enabled) = match rest.trim().strip_suffix("(disabled)") {
+            Some(name) => (name.trim(), false),
+            None => (rest.trim().trim_end_matches("(enabled)").trim(), true),
+        };
+        methods.push(AccessMethod {
+            index,
+            name: name.to_string(),
+            enabled,
+            details: Vec::new(),
+        });
+    }
+    methods
+}
+
+/// The name of the method in use from `mullvad api-access get`, which prints
+/// either just the name or a `Name: ...` line followed by details.
+pub fn parse_current(output: &str) -> Option<String> {
+    let first = output.lines().find(|line| !line.trim().is_empty())?;
+    let name = match first.split_once(':') {
+        Some((key, value)) if key.trim().eq_ignore_ascii_case("name") => value,
+        _ => first,
+    };
+    Some(name.trim().to_string())
+}
+
+/// The fields of the "new SOCKS5 method" form.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Socks5Form {
+    pub name: String,
+    pub host: String,
+    pub port: String,
+    /// The field being typed in: name, host, port.
+    pub field: usize,
+}
+
+/// A validated custom SOCKS5 method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Socks5 {
+    pub name: String,
+    pub host: IpAddr,
+    pub port: u16,
+}
+
+impl Socks5Form {
+    pub const FIELDS: [&'static str; 3] = ["Name", "Host", "Port"];
+
+    pub fn value(&self, field: usize) -> &str {
+        match field {
+            0 => &self.name,
+            1 => &self.host,
+            _ => &self.port,
+        }
+    }
+
+    pub fn current(&mut self) -> &mut String {
+        match self.field {
+            0 => &mut self.name,
+            1 => &mut self.host,
+            _ => &mut self.port,
+        }
+    }
+
+    pub fn next_field(&mut self) {
+        self.field = (self.field + 1) % Self::FIELDS.len();
+    }
+
+    pub fn previous_field(&mut self) {
+        self.field = (self.field + Self::FIELDS.len() - 1) % Self::FIELDS.len();
+    }
+
+    pub fn validate(&self) -> Result<Socks5, AppError> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(AppError::Parse("The method needs a name".to_string()));
+        }
+        let host = self.host.trim();
+        let host = host
+            .parse()
+            .map_err(|_| AppError::Parse(format!("Host must be an IP address: {}", host)))?;
+        let port = self.port.trim();
+        let port = port.parse::<u16>().ok().filter(|p| *p > 0).ok_or_else(|| {
+            AppError::Parse(format!("Port must be between 1 and 65535: {}", port))
+        })?;
+        Ok(Socks5 {
+            name: name.to_string(),
+            host,
+            port,
+        })
+    }
+}
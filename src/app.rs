@@ -1,33 +1,87 @@
-use std::fmt::Display;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+    time::{Duration, Instant, SystemTime},
+};
 
-use crossterm::event::{self, Event, KeyEvent, KeyEventKind};
+use crossterm::event::{
+    self, Event, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
 use ratatui::{
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Modifier, Style, Stylize},
+    layout::{Alignment, Constraint, Flex, Layout, Margin, Position, Rect},
+    style::{Color, Modifier, Style, Stylize},
     symbols::border,
     text::{Line, Span},
     widgets::{
-        block::{Position, Title},
-        Block, List, ListItem, ListState,
+        block::{self, Title},
+        canvas::{self, Canvas, MapResolution, Points},
+        Block, Borders, Clear, List, ListItem, ListState, Paragraph, Sparkline, Wrap,
     },
     DefaultTerminal, Frame,
 };
 
-use crate::{config, error::AppError};
+use crate::{
+    action::Action,
+    api_access::{AccessMethod, Socks5Form},
+    backend::Backend,
+    bridge::{self, BridgeSettings, ObfuscationMethod, ObfuscationSettings},
+    cache::{self, RelayCache},
+    config,
+    control::{ControlSocket, Event as ControlEvent, Request, Response},
+    data::{
+        Country, CustomList, ExitIpChange, ExitIpCheck, Location, Relay, RelaySummary, TunnelState,
+        TunnelStatus,
+    },
+    error::AppError,
+    filter::RelayFilter,
+    history::History,
+    hooks::{self, HookStatus},
+    leak::{self, LeakCheck},
+    logging::{self, Level},
+    map,
+    notify::{self, CommandNotifier, Notifier},
+    picker::{self, LocationPicker},
+    profile::{self, Change, Settings},
+    query, random,
+    rotation::{self, Clock, Rotation, SystemClock},
+    terminal::{self, Signals},
+    traffic::{self, TrafficMonitor},
+    worker::{self, WorkerEvent},
+};
+
+/// How long to wait for input before redrawing, so timers keep ticking.
+const TICK_RATE: Duration = Duration::from_millis(250);
+const STATUS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Below this width the details pane is hidden and the list takes the whole frame.
+const DETAILS_MIN_WIDTH: u16 = 80;
 
-#[derive(Debug, Default)]
-enum View {
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) enum View {
     #[default]
     Countries,
     Cities,
+    Relays,
     Connection,
+    CustomLists,
+    Bridges,
+    ApiAccess,
+    Map,
+    Profiles,
 }
 
-#[derive(Debug, Default)]
-enum InputMode {
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) enum InputMode {
     #[default]
     Normal,
     Search,
+    ListName,
+    /// Typing an obfuscation port in the bridges view.
+    Port,
+    /// Typing a `:` command.
+    Command,
 }
 
 impl Display for InputMode {
@@ -35,28 +89,160 @@ impl Display for InputMode {
         match self {
             InputMode::Normal => write!(f, "Normal"),
             InputMode::Search => write!(f, "Search"),
+            InputMode::ListName => write!(f, "New list"),
+            InputMode::Command => write!(f, "Command"),
+            InputMode::Port => write!(f, "Port"),
         }
     }
 }
 
-#[derive(Debug, Default)]
-pub struct App {
-    countries: Vec<String>,
-    cities: Vec<String>,
-
-    connection_output: Vec<String>,
-    connected: bool,
+#[derive(Debug, Clone, Copy)]
+enum Latency {
+    Pending,
+    Measured(Duration),
+    Unreachable,
+}
 
-    search_string: String,
+/// Relays pinged at most per highlighted country or city.
+const LATENCY_PROBES: usize = 3;
+
+/// Two clicks on the same row within this window count as a double-click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+/// Height of the log pane when shown.
+const LOG_HEIGHT: u16 = 8;
+
+/// Bridge mode, bridge location, obfuscation, udp2tcp port, Shadowsocks port.
+const BRIDGE_ROWS: usize = 5;
+
+/// A footer key hint: label, key name and the key it stands for.
+type Hint = (&'static str, &'static str, event::KeyCode);
+
+/// Shown after the other hints whenever `?` opens the help.
+const HELP_HINT: Hint = (" Help ", "<?>", event::KeyCode::Char('?'));
+
+/// The keys of the location lists, for the `?` popup; the other views list
+/// theirs in the footer.
+const HELP: &[(&str, &str)] = &[
+    ("j / k", "Move down / up"),
+    ("Enter", "Select or connect"),
+    ("l / h", "Open or expand / go back"),
+    ("g g / G", "Jump to the top / bottom"),
+    ("i or /", "Search"),
+    (":", "Command line"),
+    ("D", "Disconnect"),
+    ("R", "Reconnect for a new exit IP"),
+    ("s / S", "Random relay in the city / country"),
+    ("f", "Random favorite"),
+    ("p", "Pause or resume the rotation"),
+    ("V", "Check for leaks"),
+    ("T", "Switch between lists and tree"),
+    ("a", "Add to or remove from a custom list"),
+    ("c", "Custom lists"),
+    ("b", "Bridges and obfuscation"),
+    ("M", "API access methods"),
+    ("m", "World map"),
+    ("P", "Profiles"),
+    ("r", "Update the relay list"),
+    ("L / v", "Log pane / its level"),
+    ("Ctrl-Z", "Suspend to the shell"),
+    ("q or Esc", "Quit"),
+    ("?", "This help"),
+];
+
+/// A row in the custom lists tree: the list and, for children, the entry.
+type CustomListRow = (usize, Option<usize>);
+
+#[derive(Debug)]
+pub struct App {
+    backend: Arc<dyn Backend>,
+
+    /// The full relay tree, fetched once and kept for the session.
+    relay_list: Vec<Country>,
+    /// When the relay list came from the disk cache, when it was fetched.
+    relays_cached_at: Option<SystemTime>,
+    refreshing: bool,
+    cache_path: Option<PathBuf>,
+
+    picker: LocationPicker,
+    custom_lists: Vec<CustomList>,
+
+    status: TunnelStatus,
+    connected_since: Option<Instant>,
+    events: mpsc::Receiver<WorkerEvent>,
+    events_tx: mpsc::Sender<WorkerEvent>,
+    latencies: HashMap<String, Latency>,
+    traffic: TrafficMonitor,
+
+    /// Held so the socket file is removed when the app exits.
+    _control: Option<ControlSocket>,
+    /// Control clients that asked for state changes.
+    subscribers: Vec<mpsc::Sender<String>>,
+    notifier: Box<dyn Notifier>,
+    /// Set between asking for a disconnect and seeing it, so it is not
+    /// reported as unexpected.
+    disconnect_requested: bool,
+    /// Set by reconnects and random relays until the new exit IP is known.
+    exit_ip_check: Option<ExitIpCheck>,
+    exit_ip_change: Option<ExitIpChange>,
+    /// Active filter of the random actions.
+    filter: RelayFilter,
+    rng: fastrand::Rng,
+    rotation: Option<Rotation>,
+    /// The last leak check, or `None` while one is running.
+    leak_check: Option<Result<LeakCheck, String>>,
+    leak_checking: bool,
+    clock: Box<dyn Clock>,
+
+    list_name: String,
+    port_input: String,
+    command_line: String,
+    history: History,
+    /// Tab completions of the command line and the one shown, while cycling.
+    completions: Vec<String>,
+    completion_index: Option<usize>,
 
     view_mode: View,
     input_mode: InputMode,
     state: ListState,
 
-    awaiting_second_g: bool,
-
-    country_index: usize,
-    city_index: usize,
+    custom_list_index: usize,
+    bridge_index: usize,
+
+    bridge: BridgeSettings,
+    obfuscation: ObfuscationSettings,
+    /// While picking a bridge location, the lists it is picked from. They
+    /// are shown instead of `picker`, which is left as the user had it.
+    bridge_picker: Option<LocationPicker>,
+
+    access_methods: Vec<AccessMethod>,
+    access_method_in_use: Option<String>,
+    access_index: usize,
+    /// Test results by method name, `None` while the test runs.
+    access_tests: HashMap<String, Option<Result<(), String>>>,
+    /// The "new SOCKS5 method" popup, when it is open.
+    socks5_form: Option<Socks5Form>,
+    /// The city selected on the map.
+    map_city: Option<Location>,
+    profile_index: usize,
+    /// The settings the profile previews compare against.
+    profile_settings: Settings,
+
+    /// Where the list and the footer hints were last drawn, for mouse hit-testing.
+    list_area: Rect,
+    hint_areas: Vec<(Rect, event::KeyCode)>,
+    last_click: Option<(Instant, usize)>,
+
+    /// Selected row of the "add to custom list" popup, when it is open.
+    list_picker: Option<usize>,
+    show_help: bool,
+    error: Option<String>,
+
+    show_log: bool,
+    /// Ctrl-Z was pressed; the run loop suspends before the next frame.
+    suspend: bool,
+    /// Least severe level shown in the log pane.
+    log_level: Level,
 
     config: config::Config,
 
@@ -64,559 +250,2399 @@ pub struct App {
 }
 
 impl App {
-    pub fn init(config: Option<String>) -> Result<Self, AppError> {
-        println!("Initializing app...");
-
+    pub fn init(config: Option<String>, backend: Arc<dyn Backend>) -> Result<Self, AppError> {
         let config = match config::Config::load(config.as_deref()) {
             Ok(cfg) => cfg,
             Err(e) => {
-                eprintln!("Failed to load config: {:?}", e);
+                logging::error("app", format!("Failed to load config: {}", e));
                 return Err(AppError::Config(e.to_string()));
             }
         };
 
-        println!("Config loaded successfully.");
-
-        // Check if Mullvad CLI is available
-        let status = match std::process::Command::new("mullvad")
-            .arg("relay")
-            .arg("list")
-            .status() {
-            Ok(s) => s,
-            Err(e) => {
-                eprintln!("Failed to execute 'mullvad relay list': {:?}", e);
-                return Err(AppError::Command(e.to_string()));
+        // Start from the cached relay list when there is one and refresh it
+        // in the background once the UI is up.
+        let cache_path = RelayCache::default_path();
+        let mut app = match cache_path.as_deref().and_then(RelayCache::load) {
+            Some(cache) => {
+                logging::info("app", "Relay list loaded from cache");
+                let updated = cache.updated_at();
+                let mut app = Self::with_relays(config, backend, cache.countries)?;
+                app.relays_cached_at = Some(updated);
+                app
             }
+            None => Self::new(config, backend)?,
         };
-
-        if status.success() {
-            println!("The 'mullvad relay list' command ran successfully.");
-        } else {
-            eprintln!("Error: The 'mullvad relay list' command failed to run.");
-            eprintln!("Please make sure Mullvad CLI is installed and accessible.");
-            return Err(AppError::Command(status.to_string()));
+        app.cache_path = cache_path;
+        app.history = History::default_path()
+            .map(|p| History::load(&p))
+            .unwrap_or_default();
+        if app.relays_cached_at.is_none() {
+            app.save_relay_cache();
         }
 
-        let output = match std::process::Command::new("mullvad")
-            .arg("relay")
-            .arg("list")
-            .output() {
-            Ok(o) => o,
-            Err(e) => {
-                eprintln!("Failed to get output from 'mullvad relay list': {:?}", e);
-                return Err(AppError::Command(e.to_string()));
-            }
-        };
+        Ok(app)
+    }
 
-        let countries: Vec<String> = match String::from_utf8(output.stdout) {
-            Ok(s) => s.lines()
-                .filter_map(|line| {
-                    if line.contains('(') && !line.starts_with('\t') {
-                        Some(line.trim().to_string())
-                    } else {
-                        None
-                    }
-                })
-                .collect(),
+    pub fn new(config: config::Config, backend: Arc<dyn Backend>) -> Result<Self, AppError> {
+        let countries = match backend.relay_list() {
+            Ok(countries) => countries,
             Err(e) => {
-                eprintln!("Failed to parse 'mullvad relay list' output: {:?}", e);
-                return Err(AppError::Parse(e.to_string()));
+                logging::error(
+                    "app",
+                    "Failed to load the relay list, is the Mullvad CLI installed?",
+                );
+                return Err(e);
             }
         };
 
-        println!("Countries list loaded successfully.");
+        Self::with_relays(config, backend, countries)
+    }
 
-        let output = match std::process::Command::new("mullvad")
-            .arg("status")
-            .output() {
-            Ok(o) => o,
+    fn with_relays(
+        config: config::Config,
+        backend: Arc<dyn Backend>,
+        relay_list: Vec<Country>,
+    ) -> Result<Self, AppError> {
+        let status = match backend.status() {
+            Ok(status) => status,
             Err(e) => {
-                eprintln!("Failed to get Mullvad status: {:?}", e);
-                return Err(AppError::Command(e.to_string()));
+                logging::error("app", "Failed to get the tunnel state");
+                return Err(e);
             }
         };
-
-        let connection_status = String::from_utf8(output.stdout)
-            .map(|s| s.contains("Connected"))
-            .unwrap_or(false);
-
-        println!("Connection status: {}", if connection_status { "Connected" } else { "Disconnected" });
+        logging::info("state", format!("Tunnel is {}", status.state));
 
         let mut state = ListState::default();
         state.select(Some(0));
+        let mut picker = LocationPicker::new(relay_list.clone());
+        picker.set_tree_mode(config.tree_view);
+
+        let (events_tx, events) = mpsc::channel();
+        let notifier = Box::new(CommandNotifier::new(&config.notifications.command));
 
         Ok(Self {
-            countries,
-            cities: vec![],
-            connection_output: vec![],
-            connected: connection_status,
-            search_string: String::default(),
-            country_index: 0,
-            city_index: 0,
+            backend,
+            picker,
+            relay_list,
+            relays_cached_at: None,
+            refreshing: false,
+            cache_path: None,
+            custom_lists: vec![],
+            connected_since: status.is_connected().then(Instant::now),
+            status,
+            events,
+            events_tx,
+            latencies: HashMap::new(),
+            traffic: TrafficMonitor::new(traffic::SYSFS_NET),
+            _control: None,
+            subscribers: Vec::new(),
+            notifier,
+            disconnect_requested: false,
+            exit_ip_check: None,
+            exit_ip_change: None,
+            filter: config.filter.clone(),
+            rng: fastrand::Rng::new(),
+            rotation: None,
+            leak_check: None,
+            leak_checking: false,
+            clock: Box::new(SystemClock),
+            list_name: String::default(),
+            port_input: String::default(),
+            command_line: String::default(),
+            history: History::default(),
+            completions: Vec::new(),
+            completion_index: None,
+            custom_list_index: 0,
+            bridge_index: 0,
+            bridge: BridgeSettings::default(),
+            obfuscation: ObfuscationSettings::default(),
+            bridge_picker: None,
+            access_methods: Vec::new(),
+            access_method_in_use: None,
+            access_index: 0,
+            access_tests: HashMap::new(),
+            socks5_form: None,
+            map_city: None,
+            profile_index: 0,
+            profile_settings: Settings::default(),
             input_mode: InputMode::default(),
             view_mode: View::default(),
-            awaiting_second_g: false,
             state,
+            list_area: Rect::default(),
+            hint_areas: Vec::new(),
+            last_click: None,
+            list_picker: None,
+            show_help: false,
+            error: None,
+            show_log: false,
+            suspend: false,
+            log_level: Level::Info,
             config,
             exit: false,
         })
     }
 
-    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), AppError> {
+    pub fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        signals: &Signals,
+    ) -> Result<(), AppError> {
+        worker::spawn_status_worker(
+            self.backend.clone(),
+            STATUS_INTERVAL,
+            self.events_tx.clone(),
+        );
+        if self.relays_cached_at.is_some() {
+            self.refresh_relays(false);
+        }
+
         while !self.exit {
+            if signals.terminated() {
+                logging::info("app", "Terminated by signal");
+                break;
+            }
+            if std::mem::take(&mut self.suspend) || signals.take_suspend() {
+                terminal::suspend(terminal)?;
+            }
             terminal.draw(|frame| self.draw(frame))?;
             self.handle_events()?;
         }
         Ok(())
     }
 
+    /// Accepts requests from other programs on a Unix socket at `path`.
+    pub fn listen(&mut self, path: &Path) -> Result<(), AppError> {
+        self._control = Some(ControlSocket::bind(path, self.events_tx.clone())?);
+        Ok(())
+    }
 
-    fn set_countries(&mut self) -> Result<(), AppError> {
-        let output = std::process::Command::new("mullvad")
-            .arg("relay")
-            .arg("list")
-            .output()?;
+    pub(crate) fn show_error(&mut self, error: AppError) {
+        self.error = Some(error.to_string());
+    }
 
-        self.countries = String::from_utf8(output.stdout)?
-            .lines()
-            .filter_map(|line| {
-                if line.contains('(') && !line.starts_with('\t') {
-                    Some(line.trim().to_string())
-                } else {
-                    None
-                }
-            })
-        .collect();
+    #[cfg(test)]
+    pub(crate) fn set_notifier(&mut self, notifier: Box<dyn Notifier>) {
+        self.notifier = notifier;
+    }
 
-                Ok(())
+    #[cfg(test)]
+    pub(crate) fn events_tx(&self) -> mpsc::Sender<WorkerEvent> {
+        self.events_tx.clone()
     }
 
-    fn set_cities(&mut self) -> Result<(), AppError> {
-        let output = std::process::Command::new("mullvad")
-            .arg("relay")
-            .arg("list")
-            .output()?;
+    #[cfg(test)]
+    pub(crate) fn should_exit(&self) -> bool {
+        self.exit
+    }
 
-        let country = &self.countries[self.country_index];
-        let _country_code = country.split('(').nth(1).unwrap().split(')').next().unwrap();
+    #[cfg(test)]
+    pub(crate) fn view_mode(&self) -> &View {
+        &self.view_mode
+    }
 
-        self.cities = String::from_utf8(output.stdout)?
-            .lines()
-            .skip_while(|line| !line.contains(country))
-            .skip(1)
-            .take_while(|line| line.starts_with('\t'))
-            .filter_map(|line| {
-                if line.contains('(') && line.contains(')') {
-                    Some(line.trim().to_string())
-                } else {
-                    None
-                }
-            })
-        .collect();
+    #[cfg(test)]
+    pub(crate) fn input_mode(&self) -> &InputMode {
+        &self.input_mode
+    }
 
-        Ok(())
+    /// The lists the location views show and the keys move in.
+    fn picker(&self) -> &LocationPicker {
+        self.bridge_picker.as_ref().unwrap_or(&self.picker)
     }
 
-    // fn set_countries(&mut self) -> Result<(), AppError> {
-    //     let output = std::process::Command::new("mullvad")
-    //         .arg("relay list")
-    //         .output()?;
-    //
-    //     self.countries = String::from_utf8(output.stdout)?
-    //         .split_whitespace()
-    //         .map(|s| s.to_string())
-    //         .collect();
-    //
-    //     Ok(())
-    // }
-    //
-    // fn set_cities(&mut self) -> Result<(), AppError> {
-    //     let output = std::process::Command::new("mullvad")
-    //         .arg("cities")
-    //         .arg(&self.countries[self.country_index])
-    //         .output()?;
-    //
-    //     self.cities = String::from_utf8(output.stdout)?
-    //         .split_whitespace()
-    //         .map(|s| s.to_string())
-    //         .collect();
-    //
-    //     Ok(())
-    // }
-
-    fn connect(&mut self) -> Result<View, AppError> {
-        // mullvad relay set location se mma
-        let _relay = std::process::Command::new("mullvad")
-            .arg("relay set location")
-            .arg(&self.countries[self.country_index])
-            .arg(&self.cities[self.city_index])
-            .output()?;
-
-        let output = std::process::Command::new("mullvad")
-            .arg("connect")
-            .output()?;
-
-        self.connected = output.status.success();
-        self.connection_output = String::from_utf8(output.stdout)?
-            .lines()
-            .map(|s| s.to_string())
-            .collect();
+    fn picker_mut(&mut self) -> &mut LocationPicker {
+        match &mut self.bridge_picker {
+            Some(picker) => picker,
+            None => &mut self.picker,
+        }
+    }
 
-        if output.status.success() {
-            Ok(View::Connection)
-        } else {
-            Err(AppError::Command(output.status.to_string()))
+    /// Shows the location lists at the picker's level.
+    fn show_picker(&mut self) {
+        self.view_mode = match self.picker().level() {
+            picker::Level::Countries => View::Countries,
+            picker::Level::Cities => View::Cities,
+            picker::Level::Relays => View::Relays,
+        };
+    }
+
+    fn is_picking(&self) -> bool {
+        matches!(
+            self.view_mode,
+            View::Countries | View::Cities | View::Relays
+        )
+    }
+
+    fn set_custom_lists(&mut self) -> Result<(), AppError> {
+        self.custom_lists = self.backend.custom_lists(&self.relay_list)?;
+        Ok(())
+    }
+
+    /// Re-fetches the relay list in the background, first asking the daemon
+    /// to download a fresh one from the API when `update` is set.
+    fn refresh_relays(&mut self, update: bool) {
+        if self.refreshing {
+            return;
         }
+        self.refreshing = true;
+        worker::spawn_relay_refresh(self.backend.clone(), update, self.events_tx.clone());
     }
 
-    fn disconnect(&mut self) -> Result<(), AppError> {
-        let output = std::process::Command::new("mullvad")
-            .arg("disconnect")
-            .output()?;
-
-        self.connected = !output.status.success();
-        self.connection_output = String::from_utf8(output.stdout)?
-            .lines()
-            .map(|s| s.to_string())
-            .collect();
+    fn set_relay_list(&mut self, relay_list: Vec<Country>) {
+        self.relay_list = relay_list;
+        self.relays_cached_at = None;
+        self.save_relay_cache();
+        self.picker.set_tree(self.relay_list.clone());
+        if let Some(picker) = &mut self.bridge_picker {
+            picker.set_tree(self.relay_list.clone());
+        }
+        if self.is_picking() {
+            self.show_picker();
+        }
+    }
 
-        if output.status.success() {
-            Ok(())
-        } else {
-            Err(AppError::Command(output.status.to_string()))
+    fn save_relay_cache(&self) {
+        if let Some(path) = &self.cache_path {
+            // The cache only speeds up the next start; failing to write it is not fatal.
+            if let Err(e) = RelayCache::new(self.relay_list.clone(), SystemTime::now()).save(path) {
+                logging::warn("app", format!("Failed to write {}: {}", path.display(), e));
+            }
         }
     }
 
-    fn draw(&mut self, f: &mut Frame) {
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .margin(1)
-            .constraints(
-                [
-                Constraint::Length(3),
-                Constraint::Min(0),
-                Constraint::Length(3),
-                ]
-                .as_ref(),
-            )
-            .split(f.area());
+    /// What `a` adds to a custom list: the selection in the lists, the city
+    /// on the map or the relay of the connection view.
+    fn selected_location(&self) -> Option<Location> {
+        match self.view_mode {
+            _ if self.is_picking() => self.picker().selected_location(),
+            View::Map => self.map_city.clone(),
+            View::Connection => {
+                let relay = self.status.relay.as_deref()?;
+                query::find_relay(relay, &self.relay_list)
+            }
+            _ => None,
+        }
+    }
 
-        let title_text = if self.connected {
-            Line::from("Connected").style(Style::default().fg(self.config.colors.connected))
-        } else {
-            Line::from("Disconnected").style(Style::default().fg(self.config.colors.disconnected))
-        };
+    /// Opens the popup toggling the selected location in each custom list.
+    fn open_list_picker(&mut self) -> Result<(), AppError> {
+        self.set_custom_lists()?;
+        if self.custom_lists.is_empty() {
+            return Err(AppError::Command(
+                "No custom lists yet. Create one from the lists view <C>.".to_string(),
+            ));
+        }
+        self.list_picker = Some(0);
+        Ok(())
+    }
 
-        let title = Title::from(title_text.alignment(Alignment::Center));
+    fn open_bridges(&mut self) -> Result<(), AppError> {
+        self.bridge = self.backend.bridge()?;
+        self.obfuscation = self.backend.obfuscation()?;
+        self.bridge_picker = None;
+        self.state.select(Some(self.bridge_index));
+        self.view_mode = View::Bridges;
+        Ok(())
+    }
 
-        let instructions = match self.input_mode {
-            InputMode::Normal => Title::from(
-                Line::from(vec![
-                    " Normal | ".bold(),
-                    " Select ".bold(),
-                    "<Enter>".into(),
-                    " Down ".bold(),
-                    "<J | Down>".into(),
-                    " Up ".bold(),
-                    "<K | Up>".into(),
-                    " Quit ".bold(),
-                    "<Q | Esc>".into(),
-                    " Disconnect ".bold(),
-                    "<D>".into(),
-                ])
-                .style(Style::default().fg(self.config.colors.normal_mode)),
+    /// Label, value and what the setting applies to, per row of the bridges view.
+    fn bridge_rows(&self) -> [(&'static str, String, &'static str); BRIDGE_ROWS] {
+        [
+            ("Bridge mode", self.bridge.mode.clone(), "OpenVPN only"),
+            ("Bridge location", self.bridge.location.clone(), ""),
+            (
+                "Obfuscation",
+                self.obfuscation.mode.clone(),
+                "WireGuard only",
             ),
-            InputMode::Search => {
-                let search_text = format!(" Search: {} | ", self.search_string);
-                let instructions = vec![
-                    search_text.into(),
-                    " Type ".bold(),
-                    "<Esc>".into(),
-                    " to exit search mode ".bold(),
-                    " Type ".bold(),
-                    "<Backspace>".into(),
-                    " to delete ".bold(),
-                ];
-                Title::from(
-                    Line::from(instructions)
-                    .style(Style::default().fg(self.config.colors.search_mode)),
-                )
-            }
-        };
-
-        let block = Block::bordered()
-            .title(title.alignment(Alignment::Center))
-            .title(
-                instructions
-                .alignment(Alignment::Center)
-                .position(Position::Bottom),
-            )
-            .bg(self.config.colors.background)
-            .border_set(border::THICK);
+            (
+                "udp2tcp port",
+                bridge::format_port(self.obfuscation.udp2tcp_port),
+                "",
+            ),
+            (
+                "Shadowsocks port",
+                bridge::format_port(self.obfuscation.shadowsocks_port),
+                "",
+            ),
+        ]
+    }
 
-        match self.view_mode {
-            View::Countries | View::Cities => self.draw_lists(f, chunks[1], block),
-            View::Connection => self.draw_connection(f, chunks[1], block),
+    /// Cycles the selected setting, or opens the picker or port input for it.
+    fn change_bridge_setting(&mut self, back: bool) -> Result<(), AppError> {
+        match self.bridge_index {
+            0 => {
+                let mode = bridge::cycle(&bridge::BRIDGE_MODES, &self.bridge.mode, back);
+                self.backend.set_bridge_state(mode)?;
+            }
+            1 => {
+                let mut picker = LocationPicker::new(self.relay_list.clone());
+                picker.set_tree_mode(self.picker.is_tree());
+                self.bridge_picker = Some(picker);
+                self.view_mode = View::Countries;
+                return Ok(());
+            }
+            2 => {
+                let mode = bridge::cycle(&bridge::OBFUSCATION_MODES, &self.obfuscation.mode, back);
+                self.backend.set_obfuscation_mode(mode)?;
+            }
+            _ => {
+                let port = match self.port_method() {
+                    ObfuscationMethod::Udp2tcp => self.obfuscation.udp2tcp_port,
+                    ObfuscationMethod::Shadowsocks => self.obfuscation.shadowsocks_port,
+                };
+                self.port_input = port.map(|p| p.to_string()).unwrap_or_default();
+                self.input_mode = InputMode::Port;
+                return Ok(());
+            }
         }
+        self.open_bridges()
     }
 
-    fn draw_lists(&mut self, f: &mut Frame, _area: Rect, block: Block) {
-        let mut list = Vec::<ListItem>::new();
+    fn port_method(&self) -> ObfuscationMethod {
+        if self.bridge_index == 3 {
+            ObfuscationMethod::Udp2tcp
+        } else {
+            ObfuscationMethod::Shadowsocks
+        }
+    }
 
-        let l = match self.view_mode {
-            View::Countries => self
-                .countries
-                .clone()
-                .into_iter()
-                .filter(|c| c.to_lowercase().contains(&self.search_string))
-                .collect(),
-            View::Cities => self
-                .cities
-                .clone()
-                .into_iter()
-                .filter(|c| c.to_lowercase().contains(&self.search_string))
-                .collect(),
-            _ => Vec::new(),
+    /// Bridges are picked by country or city; their hostnames differ from relays.
+    fn pick_bridge_location(&mut self, location: Location) -> Result<(), AppError> {
+        let location = match location {
+            Location::Relay(country, city, _) => Location::City(country, city),
+            location => location,
         };
-        for (i, country) in l.iter().enumerate() {
-            let style = match self.view_mode {
-                View::Countries => {
-                    if i == self.country_index {
-                        Style::default().fg(self.config.colors.items_selected)
-                    } else {
-                        Style::default().fg(self.config.colors.items)
-                    }
-                }
-                View::Cities => {
-                    if i == self.city_index {
-                        Style::default().fg(self.config.colors.items_selected)
-                    } else {
-                        Style::default().fg(self.config.colors.items)
-                    }
-                }
-                _ => Style::default().fg(self.config.colors.items),
-            };
-            list.push(ListItem::new(
-                    Line::from(Span::from(country.to_string()))
-                    .alignment(Alignment::Center)
-                    .style(style),
-            ));
+        self.backend.set_bridge_location(&location)?;
+        self.open_bridges()
+    }
+
+    /// The city of the relay the tunnel uses.
+    fn connected_city(&self) -> Option<Location> {
+        let hostname = self.status.relay.as_deref()?;
+        match query::find_relay(hostname, &self.relay_list)? {
+            Location::Relay(country, city, _) => Some(Location::City(country, city)),
+            _ => None,
         }
+    }
 
-        let list = List::new(list).block(block).highlight_style(
-            Style::default()
-            .add_modifier(Modifier::BOLD)
-            .add_modifier(Modifier::ITALIC),
-        );
-        f.render_stateful_widget(list, f.area(), &mut self.state);
+    /// Shows the map with the connected city selected, or the one selected last.
+    fn open_map(&mut self) {
+        let cities = map::cities(&self.relay_list);
+        let known = |location: &Location| cities.iter().any(|c| &c.location == location);
+        self.map_city = self
+            .connected_city()
+            .filter(known)
+            .or_else(|| self.map_city.take().filter(known))
+            .or_else(|| cities.first().map(|c| c.location.clone()));
+        self.view_mode = View::Map;
     }
 
-    fn draw_connection(&mut self, f: &mut Frame, _area: Rect, block: Block) {
-        let mut list = Vec::<ListItem>::new();
+    fn move_on_map(&mut self, direction: map::Direction) {
+        let cities = map::cities(&self.relay_list);
+        let from = cities
+            .iter()
+            .position(|c| Some(&c.location) == self.map_city.as_ref());
+        if let Some(to) = from.and_then(|from| map::nearest(&cities, from, direction)) {
+            self.map_city = Some(cities[to].location.clone());
+        }
+    }
 
-        for line in self.connection_output.iter() {
-            list.push(ListItem::new(
-                    Line::from(Span::from(line.to_string()))
-                    .alignment(Alignment::Center)
-                    .style(Style::default().fg(self.config.colors.connection_output)),
+    fn open_access_methods(&mut self) -> Result<(), AppError> {
+        self.access_methods = self.backend.access_methods()?;
+        self.access_method_in_use = self.backend.access_method_in_use()?;
+        self.access_index = self
+            .access_index
+            .min(self.access_methods.len().saturating_sub(1));
+        self.state.select(Some(self.access_index));
+        self.view_mode = View::ApiAccess;
+        Ok(())
+    }
+
+    fn open_profiles(&mut self) -> Result<(), AppError> {
+        if self.config.profiles.is_empty() {
+            return Err(AppError::Config(
+                "No profiles yet. Add them under [profiles] in the config.".to_string(),
             ));
         }
+        self.profile_settings =
+            Settings::fetch(self.backend.as_ref(), &self.relay_list, Some(&self.filter))?;
+        self.profile_index = self.profile_index.min(self.config.profiles.len() - 1);
+        self.view_mode = View::Profiles;
+        Ok(())
+    }
 
-        let list = List::new(list).block(block).highlight_style(
-            Style::default()
-            .add_modifier(Modifier::BOLD)
-            .add_modifier(Modifier::ITALIC),
-        );
-        f.render_widget(list, f.area());
+    /// What applying the selected profile would change.
+    fn profile_changes(&self) -> Result<Vec<Change>, AppError> {
+        let Some(profile) = self.config.profiles.values().nth(self.profile_index) else {
+            return Ok(Vec::new());
+        };
+        let location = profile.location(&self.relay_list, &self.config.favorites)?;
+        Ok(profile.changes(&self.profile_settings, location.as_ref()))
     }
 
-    fn handle_events(&mut self) -> Result<(), AppError> {
-        if let Event::Key(key_event) = event::read()? {
-            if key_event.kind == KeyEventKind::Press {
-                self.handle_key_event(key_event)?
+    fn apply_profile(&mut self, name: &str) -> Result<(), AppError> {
+        let profile = self
+            .config
+            .profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| AppError::Config(format!("Unknown profile: {}", name)))?;
+        // Resolve first so a bad location changes nothing.
+        let location = profile.location(&self.relay_list, &self.config.favorites)?;
+        logging::info("profile", format!("Applying {}", name));
+        profile.daemon.apply(self.backend.as_ref())?;
+        if let Some(filter) = profile.filter {
+            self.dispatch(Action::SetFilter(filter))?;
+        }
+        let current = self
+            .status
+            .relay
+            .as_deref()
+            .and_then(|relay| query::find_relay(relay, &self.relay_list));
+        match location {
+            // The preview said the location stays, so don't move to another relay.
+            Some(location) if current.is_some_and(|c| profile::is_within(&c, &location)) => {
+                self.view_mode = View::Connection
             }
+            Some(location) => self.dispatch(Action::Connect(location))?,
+            None if self.view_mode == View::Profiles => self.open_profiles()?,
+            None => {}
         }
         Ok(())
     }
 
-    fn handle_key_event(&mut self, event: KeyEvent) -> Result<(), AppError> {
-        match self.input_mode {
-            InputMode::Normal => self.handle_normal_mode(event)?,
-            InputMode::Search => self.handle_search_mode(event)?,
-        }
-        Ok(())
+    fn custom_list_rows(&self) -> Vec<CustomListRow> {
+        self.custom_lists
+            .iter()
+            .enumerate()
+            .flat_map(|(i, list)| {
+                std::iter::once((i, None))
+                    .chain((0..list.locations.len()).map(move |j| (i, Some(j))))
+            })
+            .collect()
     }
 
-    fn handle_normal_mode(&mut self, event: KeyEvent) -> Result<(), AppError> {
-        match event.code {
-            event::KeyCode::Esc | event::KeyCode::Char('q') => self.exit = true,
-            event::KeyCode::Enter => {
-                self.search_string.clear();
-                self.view_mode = match self.view_mode {
-                    View::Countries => {
-                        self.state.select(Some(0));
-                        self.set_cities()?;
-                        self.city_index = 0;
-                        View::Cities
-                    }
-                    View::Cities => self.connect()?,
-                    View::Connection => {
-                        self.country_index = 0;
-                        self.city_index = 0;
-                        self.set_countries()?;
-                        View::Countries
-                    }
-                };
+    fn set_status(&mut self, status: TunnelStatus) {
+        if !status.is_connected() {
+            self.connected_since = None;
+            self.traffic.reset();
+        } else if !self.status.is_connected() || self.status.relay != status.relay {
+            self.connected_since = Some(Instant::now());
+            if self.config.leak_check.enabled {
+                self.check_leaks();
             }
-            event::KeyCode::Char('D') => self.disconnect()?,
-            event::KeyCode::Down | event::KeyCode::Char('j') => self.increment_index(),
-            event::KeyCode::Up | event::KeyCode::Char('k') => self.decrement_index(),
-            event::KeyCode::Char('G') => match self.view_mode {
-                View::Countries => {
-                    self.country_index = self.countries.len() - 1;
-                    self.state.select(Some(self.country_index));
+        }
+        if status.state != self.status.state {
+            let mut fields = Vec::new();
+            if let Some(relay) = &status.relay {
+                fields.push(("relay", relay.clone()));
+            }
+            let message = format!("{} -> {}", self.status.state, status.state);
+            logging::log(Level::Info, "state", message, fields);
+        }
+        if let Some(notification) =
+            notify::transition(&self.status, &status, self.disconnect_requested)
+        {
+            if self.config.notifications.allows(notification.event) {
+                // A missing notification daemon is no reason to interrupt the user.
+                if let Err(e) = self.notifier.notify(&notification) {
+                    logging::warn("notify", e.to_string());
                 }
-                View::Cities => {
-                    self.city_index = self.cities.len() - 1;
-                    self.state.select(Some(self.city_index))
+            }
+        }
+        if status.state == TunnelState::Disconnected {
+            self.disconnect_requested = false;
+        }
+        if let Some(change) = self
+            .exit_ip_check
+            .as_mut()
+            .and_then(|c| c.update(&status, Instant::now()))
+        {
+            match &change {
+                ExitIpChange::Changed { old, new } => {
+                    logging::info("state", format!("Exit IP changed from {} to {}", old, new))
                 }
-                _ => {}
-            },
-            //TODO: fix `gg` keybind
-            event::KeyCode::Char('g') => {
-                if self.awaiting_second_g {
-                    match self.view_mode {
-                        View::Countries => {
-                            self.country_index = 0;
-                            self.state.select(Some(self.country_index));
-                        }
-                        View::Cities => {
-                            self.city_index = 0;
-                            self.state.select(Some(self.city_index));
-                        }
-                        _ => {}
-                    }
-                    self.awaiting_second_g = false;
-                } else {
-                    self.awaiting_second_g = true;
+                ExitIpChange::Unchanged(ip) => {
+                    logging::warn("state", format!("Exit IP is still {}", ip))
                 }
             }
-            event::KeyCode::Char('/') | event::KeyCode::Char('i') => {
-                self.input_mode = InputMode::Search;
+            self.exit_ip_check = None;
+            self.exit_ip_change = Some(change);
+        }
+        if let Some(event) = hooks::event(&self.status, &status) {
+            if let Some(command) = self.config.hooks.command(event).map(String::from) {
+                logging::info("hook", format!("Running {}: {}", event, command));
+                let timeout = Duration::from_secs(self.config.hooks.timeout);
+                let env = hooks::env(event, &status);
+                hooks::spawn(event, command, env, timeout, self.events_tx.clone());
             }
-            event::KeyCode::Char('h') => match self.view_mode {
-                View::Cities => {
-                    self.set_countries()?;
-                    self.city_index = 0;
-                    self.view_mode = View::Countries;
-                }
-                View::Connection => {
-                    self.set_cities()?;
-                    self.city_index = 0;
-                    self.view_mode = View::Cities;
-                }
-                _ => {}
-            },
-            _ => {}
         }
-        Ok(())
+        if status != self.status && !self.subscribers.is_empty() {
+            let line = ControlEvent::Status(status.clone()).to_line();
+            self.subscribers.retain(|s| s.send(line.clone()).is_ok());
+        }
+        self.status = status;
     }
 
-    fn handle_search_mode(&mut self, event: KeyEvent) -> Result<(), AppError> {
-        match event.code {
-            event::KeyCode::Enter => {
-                self.input_mode = InputMode::Normal;
-                self.view_mode = match self.view_mode {
-                    View::Countries => {
-                        self.set_cities()?;
-                        self.search_string.clear();
-                        View::Cities
-                    }
-                    View::Cities => View::Cities, // self.connect()?,
-                    View::Connection => {
-                        self.country_index = 0;
-                        View::Countries
-                    }
-                };
+    /// Carries out an action requested by a keypress or a control client.
+    pub(crate) fn dispatch(&mut self, action: Action) -> Result<(), AppError> {
+        match action {
+            Action::Quit => self.exit = true,
+            Action::Connect(location) => {
+                self.picker_mut().clear_filter();
+                self.view_mode = self.connect(&location)?;
             }
-            event::KeyCode::Esc => {
-                self.input_mode = InputMode::Normal;
+            Action::Disconnect => self.disconnect()?,
+            Action::Reconnect => {
+                self.expect_new_exit_ip();
+                self.backend.reconnect()?;
+                self.set_status(self.backend.status()?);
             }
-            event::KeyCode::Char(c) => {
-                self.search_string.push(c);
-                self.state.select(Some(0));
-                match self.view_mode {
-                    View::Countries => {
-                        self.countries = self
-                            .countries
-                            .clone()
-                            .into_iter()
-                            .filter(|c| c.to_lowercase().contains(&self.search_string))
-                            .collect();
-                        }
-                    View::Cities => {
-                        self.cities = self
-                            .cities
-                            .clone()
-                            .into_iter()
-                            .filter(|c| c.to_lowercase().contains(&self.search_string))
-                            .collect();
-                        }
-                    View::Connection => {}
+            Action::Random(scope) => {
+                let current = self
+                    .status
+                    .relay
+                    .as_deref()
+                    .and_then(|h| query::find_relay(h, &self.relay_list));
+                let location = random::pick(
+                    scope,
+                    current.as_ref(),
+                    &self.relay_list,
+                    &self.config.favorites,
+                    &self.filter,
+                    &mut self.rng,
+                )?;
+                logging::info("app", format!("Random relay in {}: {}", scope, location));
+                self.expect_new_exit_ip();
+                self.picker_mut().clear_filter();
+                self.view_mode = self.connect(&location)?;
+            }
+            Action::Rotate(plan) => {
+                if let rotation::Pool::CustomList(_) = plan.pool {
+                    self.set_custom_lists()?;
                 }
-                self.country_index = 0;
+                // Fail now rather than at the first rotation.
+                self.rotation_pool(&plan.pool)?;
+                logging::info(
+                    "rotation",
+                    format!(
+                        "Rotating every {} min through {}",
+                        plan.interval.as_secs() / 60,
+                        plan.pool
+                    ),
+                );
+                self.rotation = Some(Rotation::new(plan, self.clock.now()));
             }
-            event::KeyCode::Backspace => {
-                self.search_string.pop();
-                self.set_countries()?;
-                self.country_index = 0;
+            Action::StopRotation => {
+                if self.rotation.take().is_some() {
+                    logging::info("rotation", "Stopped");
+                }
             }
-            _ => {}
+            Action::PauseRotation(pause) => {
+                let now = self.clock.now();
+                if let Some(rotation) = &mut self.rotation {
+                    if pause {
+                        rotation.pause(now);
+                    } else {
+                        rotation.resume(now);
+                    }
+                    logging::info("rotation", if pause { "Paused" } else { "Resumed" });
+                }
+            }
+            Action::CheckLeaks => self.check_leaks(),
+            Action::SetFilter(filter) => {
+                logging::info("app", format!("Relay filter: {}", filter));
+                self.filter = filter;
+            }
+            Action::SetProtocol(protocol) => self.backend.set_tunnel_protocol(&protocol)?,
+            Action::Navigate(location) => self.navigate(&location)?,
+            Action::RefreshRelays => self.refresh_relays(true),
+            Action::ApplyProfile(name) => self.apply_profile(&name)?,
         }
         Ok(())
     }
 
-    fn decrement_country(&mut self) {
-        if self.country_index > 0 {
-            self.country_index -= 1;
+    fn rotation_pool(&self, pool: &rotation::Pool) -> Result<Vec<Location>, AppError> {
+        rotation::pool_relays(
+            pool,
+            &self.relay_list,
+            &self.custom_lists,
+            &self.config.favorites,
+            &self.filter,
+        )
+    }
+
+    /// Connects to the next relay of the rotation once it is due. Failures
+    /// are logged here, the caller only has to show them.
+    pub(crate) fn rotate_if_due(&mut self) -> Result<(), AppError> {
+        let now = self.clock.now();
+        let Some(rotation) = self.rotation.as_ref().filter(|r| r.is_due(now)) else {
+            return Ok(());
+        };
+        let pool = match self.rotation_pool(&rotation.plan.pool) {
+            Ok(pool) => pool,
+            Err(e) => {
+                // The list or favorites are gone; retrying every tick would only repeat the error.
+                logging::warn(
+                    "rotation",
+                    format!("Stopped rotating through {}: {}", rotation.plan.pool, e),
+                );
+                self.rotation = None;
+                return Err(e);
+            }
+        };
+        let current = self.status.relay.clone();
+        let Some(rotation) = &mut self.rotation else {
+            return Ok(());
+        };
+        let pool_name = rotation.plan.pool.to_string();
+        let result = rotation
+            .next(now, current.as_deref(), &pool, &mut self.rng)
+            .and_then(|location| {
+                logging::log(
+                    Level::Info,
+                    "rotation",
+                    format!("Rotating to {}", location),
+                    vec![("pool", pool_name)],
+                );
+                self.expect_new_exit_ip();
+                self.connect(&location)
+            });
+        if let Err(e) = &result {
+            logging::error("rotation", e.to_string());
+        }
+        result.map(|_| ())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    fn check_leaks(&mut self) {
+        if self.leak_checking {
+            return;
         }
-        self.state.select(Some(self.country_index));
+        self.leak_checking = true;
+        leak::spawn(self.config.leak_check.clone(), self.events_tx.clone());
     }
 
-    fn increment_country(&mut self) {
-        if self.country_index < self.countries.len() - 1 {
-            self.country_index += 1;
+    fn set_leak_check(&mut self, result: Result<LeakCheck, String>) {
+        self.leak_checking = false;
+        match &result {
+            Ok(check) => {
+                let fields = vec![
+                    ("ip", check.ip.clone()),
+                    ("mullvad", check.mullvad_exit_ip.to_string()),
+                ];
+                logging::log(Level::Info, "leak", "Checked the exit IP", fields);
+                if let Some(warning) = leak::disagreement(&self.status, check) {
+                    logging::warn("leak", warning.clone());
+                    self.error = Some(warning);
+                }
+            }
+            Err(e) => logging::warn("leak", format!("Leak check failed: {}", e)),
         }
-        self.state.select(Some(self.country_index));
+        self.leak_check = Some(result);
+    }
+
+    /// Starts watching the status for the exit IP to change.
+    fn expect_new_exit_ip(&mut self) {
+        self.exit_ip_check = ExitIpCheck::start(&self.status, Instant::now());
+        self.exit_ip_change = None;
+    }
+
+    /// Shows the list the location is in with the location selected.
+    fn navigate(&mut self, location: &Location) -> Result<(), AppError> {
+        self.picker_mut().navigate(location)?;
+        self.input_mode = InputMode::Normal;
+        self.show_picker();
+        Ok(())
+    }
+
+    fn handle_control(&mut self, request: Request, reply: mpsc::Sender<String>) {
+        // The same locations as `:connect`, favorites and bare hostnames included.
+        let location = |args: &[String]| {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            query::resolve(&args, &self.relay_list, &self.config.favorites)
+        };
+        let result = match request {
+            Request::State => Ok(()),
+            Request::Connect { location: args } => {
+                location(&args).and_then(|l| self.dispatch(Action::Connect(l)))
+            }
+            Request::Disconnect => self.dispatch(Action::Disconnect),
+            Request::Navigate { location: args } => {
+                location(&args).and_then(|l| self.dispatch(Action::Navigate(l)))
+            }
+            Request::Subscribe => {
+                self.subscribers.push(reply.clone());
+                Ok(())
+            }
+        };
+        let response = match result {
+            Ok(()) => Response::ok(&self.status),
+            Err(e) => Response::error(e),
+        };
+        let _ = reply.send(response.to_line());
+    }
+
+    fn connect(&mut self, location: &Location) -> Result<View, AppError> {
+        // mullvad relay set location se mma
+        self.backend.set_location(location)?;
+        self.start_connection()
+    }
+
+    fn connect_custom_list(&mut self, name: &str) -> Result<View, AppError> {
+        self.backend.set_custom_list(name)?;
+        self.start_connection()
+    }
+
+    fn start_connection(&mut self) -> Result<View, AppError> {
+        self.backend.connect()?;
+        self.set_status(self.backend.status()?);
+        Ok(View::Connection)
+    }
+
+    fn disconnect(&mut self) -> Result<(), AppError> {
+        self.disconnect_requested = true;
+        self.backend.disconnect()?;
+        self.set_status(self.backend.status()?);
+        Ok(())
     }
 
-    fn decrement_city(&mut self) {
-        if self.city_index > 0 {
-            self.city_index -= 1;
+    /// Adds the location to the list, or removes it if it is already there.
+    fn toggle_in_custom_list(
+        &mut self,
+        list_index: usize,
+        location: &Location,
+    ) -> Result<(), AppError> {
+        let Some(list) = self.custom_lists.get(list_index) else {
+            return Ok(());
+        };
+        if list
+            .locations
+            .iter()
+            .any(|e| e.location.as_ref() == Some(location))
+        {
+            self.backend.remove_from_custom_list(&list.name, location)?;
+        } else {
+            self.backend.add_to_custom_list(&list.name, location)?;
         }
-        self.state.select(Some(self.city_index));
+        self.set_custom_lists()
     }
 
-    fn increment_city(&mut self) {
-        if self.city_index < self.cities.len() - 1 {
-            self.city_index += 1;
+    /// Relays behind the highlighted country, city or relay.
+    fn selected_relays(&self) -> Vec<&Relay> {
+        if self.is_picking() {
+            self.picker().selected_relays()
+        } else {
+            Vec::new()
         }
-        self.state.select(Some(self.city_index));
     }
 
-    fn decrement_index(&mut self) {
-        match self.view_mode {
-            View::Countries => self.decrement_country(),
-            View::Cities => self.decrement_city(),
-            _ => {}
+    /// Pings a few relays of the highlighted item that have not been measured yet.
+    fn probe_latency(&mut self) {
+        let probes: Vec<(String, String)> = self
+            .selected_relays()
+            .into_iter()
+            .take(LATENCY_PROBES)
+            .filter(|r| !self.latencies.contains_key(&r.hostname))
+            .map(|r| (r.hostname.clone(), r.ipv4.clone()))
+            .collect();
+        for (hostname, ip) in probes {
+            self.latencies.insert(hostname.clone(), Latency::Pending);
+            worker::spawn_latency_probe(hostname, ip, self.events_tx.clone());
         }
     }
 
-    fn increment_index(&mut self) {
-        match self.view_mode {
-            View::Countries => self.increment_country(),
-            View::Cities => self.increment_city(),
-            _ => {}
+    pub(crate) fn draw(&mut self, f: &mut Frame) {
+        let title_text = if self.status.is_connected() {
+            Line::from(self.status.state.to_string())
+                .style(Style::default().fg(self.config.colors.connected))
+        } else {
+            Line::from(self.status.state.to_string())
+                .style(Style::default().fg(self.config.colors.disconnected))
+        };
+
+        let mut title_text = title_text;
+        if self.refreshing {
+            title_text
+                .push_span(Span::from(" | refreshing relays...").fg(self.config.colors.items));
+        } else if let Some(updated) = self.relays_cached_at {
+            let age = SystemTime::now()
+                .duration_since(updated)
+                .unwrap_or_default();
+            let cached = format!(" | cached {}", cache::format_age(age));
+            title_text.push_span(Span::from(cached).fg(self.config.colors.items));
+        }
+        if let Some(rotation) = &self.rotation {
+            let text = if rotation.is_paused() {
+                " | rotation paused".to_string()
+            } else {
+                let left = rotation.remaining(self.clock.now());
+                format!(" | next relay in {}", rotation::format_countdown(left))
+            };
+            title_text.push_span(Span::from(text).fg(self.config.colors.items));
         }
+
+        let title = Title::from(title_text.alignment(Alignment::Center));
+        self.list_area = Rect::default();
+
+        let (mode, hints, color): (String, &[Hint], _) = match (&self.input_mode, &self.view_mode) {
+            (InputMode::Normal, _) if self.bridge_picker.is_some() => (
+                " Bridge location | ".to_string(),
+                &[
+                    (" Pick ", "<Enter>", event::KeyCode::Enter),
+                    (" Pick country ", "<Space>", event::KeyCode::Char(' ')),
+                    (" Back ", "<H>", event::KeyCode::Char('h')),
+                    (" Cancel ", "<Esc>", event::KeyCode::Esc),
+                ],
+                self.config.colors.normal_mode,
+            ),
+            (InputMode::Normal, View::Bridges) => (
+                " Bridges | ".to_string(),
+                &[
+                    (" Change ", "<Enter | Right>", event::KeyCode::Enter),
+                    (" Down ", "<J | Down>", event::KeyCode::Down),
+                    (" Up ", "<K | Up>", event::KeyCode::Up),
+                    (" Back ", "<H>", event::KeyCode::Char('h')),
+                    (" Quit ", "<Q | Esc>", event::KeyCode::Char('q')),
+                    (" Log ", "<L>", event::KeyCode::Char('L')),
+                ],
+                self.config.colors.normal_mode,
+            ),
+            (InputMode::Normal, View::ApiAccess) => (
+                " API access | ".to_string(),
+                &[
+                    (" Enable/disable ", "<E>", event::KeyCode::Char('e')),
+                    (" Test ", "<T>", event::KeyCode::Char('t')),
+                    (" New SOCKS5 ", "<N>", event::KeyCode::Char('n')),
+                    (" Back ", "<H>", event::KeyCode::Char('h')),
+                    (" Quit ", "<Q | Esc>", event::KeyCode::Char('q')),
+                    (" Log ", "<L>", event::KeyCode::Char('L')),
+                ],
+                self.config.colors.normal_mode,
+            ),
+            (InputMode::Normal, View::Map) => (
+                " Map | ".to_string(),
+                &[
+                    (" Connect ", "<Enter>", event::KeyCode::Enter),
+                    (" Move ", "<Arrows>", event::KeyCode::Right),
+                    (" Add to list ", "<A>", event::KeyCode::Char('a')),
+                    (" Back ", "<H>", event::KeyCode::Char('h')),
+                    (" Quit ", "<Q | Esc>", event::KeyCode::Char('q')),
+                    (" Log ", "<L>", event::KeyCode::Char('L')),
+                ],
+                self.config.colors.normal_mode,
+            ),
+            (InputMode::Normal, View::Profiles) => (
+                " Profiles | ".to_string(),
+                &[
+                    (" Apply ", "<Enter>", event::KeyCode::Enter),
+                    (" Down ", "<J | Down>", event::KeyCode::Down),
+                    (" Up ", "<K | Up>", event::KeyCode::Up),
+                    (" Back ", "<H>", event::KeyCode::Char('h')),
+                    (" Quit ", "<Q | Esc>", event::KeyCode::Char('q')),
+                    (" Log ", "<L>", event::KeyCode::Char('L')),
+                ],
+                self.config.colors.normal_mode,
+            ),
+            (InputMode::Normal, View::CustomLists) => (
+                " Custom lists | ".to_string(),
+                &[
+                    (" Connect ", "<Enter>", event::KeyCode::Enter),
+                    (" New ", "<N>", event::KeyCode::Char('n')),
+                    (" Delete ", "<X>", event::KeyCode::Char('x')),
+                    (" Back ", "<H>", event::KeyCode::Char('h')),
+                    (" Quit ", "<Q | Esc>", event::KeyCode::Char('q')),
+                    (" Log ", "<L>", event::KeyCode::Char('L')),
+                ],
+                self.config.colors.normal_mode,
+            ),
+            (InputMode::Normal, _) => (
+                " Normal | ".to_string(),
+                &[
+                    (" Select ", "<Enter>", event::KeyCode::Enter),
+                    (" Down ", "<J | Down>", event::KeyCode::Down),
+                    (" Up ", "<K | Up>", event::KeyCode::Up),
+                    (" Quit ", "<Q | Esc>", event::KeyCode::Char('q')),
+                    (" Disconnect ", "<D>", event::KeyCode::Char('D')),
+                    (" Lists ", "<C>", event::KeyCode::Char('c')),
+                    (" Add to list ", "<A>", event::KeyCode::Char('a')),
+                    (" Refresh ", "<R>", event::KeyCode::Char('r')),
+                    (" Log ", "<L>", event::KeyCode::Char('L')),
+                ],
+                self.config.colors.normal_mode,
+            ),
+            (InputMode::Search, _) => (
+                format!(" Search: {} | ", self.picker().filter()),
+                &[
+                    (" Exit search ", "<Esc>", event::KeyCode::Esc),
+                    (" Delete ", "<Backspace>", event::KeyCode::Backspace),
+                ],
+                self.config.colors.search_mode,
+            ),
+            (InputMode::Command, _) => (
+                format!(" :{} | ", self.command_line),
+                &[
+                    (" Run ", "<Enter>", event::KeyCode::Enter),
+                    (" Complete ", "<Tab>", event::KeyCode::Tab),
+                    (" History ", "<Up | Down>", event::KeyCode::Up),
+                    (" Cancel ", "<Esc>", event::KeyCode::Esc),
+                ],
+                self.config.colors.search_mode,
+            ),
+            (InputMode::Port, _) => (
+                format!(
+                    " {} port (empty for any): {} | ",
+                    self.port_method(),
+                    self.port_input
+                ),
+                &[
+                    (" Set ", "<Enter>", event::KeyCode::Enter),
+                    (" Cancel ", "<Esc>", event::KeyCode::Esc),
+                ],
+                self.config.colors.search_mode,
+            ),
+            (InputMode::ListName, _) => (
+                format!(" New list: {} | ", self.list_name),
+                &[
+                    (" Create ", "<Enter>", event::KeyCode::Enter),
+                    (" Cancel ", "<Esc>", event::KeyCode::Esc),
+                ],
+                self.config.colors.search_mode,
+            ),
+        };
+
+        let block = Block::bordered()
+            .title(title.alignment(Alignment::Center))
+            .bg(self.config.colors.background)
+            .border_set(border::THICK);
+
+        let mut area = block.inner(f.area()).inner(Margin::new(1, 0));
+        f.render_widget(block, f.area());
+        let help = (self.input_mode == InputMode::Normal).then_some(HELP_HINT);
+        self.draw_footer(f, &mode, hints, help, color);
+
+        if self.show_log {
+            let [main, log] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(LOG_HEIGHT)]).areas(area);
+            self.draw_log(f, log);
+            area = main;
+        }
+
+        match self.view_mode {
+            View::Countries | View::Cities | View::Relays => {
+                if area.width >= DETAILS_MIN_WIDTH {
+                    let [list, details] = Layout::horizontal([
+                        Constraint::Percentage(50),
+                        Constraint::Percentage(50),
+                    ])
+                    .spacing(1)
+                    .areas(area);
+                    self.draw_lists(f, list);
+                    self.draw_details(f, details);
+                } else {
+                    self.draw_lists(f, area);
+                }
+            }
+            View::Connection => self.draw_connection(f, area),
+            View::Bridges => self.draw_bridges(f, area),
+            View::ApiAccess => self.draw_access_methods(f, area),
+            View::Map => self.draw_map(f, area),
+            View::Profiles => self.draw_profiles(f, area),
+            View::CustomLists => self.draw_custom_lists(f, area),
+        }
+
+        if !self.completions.is_empty() {
+            self.draw_completions(f, area);
+        }
+        if self.list_picker.is_some() {
+            self.draw_list_picker(f);
+        }
+        if self.socks5_form.is_some() {
+            self.draw_socks5_form(f);
+        }
+        if self.show_help {
+            self.draw_help(f);
+        }
+        if self.error.is_some() {
+            self.draw_error(f);
+        }
+    }
+
+    /// Draws the key hints over the bottom border, remembering where each
+    /// one landed so it can be clicked. `last` goes at the end and keeps its
+    /// room there when the other hints do not all fit.
+    fn draw_footer(
+        &mut self,
+        f: &mut Frame,
+        mode: &str,
+        hints: &[Hint],
+        last: Option<Hint>,
+        color: Color,
+    ) {
+        let border = f.area();
+        self.hint_areas.clear();
+        if border.height < 2 {
+            return;
+        }
+        let max_width = border.width.saturating_sub(2) as usize;
+        let hint_width =
+            |(label, key, _): &Hint| Span::raw(*label).width() + Span::raw(*key).width();
+
+        // Hints that do not fit are dropped rather than cut in half.
+        let reserved = last.as_ref().map_or(0, hint_width);
+        let mut width = Span::raw(mode).width();
+        let mut shown: Vec<&Hint> = Vec::new();
+        for hint in hints {
+            if width + hint_width(hint) + reserved > max_width {
+                break;
+            }
+            width += hint_width(hint);
+            shown.push(hint);
+        }
+        if let Some(hint) = last.as_ref().filter(|_| width + reserved <= max_width) {
+            width += reserved;
+            shown.push(hint);
+        }
+
+        let mut spans = vec![Span::from(mode.to_string()).bold()];
+        for (label, key, _) in &shown {
+            spans.push(Span::from(*label).bold());
+            spans.push(Span::from(*key));
+        }
+        let line = Line::from(spans).style(Style::default().fg(color));
+
+        let width = width.min(max_width) as u16;
+        let x = border.x + 1 + (max_width as u16 - width) / 2;
+        let y = border.bottom() - 1;
+        f.render_widget(line, Rect::new(x, y, width, 1));
+
+        let mut offset = x + Span::raw(mode).width() as u16;
+        for hint in shown {
+            let area = Rect::new(offset, y, hint_width(hint) as u16, 1);
+            self.hint_areas.push((area, hint.2));
+            offset += area.width;
+        }
+    }
+
+    fn draw_lists(&mut self, f: &mut Frame, area: Rect) {
+        let colors = &self.config.colors;
+        let (items, selected) = (colors.items, colors.items_selected);
+        self.picker_mut().draw(f, area, items, selected);
+        self.list_area = area;
+    }
+
+    fn draw_details(&mut self, f: &mut Frame, area: Rect) {
+        let relays = self.selected_relays();
+        let summary = RelaySummary::new(relays.iter().copied());
+
+        let latency = relays
+            .iter()
+            .filter_map(|r| self.latencies.get(&r.hostname))
+            .fold(None, |best, latency| match (best, *latency) {
+                (Some(Latency::Measured(a)), Latency::Measured(b)) => {
+                    Some(Latency::Measured(a.min(b)))
+                }
+                (Some(Latency::Measured(a)), _) => Some(Latency::Measured(a)),
+                (_, latency) => Some(latency),
+            });
+        let latency = match latency {
+            Some(Latency::Measured(d)) => format!("{} ms", d.as_millis()),
+            Some(Latency::Pending) => "measuring...".to_string(),
+            Some(Latency::Unreachable) => "unreachable".to_string(),
+            None => "n/a".to_string(),
+        };
+
+        let mut rows: Vec<(&str, String)> = Vec::new();
+        match (&self.view_mode, relays.as_slice()) {
+            (View::Relays, [relay]) => {
+                rows.push(("Hostname", relay.hostname.clone()));
+                rows.push(("IPv4", relay.ipv4.clone()));
+                if let Some(ipv6) = &relay.ipv6 {
+                    rows.push(("IPv6", ipv6.clone()));
+                }
+                rows.push(("Protocol", relay.protocol.clone()));
+                rows.push(("Provider", relay.provider.clone()));
+                rows.push((
+                    "Ownership",
+                    if relay.owned {
+                        "Mullvad-owned"
+                    } else {
+                        "rented"
+                    }
+                    .to_string(),
+                ));
+            }
+            _ => {
+                if let (View::Countries, Some(country)) =
+                    (&self.view_mode, self.picker().selected_country())
+                {
+                    rows.push(("Cities", country.cities.len().to_string()));
+                }
+                rows.push(("Relays", summary.relays.to_string()));
+                rows.push((
+                    "Protocols",
+                    summary
+                        .protocols
+                        .iter()
+                        .map(|(protocol, count)| format!("{} ({})", protocol, count))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ));
+                rows.push((
+                    "Ownership",
+                    format!(
+                        "{} owned, {} rented",
+                        summary.owned,
+                        summary.relays - summary.owned
+                    ),
+                ));
+                rows.push((
+                    "Providers",
+                    summary
+                        .providers
+                        .iter()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ));
+            }
+        }
+        rows.push(("Latency", latency));
+
+        let lines: Vec<Line> = rows
+            .into_iter()
+            .map(|(label, value)| {
+                Line::from(vec![
+                    Span::from(format!("{:>10}  ", label)).bold(),
+                    Span::from(value),
+                ])
+            })
+            .collect();
+
+        let paragraph = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::LEFT))
+            .style(Style::default().fg(self.config.colors.items))
+            .wrap(Wrap { trim: false });
+        f.render_widget(paragraph, area);
+    }
+
+    fn draw_connection(&mut self, f: &mut Frame, area: Rect) {
+        let mut list = Vec::<ListItem>::new();
+
+        let mut rows = self.status.details();
+        if let Some(since) = self.connected_since {
+            rows.push(("Connected for", format_duration(since.elapsed())));
+        }
+        match (&self.exit_ip_check, &self.exit_ip_change) {
+            (Some(_), _) => rows.push(("New exit IP", "waiting...".to_string())),
+            (None, Some(ExitIpChange::Changed { old, .. })) => {
+                rows.push(("New exit IP", format!("yes, was {}", old)))
+            }
+            (None, Some(ExitIpChange::Unchanged(_))) => {
+                rows.push(("New exit IP", "no, unchanged".to_string()))
+            }
+            (None, None) => {}
+        }
+        if !self.filter.is_empty() {
+            rows.push(("Filter", self.filter.to_string()));
+        }
+        match &self.leak_check {
+            _ if self.leak_checking => rows.push(("Leak check", "checking...".to_string())),
+            Some(Ok(check)) => {
+                let exit = match (&check.hostname, check.mullvad_exit_ip) {
+                    (Some(hostname), true) => format!("Mullvad exit {} ({})", check.ip, hostname),
+                    (None, true) => format!("Mullvad exit {}", check.ip),
+                    (_, false) => format!("NOT Mullvad, exit {}", check.ip),
+                };
+                rows.push(("Leak check", exit));
+                match check.dns_leaking {
+                    Some(true) => rows.push(("DNS", "leaking".to_string())),
+                    Some(false) => rows.push(("DNS", "no leak".to_string())),
+                    None => rows.push(("DNS", "not checked".to_string())),
+                }
+            }
+            Some(Err(e)) => rows.push(("Leak check", format!("failed: {}", e))),
+            None => {}
+        }
+
+        for (label, value) in rows {
+            list.push(ListItem::new(
+                Line::from(vec![
+                    Span::from(format!("{:>14}  ", label)).bold(),
+                    Span::from(value),
+                ])
+                .style(Style::default().fg(self.config.colors.connection_output)),
+            ));
+        }
+
+        let [details, rx, tx] = Layout::vertical([
+            Constraint::Min(0),
+            Constraint::Length(5),
+            Constraint::Length(5),
+        ])
+        .areas(area);
+
+        let list = List::new(list).highlight_style(
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .add_modifier(Modifier::ITALIC),
+        );
+        f.render_widget(list, details);
+
+        if !self.status.is_connected() {
+            return;
+        }
+        let totals = self.traffic.session_totals();
+        let interface = self.traffic.interface().unwrap_or("no tunnel interface");
+        let graphs = [
+            (
+                rx,
+                "Download",
+                self.traffic.rx_rate(),
+                totals.rx_bytes,
+                &self.traffic.rx_rates,
+            ),
+            (
+                tx,
+                "Upload",
+                self.traffic.tx_rate(),
+                totals.tx_bytes,
+                &self.traffic.tx_rates,
+            ),
+        ];
+        for (area, label, rate, total, history) in graphs {
+            let title = format!(
+                " {} {}/s | session {} | {} ",
+                label,
+                traffic::format_bytes(rate),
+                traffic::format_bytes(total),
+                interface,
+            );
+            // Show the most recent samples that fit the width.
+            let width = area.width.saturating_sub(2) as usize;
+            let data: Vec<u64> = history.iter().rev().take(width).rev().copied().collect();
+            let sparkline = Sparkline::default()
+                .block(Block::default().borders(Borders::TOP).title(title))
+                .data(&data)
+                .style(Style::default().fg(self.config.colors.connection_output));
+            f.render_widget(sparkline, area);
+        }
+    }
+
+    fn draw_bridges(&mut self, f: &mut Frame, area: Rect) {
+        let [active, settings] =
+            Layout::vertical([Constraint::Length(2), Constraint::Min(0)]).areas(area);
+        let summary = bridge::active(&self.status, &self.bridge, &self.obfuscation);
+        f.render_widget(
+            Paragraph::new(Line::from(vec![
+                Span::from("In use: ").bold(),
+                Span::from(summary),
+            ]))
+            .style(Style::default().fg(self.config.colors.connection_output)),
+            active,
+        );
+
+        let list: Vec<ListItem> = self
+            .bridge_rows()
+            .into_iter()
+            .enumerate()
+            .map(|(row, (label, value, applies))| {
+                let color = if row == self.bridge_index {
+                    self.config.colors.items_selected
+                } else {
+                    self.config.colors.items
+                };
+                ListItem::new(Line::from(vec![
+                    Span::from(format!("{:>18}  ", label)).bold(),
+                    Span::from(format!("{:<24}", value)),
+                    Span::from(applies).italic(),
+                ]))
+                .style(Style::default().fg(color))
+            })
+            .collect();
+        f.render_stateful_widget(List::new(list), settings, &mut self.state);
+        self.list_area = settings;
+    }
+
+    /// Cities as dots on a world map, the connected one highlighted and the
+    /// selected one labelled.
+    fn draw_map(&mut self, f: &mut Frame, area: Rect) {
+        let colors = &self.config.colors;
+        let cities = map::cities(&self.relay_list);
+        let connected = self.connected_city();
+        let point = |city: &map::MapCity| (city.coordinates.longitude, city.coordinates.latitude);
+        let canvas = Canvas::default()
+            .background_color(colors.background)
+            .x_bounds([-180.0, 180.0])
+            .y_bounds([-90.0, 90.0])
+            .paint(|ctx| {
+                ctx.draw(&canvas::Map {
+                    resolution: MapResolution::High,
+                    color: colors.items,
+                });
+                ctx.layer();
+                let coords: Vec<(f64, f64)> = cities.iter().map(point).collect();
+                ctx.draw(&Points {
+                    coords: &coords,
+                    color: colors.normal_mode,
+                });
+                for city in &cities {
+                    let (x, y) = point(city);
+                    if Some(&city.location) == self.map_city.as_ref() {
+                        let label = format!("◆ {}", city.name);
+                        ctx.print(x, y, Line::from(label).fg(colors.items_selected).bold());
+                    } else if Some(&city.location) == connected.as_ref() {
+                        ctx.print(x, y, Line::from("●").fg(colors.connected));
+                    }
+                }
+            });
+        f.render_widget(canvas, area);
+        if cities.is_empty() {
+            let message = Paragraph::new("No relay list coordinates for the map.")
+                .alignment(Alignment::Center);
+            f.render_widget(message, area);
+        }
+    }
+
+    fn draw_profiles(&mut self, f: &mut Frame, area: Rect) {
+        let colors = &self.config.colors;
+        let [names, preview] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .spacing(1)
+                .areas(area);
+        let list: Vec<ListItem> = self
+            .config
+            .profiles
+            .keys()
+            .enumerate()
+            .map(|(row, name)| {
+                let item = ListItem::new(name.as_str());
+                if row == self.profile_index {
+                    item.style(Style::default().fg(colors.items_selected).bold())
+                } else {
+                    item.style(Style::default().fg(colors.items))
+                }
+            })
+            .collect();
+        f.render_widget(List::new(list), names);
+
+        let lines: Vec<Line> = match self.profile_changes() {
+            Ok(changes) if changes.is_empty() => vec![Line::from("Nothing to change.")],
+            Ok(changes) => changes
+                .into_iter()
+                .map(|change| {
+                    Line::from(vec![
+                        Span::from(format!("{:>15}  ", change.setting)).bold(),
+                        Span::from(format!("{} → ", change.from)),
+                        Span::from(change.to).fg(colors.items_selected),
+                    ])
+                })
+                .collect(),
+            Err(e) => vec![Line::from(e.to_string()).fg(colors.disconnected)],
+        };
+        let paragraph = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::LEFT))
+            .style(Style::default().fg(colors.items))
+            .wrap(Wrap { trim: false });
+        f.render_widget(paragraph, preview);
+        self.list_area = Rect::default();
+    }
+
+    fn draw_access_methods(&mut self, f: &mut Frame, area: Rect) {
+        let mut list = Vec::<ListItem>::new();
+        for (row, method) in self.access_methods.iter().enumerate() {
+            let in_use = self.access_method_in_use.as_deref() == Some(method.name.as_str());
+            let color = if in_use {
+                self.config.colors.connected
+            } else if row == self.access_index {
+                self.config.colors.items_selected
+            } else {
+                self.config.colors.items
+            };
+            let marker = if in_use { "●" } else { " " };
+            let enabled = if method.enabled {
+                "enabled"
+            } else {
+                "disabled"
+            };
+            let test = match self.access_tests.get(&method.name) {
+                Some(None) => "testing...".to_string(),
+                Some(Some(Ok(()))) => "test passed".to_string(),
+                Some(Some(Err(e))) => format!("test failed: {}", e),
+                None => String::new(),
+            };
+            let mut spans = vec![
+                Span::from(format!("{} {:<28}", marker, method.name)),
+                Span::from(format!("{:<10}", enabled)),
+                Span::from(test),
+            ];
+            if in_use {
+                spans.push(Span::from(" in use").bold());
+            }
+            let mut style = Style::default().fg(color);
+            if row == self.access_index {
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            // One item with the details below, so they scroll into view with the method.
+            let detail = Style::default()
+                .fg(self.config.colors.items)
+                .remove_modifier(Modifier::BOLD);
+            let mut lines = vec![Line::from(spans)];
+            lines.extend(
+                method.details.iter().map(|(key, value)| {
+                    Line::from(format!("    {}: {}", key, value)).style(detail)
+                }),
+            );
+            list.push(ListItem::new(lines).style(style));
+        }
+        if list.is_empty() {
+            list.push(ListItem::new(
+                Line::from("No API access methods.")
+                    .alignment(Alignment::Center)
+                    .style(Style::default().fg(self.config.colors.items)),
+            ));
+        }
+        self.state.select(Some(self.access_index));
+        f.render_stateful_widget(List::new(list), area, &mut self.state);
+        self.list_area = Rect::default();
+    }
+
+    fn draw_socks5_form(&mut self, f: &mut Frame) {
+        let Some(form) = &self.socks5_form else {
+            return;
+        };
+        let lines: Vec<Line> = Socks5Form::FIELDS
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                let cursor = if i == form.field { "▏" } else { "" };
+                let color = if i == form.field {
+                    self.config.colors.items_selected
+                } else {
+                    self.config.colors.items
+                };
+                Line::from(vec![
+                    Span::from(format!("{:>5}: ", label)).bold(),
+                    Span::from(format!("{}{}", form.value(i), cursor)),
+                ])
+                .fg(color)
+            })
+            .collect();
+        let block = Block::bordered()
+            .title(" New SOCKS5 method ")
+            .title_bottom(" Next <Tab> | Add <Enter> | Cancel <Esc> ")
+            .bg(self.config.colors.background)
+            .border_set(border::THICK);
+        let area = popup_area(f.area(), 50, lines.len() as u16 + 2);
+        f.render_widget(Clear, area);
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn draw_custom_lists(&mut self, f: &mut Frame, area: Rect) {
+        let mut list = Vec::<ListItem>::new();
+
+        for (row, (i, entry)) in self.custom_list_rows().into_iter().enumerate() {
+            let custom_list = &self.custom_lists[i];
+            let text = match entry {
+                None => format!("▾ {} ({})", custom_list.name, custom_list.locations.len()),
+                Some(j) => {
+                    let branch = if j + 1 == custom_list.locations.len() {
+                        "└"
+                    } else {
+                        "├"
+                    };
+                    format!("  {} {}", branch, custom_list.locations[j].label)
+                }
+            };
+            let style = if row == self.custom_list_index {
+                Style::default().fg(self.config.colors.items_selected)
+            } else {
+                Style::default().fg(self.config.colors.items)
+            };
+            list.push(ListItem::new(Line::from(Span::from(text)).style(style)));
+        }
+
+        if list.is_empty() {
+            list.push(ListItem::new(
+                Line::from("No custom lists. Press <N> to create one.")
+                    .alignment(Alignment::Center)
+                    .style(Style::default().fg(self.config.colors.items)),
+            ));
+        }
+
+        let list = List::new(list).highlight_style(
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .add_modifier(Modifier::ITALIC),
+        );
+        f.render_stateful_widget(list, area, &mut self.state);
+        self.list_area = area;
+    }
+
+    fn draw_list_picker(&mut self, f: &mut Frame) {
+        let location = self.selected_location();
+        let items: Vec<ListItem> = self
+            .custom_lists
+            .iter()
+            .map(|list| {
+                let member = list
+                    .locations
+                    .iter()
+                    .any(|e| e.location.is_some() && e.location == location);
+                let mark = if member { "[x]" } else { "[ ]" };
+                ListItem::new(format!("{} {}", mark, list.name))
+                    .style(Style::default().fg(self.config.colors.items))
+            })
+            .collect();
+
+        let title = match &location {
+            Some(location) => format!(" Toggle {} in list ", location),
+            None => " Custom lists ".to_string(),
+        };
+        let block = Block::bordered()
+            .title(title)
+            .bg(self.config.colors.background)
+            .border_set(border::THICK);
+
+        let area = popup_area(f.area(), 50, items.len() as u16 + 2);
+        let mut state = ListState::default().with_selected(self.list_picker);
+        let list = List::new(items).block(block).highlight_style(
+            Style::default()
+                .fg(self.config.colors.items_selected)
+                .add_modifier(Modifier::BOLD),
+        );
+        f.render_widget(Clear, area);
+        f.render_stateful_widget(list, area, &mut state);
+    }
+
+    /// The Tab completions on the last row of `area`, the current one highlighted.
+    fn draw_completions(&self, f: &mut Frame, area: Rect) {
+        let row = Rect::new(area.x, area.bottom().saturating_sub(1), area.width, 1);
+        let spans: Vec<Span> = self
+            .completions
+            .iter()
+            .enumerate()
+            .map(|(i, completion)| {
+                let word = completion.rsplit(' ').next().unwrap_or(completion);
+                let span = Span::from(format!(" {} ", word));
+                if Some(i) == self.completion_index {
+                    span.fg(self.config.colors.background)
+                        .bg(self.config.colors.search_mode)
+                } else {
+                    span.fg(self.config.colors.items)
+                }
+            })
+            .collect();
+        f.render_widget(Clear, row);
+        f.render_widget(
+            Paragraph::new(Line::from(spans)).bg(self.config.colors.background),
+            row,
+        );
+    }
+
+    /// The most recent log lines below the current view.
+    fn draw_log(&self, f: &mut Frame, area: Rect) {
+        let block = Block::new()
+            .borders(Borders::TOP)
+            .title(format!(
+                " Log: {} and above <V> ",
+                self.log_level.to_string().to_lowercase()
+            ))
+            .fg(self.config.colors.connection_output);
+        let rows = block.inner(area).height as usize;
+        let lines: Vec<Line> = logging::recent(self.log_level, rows)
+            .iter()
+            .map(|entry| {
+                let color = match entry.level {
+                    Level::Error | Level::Warn => self.config.colors.disconnected,
+                    _ => self.config.colors.connection_output,
+                };
+                Line::from(entry.to_string()).fg(color)
+            })
+            .collect();
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    /// The keys in two columns, or one when the terminal is too narrow.
+    fn draw_help(&self, f: &mut Frame) {
+        const KEY: usize = 10;
+        const ACTION: usize = 36;
+        let columns = if f.area().width as usize >= 2 * (KEY + ACTION) + 4 {
+            2
+        } else {
+            1
+        };
+        let rows = HELP.len().div_ceil(columns);
+        let lines: Vec<Line> = (0..rows)
+            .map(|row| {
+                let spans = HELP
+                    .iter()
+                    .skip(row)
+                    .step_by(rows)
+                    .flat_map(|(key, action)| {
+                        [
+                            Span::from(format!("{:>w$}  ", key, w = KEY - 2)).bold(),
+                            Span::from(format!("{:<w$}", action, w = ACTION)),
+                        ]
+                    });
+                Line::from(spans.collect::<Vec<_>>())
+            })
+            .collect();
+        let block = Block::bordered()
+            .title(Title::from(" Keys ").alignment(Alignment::Center))
+            .title(
+                Title::from(" Close <any key> ")
+                    .alignment(Alignment::Center)
+                    .position(block::Position::Bottom),
+            )
+            .bg(self.config.colors.background)
+            .fg(self.config.colors.items)
+            .border_set(border::THICK);
+
+        let width = (columns * (KEY + ACTION) + 2) as u16;
+        let [area] = Layout::vertical([Constraint::Length(rows as u16 + 2)])
+            .flex(Flex::Center)
+            .areas(f.area());
+        let [area] = Layout::horizontal([Constraint::Length(width)])
+            .flex(Flex::Center)
+            .areas(area);
+        f.render_widget(Clear, area);
+        f.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn draw_error(&mut self, f: &mut Frame) {
+        let Some(error) = &self.error else {
+            return;
+        };
+        let block = Block::bordered()
+            .title(Title::from(" Error ").alignment(Alignment::Center))
+            .title(
+                Title::from(" Dismiss <any key> ")
+                    .alignment(Alignment::Center)
+                    .position(block::Position::Bottom),
+            )
+            .bg(self.config.colors.background)
+            .fg(self.config.colors.disconnected)
+            .border_set(border::THICK);
+        let paragraph = Paragraph::new(error.as_str())
+            .block(block)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true });
+
+        let area = popup_area(f.area(), 60, 7);
+        f.render_widget(Clear, area);
+        f.render_widget(paragraph, area);
+    }
+
+    fn handle_events(&mut self) -> Result<(), AppError> {
+        if event::poll(TICK_RATE)? {
+            let result = match event::read()? {
+                Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                    self.handle_key_event(key_event)
+                }
+                Event::Mouse(mouse_event) => self.handle_mouse_event(mouse_event),
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                self.show_error(e);
+            }
+            self.probe_latency();
+        }
+        self.handle_worker_events();
+        if let Err(e) = self.rotate_if_due() {
+            self.show_error(e);
+        }
+        if self.status.is_connected() {
+            self.traffic.tick(Instant::now());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle_worker_events(&mut self) {
+        let events: Vec<WorkerEvent> = self.events.try_iter().collect();
+        for event in events {
+            match event {
+                WorkerEvent::Status(status) => self.set_status(*status),
+                WorkerEvent::StatusError(e) => {
+                    self.set_status(TunnelStatus {
+                        state: TunnelState::Error(e),
+                        ..TunnelStatus::default()
+                    });
+                }
+                WorkerEvent::Relays { result, manual } => {
+                    self.refreshing = false;
+                    match result {
+                        Ok(relay_list) => self.set_relay_list(relay_list),
+                        // Keep working from the cache when a startup refresh fails.
+                        Err(e) if manual => self.error = Some(e),
+                        Err(e) => logging::warn("app", format!("Relay list refresh failed: {}", e)),
+                    }
+                }
+                WorkerEvent::Latency(hostname, latency) => {
+                    let latency = latency.map_or(Latency::Unreachable, Latency::Measured);
+                    self.latencies.insert(hostname, latency);
+                }
+                WorkerEvent::Hook(result) => {
+                    let level = match result.status {
+                        HookStatus::Exited(Some(0)) => Level::Info,
+                        _ => Level::Warn,
+                    };
+                    logging::log(level, "hook", result.summary(), Vec::new());
+                    for line in result.output.lines() {
+                        logging::info("hook", format!("  {}", line));
+                    }
+                }
+                WorkerEvent::LeakCheck(result) => self.set_leak_check(result),
+                WorkerEvent::AccessMethodTest { name, result } => {
+                    match &result {
+                        Ok(()) => logging::info("api-access", format!("{} reached the API", name)),
+                        Err(e) => logging::warn("api-access", format!("{} failed: {}", name, e)),
+                    }
+                    self.access_tests.insert(name, Some(result));
+                }
+                WorkerEvent::Control { request, reply } => self.handle_control(request, reply),
+            }
+        }
+    }
+
+    fn handle_mouse_event(&mut self, event: MouseEvent) -> Result<(), AppError> {
+        let position = Position::new(event.column, event.row);
+        match event.kind {
+            MouseEventKind::ScrollDown => {
+                self.handle_key_event(KeyEvent::from(event::KeyCode::Down))
+            }
+            MouseEventKind::ScrollUp => self.handle_key_event(KeyEvent::from(event::KeyCode::Up)),
+            MouseEventKind::Down(MouseButton::Left) => {
+                if self.error.take().is_some() || std::mem::take(&mut self.show_help) {
+                    return Ok(());
+                }
+                if let Some((_, code)) = self
+                    .hint_areas
+                    .iter()
+                    .find(|(area, _)| area.contains(position))
+                {
+                    return self.handle_key_event(KeyEvent::from(*code));
+                }
+                if self.list_picker.is_some() || !self.list_area.contains(position) {
+                    return Ok(());
+                }
+
+                let offset = if self.is_picking() {
+                    self.picker().offset()
+                } else {
+                    self.state.offset()
+                };
+                let row = offset + (event.row - self.list_area.y) as usize;
+                if row >= self.visible_rows() {
+                    return Ok(());
+                }
+                let now = Instant::now();
+                let double = matches!(self.last_click, Some((at, r)) if r == row && now.duration_since(at) < DOUBLE_CLICK);
+                self.select_row(row);
+                if double {
+                    self.last_click = None;
+                    self.handle_key_event(KeyEvent::from(event::KeyCode::Enter))
+                } else {
+                    self.last_click = Some((now, row));
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Number of rows in the list of the current view.
+    fn visible_rows(&self) -> usize {
+        match self.view_mode {
+            View::Countries | View::Cities | View::Relays => self.picker().len(),
+            View::CustomLists => self.custom_list_rows().len(),
+            View::Bridges => BRIDGE_ROWS,
+            // Detail lines make rows and methods differ; clicks are not supported.
+            View::Connection | View::ApiAccess | View::Map | View::Profiles => 0,
+        }
+    }
+
+    fn select_row(&mut self, row: usize) {
+        match self.view_mode {
+            View::Countries | View::Cities | View::Relays => {
+                return self.picker_mut().select_row(row)
+            }
+            View::CustomLists => self.custom_list_index = row,
+            View::Bridges => self.bridge_index = row,
+            View::Connection | View::ApiAccess | View::Map | View::Profiles => return,
+        }
+        self.state.select(Some(row));
+    }
+
+    pub(crate) fn handle_key_event(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        if event.code == event::KeyCode::Char('z')
+            && event.modifiers.contains(KeyModifiers::CONTROL)
+        {
+            self.suspend = true;
+            return Ok(());
+        }
+        if self.error.take().is_some() {
+            return Ok(());
+        }
+        if self.show_help {
+            self.show_help = false;
+            return Ok(());
+        }
+        if self.list_picker.is_some() {
+            return self.handle_list_picker(event);
+        }
+        if self.socks5_form.is_some() {
+            return self.handle_socks5_form(event);
+        }
+        if self.input_mode == InputMode::Normal && event.code == event::KeyCode::Char('?') {
+            self.show_help = true;
+            return Ok(());
+        }
+        match self.input_mode {
+            InputMode::Normal => match self.view_mode {
+                View::CustomLists => self.handle_custom_lists(event)?,
+                View::Bridges => self.handle_bridges(event)?,
+                View::ApiAccess => self.handle_access_methods(event)?,
+                View::Map => self.handle_map(event)?,
+                View::Profiles => self.handle_profiles(event)?,
+                _ if self.bridge_picker.is_some() => self.handle_bridge_picker(event)?,
+                _ => self.handle_normal_mode(event)?,
+            },
+            InputMode::Search => self.handle_search_mode(event)?,
+            InputMode::ListName => self.handle_list_name(event)?,
+            InputMode::Command => self.handle_command_line(event)?,
+            InputMode::Port => self.handle_port_input(event)?,
+        }
+        Ok(())
+    }
+
+    fn handle_normal_mode(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        if self.is_picking() && self.picker_mut().handle_key(event.code) {
+            // In tree mode the view follows the kind of row selected.
+            self.show_picker();
+            return Ok(());
+        }
+        match event.code {
+            event::KeyCode::Esc | event::KeyCode::Char('q') => self.dispatch(Action::Quit)?,
+            event::KeyCode::Enter => match self.view_mode {
+                View::Cities | View::Relays => {
+                    if let Some(location) = self.selected_location() {
+                        self.dispatch(Action::Connect(location))?;
+                    }
+                }
+                View::Countries => {
+                    self.picker_mut().enter();
+                    self.show_picker();
+                }
+                View::Connection
+                | View::CustomLists
+                | View::Bridges
+                | View::ApiAccess
+                | View::Map
+                | View::Profiles => {
+                    self.picker_mut().reset();
+                    self.view_mode = View::Countries;
+                }
+            },
+            event::KeyCode::Char('D') => self.dispatch(Action::Disconnect)?,
+            event::KeyCode::Char('/') | event::KeyCode::Char('i') => {
+                self.input_mode = InputMode::Search;
+            }
+            event::KeyCode::Char(':') => self.input_mode = InputMode::Command,
+            event::KeyCode::Char('R') => self.dispatch(Action::Reconnect)?,
+            event::KeyCode::Char('s') => self.dispatch(Action::Random(random::Scope::City))?,
+            event::KeyCode::Char('S') => self.dispatch(Action::Random(random::Scope::Country))?,
+            event::KeyCode::Char('f') => self.dispatch(Action::Random(random::Scope::Favorite))?,
+            event::KeyCode::Char('V') => self.dispatch(Action::CheckLeaks)?,
+            event::KeyCode::Char('p') => {
+                let paused = self.rotation.as_ref().is_some_and(Rotation::is_paused);
+                self.dispatch(Action::PauseRotation(!paused))?;
+            }
+            event::KeyCode::Char('l') | event::KeyCode::Right if self.is_picking() => {
+                self.picker_mut().enter();
+                self.show_picker();
+            }
+            event::KeyCode::Char('T') if self.is_picking() => {
+                let tree = self.picker().is_tree();
+                self.picker_mut().set_tree_mode(!tree);
+                self.show_picker();
+            }
+            event::KeyCode::Char('h') | event::KeyCode::Left => match self.view_mode {
+                View::Countries | View::Cities | View::Relays => {
+                    self.picker_mut().back();
+                    self.show_picker();
+                }
+                View::Connection => self.show_picker(),
+                _ => {}
+            },
+            event::KeyCode::Char('r') => self.dispatch(Action::RefreshRelays)?,
+            event::KeyCode::Char('L') => self.show_log = !self.show_log,
+            event::KeyCode::Char('v') if self.show_log => self.log_level = self.log_level.cycle(),
+            event::KeyCode::Char('b') => self.open_bridges()?,
+            event::KeyCode::Char('M') => self.open_access_methods()?,
+            event::KeyCode::Char('m') => self.open_map(),
+            event::KeyCode::Char('P') => self.open_profiles()?,
+            event::KeyCode::Char('c') => {
+                self.set_custom_lists()?;
+                self.custom_list_index = 0;
+                self.state.select(Some(0));
+                self.view_mode = View::CustomLists;
+            }
+            event::KeyCode::Char('a') if self.selected_location().is_some() => {
+                self.open_list_picker()?
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_custom_lists(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        let rows = self.custom_list_rows();
+        let row = rows.get(self.custom_list_index).copied();
+
+        match event.code {
+            event::KeyCode::Esc | event::KeyCode::Char('q') => self.dispatch(Action::Quit)?,
+            event::KeyCode::Char('h') => {
+                self.picker_mut().reset();
+                self.view_mode = View::Countries;
+            }
+            event::KeyCode::Down | event::KeyCode::Char('j') => {
+                if self.custom_list_index + 1 < rows.len() {
+                    self.custom_list_index += 1;
+                }
+                self.state.select(Some(self.custom_list_index));
+            }
+            event::KeyCode::Up | event::KeyCode::Char('k') => {
+                self.custom_list_index = self.custom_list_index.saturating_sub(1);
+                self.state.select(Some(self.custom_list_index));
+            }
+            event::KeyCode::Char('n') => {
+                self.list_name.clear();
+                self.input_mode = InputMode::ListName;
+            }
+            event::KeyCode::Char('x') => {
+                match row {
+                    Some((i, None)) => {
+                        let name = self.custom_lists[i].name.clone();
+                        self.backend.delete_custom_list(&name)?;
+                    }
+                    Some((i, Some(j))) => {
+                        let list = &self.custom_lists[i];
+                        if let Some(location) = &list.locations[j].location {
+                            self.backend.remove_from_custom_list(&list.name, location)?;
+                        }
+                    }
+                    None => return Ok(()),
+                }
+                self.set_custom_lists()?;
+                let len = self.custom_list_rows().len();
+                self.custom_list_index = self.custom_list_index.min(len.saturating_sub(1));
+                self.state.select(Some(self.custom_list_index));
+            }
+            event::KeyCode::Char('D') => self.dispatch(Action::Disconnect)?,
+            event::KeyCode::Char('L') => self.show_log = !self.show_log,
+            event::KeyCode::Char('v') if self.show_log => self.log_level = self.log_level.cycle(),
+            event::KeyCode::Char(':') => self.input_mode = InputMode::Command,
+            event::KeyCode::Enter => {
+                if let Some((i, _)) = row {
+                    let name = self.custom_lists[i].name.clone();
+                    self.view_mode = self.connect_custom_list(&name)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_bridges(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        match event.code {
+            event::KeyCode::Esc | event::KeyCode::Char('q') => self.dispatch(Action::Quit)?,
+            event::KeyCode::Char('h') => {
+                self.picker_mut().reset();
+                self.view_mode = View::Countries;
+            }
+            event::KeyCode::Down | event::KeyCode::Char('j') => {
+                self.bridge_index = (self.bridge_index + 1).min(BRIDGE_ROWS - 1);
+                self.state.select(Some(self.bridge_index));
+            }
+            event::KeyCode::Up | event::KeyCode::Char('k') => {
+                self.bridge_index = self.bridge_index.saturating_sub(1);
+                self.state.select(Some(self.bridge_index));
+            }
+            event::KeyCode::Enter | event::KeyCode::Right => self.change_bridge_setting(false)?,
+            event::KeyCode::Left => self.change_bridge_setting(true)?,
+            event::KeyCode::Char('D') => self.dispatch(Action::Disconnect)?,
+            event::KeyCode::Char('L') => self.show_log = !self.show_log,
+            event::KeyCode::Char('v') if self.show_log => self.log_level = self.log_level.cycle(),
+            event::KeyCode::Char(':') => self.input_mode = InputMode::Command,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The country and city lists while choosing a bridge location; moving
+    /// around works as usual.
+    fn handle_bridge_picker(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        match event.code {
+            event::KeyCode::Esc | event::KeyCode::Char('q') => self.open_bridges(),
+            event::KeyCode::Enter if self.view_mode != View::Countries => {
+                match self.selected_location() {
+                    Some(location) => self.pick_bridge_location(location),
+                    None => Ok(()),
+                }
+            }
+            event::KeyCode::Char(' ') => match self.selected_location() {
+                Some(location) => self.pick_bridge_location(location),
+                None => Ok(()),
+            },
+            event::KeyCode::Char('h') | event::KeyCode::Left => {
+                if self.picker_mut().back() {
+                    self.show_picker();
+                    Ok(())
+                } else {
+                    self.open_bridges()
+                }
+            }
+            _ => {
+                self.handle_normal_mode(event)?;
+                // Leaving the location lists some other way ends the picking.
+                if !matches!(
+                    self.view_mode,
+                    View::Countries | View::Cities | View::Relays
+                ) {
+                    self.bridge_picker = None;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn handle_access_methods(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        let method = self.access_methods.get(self.access_index).cloned();
+        match event.code {
+            event::KeyCode::Esc | event::KeyCode::Char('q') => self.dispatch(Action::Quit)?,
+            event::KeyCode::Char('h') => {
+                self.picker_mut().reset();
+                self.view_mode = View::Countries;
+            }
+            event::KeyCode::Down | event::KeyCode::Char('j')
+                if self.access_index + 1 < self.access_methods.len() =>
+            {
+                self.access_index += 1;
+            }
+            event::KeyCode::Up | event::KeyCode::Char('k') => {
+                self.access_index = self.access_index.saturating_sub(1);
+            }
+            event::KeyCode::Char('e') | event::KeyCode::Enter => {
+                if let Some(method) = method {
+                    self.backend
+                        .set_access_method_enabled(method.index, !method.enabled)?;
+                    self.open_access_methods()?;
+                }
+            }
+            event::KeyCode::Char('t') => {
+                if let Some(method) = method {
+                    if self.access_tests.get(&method.name) != Some(&None) {
+                        logging::info("api-access", format!("Testing {}", method.name));
+                        self.access_tests.insert(method.name.clone(), None);
+                        worker::spawn_access_method_test(
+                            self.backend.clone(),
+                            method.index,
+                            method.name,
+                            self.events_tx.clone(),
+                        );
+                    }
+                }
+            }
+            event::KeyCode::Char('n') => self.socks5_form = Some(Socks5Form::default()),
+            event::KeyCode::Char('D') => self.dispatch(Action::Disconnect)?,
+            event::KeyCode::Char('L') => self.show_log = !self.show_log,
+            event::KeyCode::Char('v') if self.show_log => self.log_level = self.log_level.cycle(),
+            event::KeyCode::Char(':') => self.input_mode = InputMode::Command,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_profiles(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        match event.code {
+            event::KeyCode::Esc | event::KeyCode::Char('q') => self.dispatch(Action::Quit)?,
+            event::KeyCode::Char('h') => {
+                self.picker_mut().reset();
+                self.view_mode = View::Countries;
+            }
+            event::KeyCode::Down | event::KeyCode::Char('j')
+                if self.profile_index + 1 < self.config.profiles.len() =>
+            {
+                self.profile_index += 1;
+            }
+            event::KeyCode::Up | event::KeyCode::Char('k') => {
+                self.profile_index = self.profile_index.saturating_sub(1);
+            }
+            event::KeyCode::Enter => {
+                if let Some(name) = self.config.profiles.keys().nth(self.profile_index).cloned() {
+                    self.dispatch(Action::ApplyProfile(name))?;
+                }
+            }
+            event::KeyCode::Char('D') => self.dispatch(Action::Disconnect)?,
+            event::KeyCode::Char('L') => self.show_log = !self.show_log,
+            event::KeyCode::Char('v') if self.show_log => self.log_level = self.log_level.cycle(),
+            event::KeyCode::Char(':') => self.input_mode = InputMode::Command,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_map(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        match event.code {
+            event::KeyCode::Esc | event::KeyCode::Char('q') => self.dispatch(Action::Quit)?,
+            event::KeyCode::Char('h') => {
+                self.picker_mut().reset();
+                self.view_mode = View::Countries;
+            }
+            event::KeyCode::Up => self.move_on_map(map::Direction::Up),
+            event::KeyCode::Down => self.move_on_map(map::Direction::Down),
+            event::KeyCode::Left => self.move_on_map(map::Direction::Left),
+            event::KeyCode::Right => self.move_on_map(map::Direction::Right),
+            event::KeyCode::Enter => {
+                if let Some(location) = self.map_city.clone() {
+                    self.dispatch(Action::Connect(location))?;
+                }
+            }
+            event::KeyCode::Char('a') if self.map_city.is_some() => self.open_list_picker()?,
+            event::KeyCode::Char('D') => self.dispatch(Action::Disconnect)?,
+            event::KeyCode::Char('L') => self.show_log = !self.show_log,
+            event::KeyCode::Char('v') if self.show_log => self.log_level = self.log_level.cycle(),
+            event::KeyCode::Char(':') => self.input_mode = InputMode::Command,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Typing in the "new SOCKS5 method" popup; invalid input keeps it open.
+    fn handle_socks5_form(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        let Some(form) = &mut self.socks5_form else {
+            return Ok(());
+        };
+        match event.code {
+            event::KeyCode::Esc => self.socks5_form = None,
+            event::KeyCode::Tab | event::KeyCode::Down => form.next_field(),
+            event::KeyCode::BackTab | event::KeyCode::Up => form.previous_field(),
+            event::KeyCode::Enter if form.field + 1 < Socks5Form::FIELDS.len() => form.next_field(),
+            event::KeyCode::Enter => {
+                let socks5 = form.validate()?;
+                self.backend.add_socks5_access_method(&socks5)?;
+                logging::info("api-access", format!("Added SOCKS5 method {}", socks5.name));
+                self.socks5_form = None;
+                self.open_access_methods()?;
+            }
+            event::KeyCode::Char(c) => form.current().push(c),
+            event::KeyCode::Backspace => {
+                form.current().pop();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_port_input(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        match event.code {
+            event::KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                let input = self.port_input.trim();
+                let port = match input {
+                    "" | "any" => None,
+                    input => Some(
+                        input
+                            .parse::<u16>()
+                            .ok()
+                            .filter(|p| *p > 0)
+                            .ok_or_else(|| AppError::Parse(format!("Invalid port: {}", input)))?,
+                    ),
+                };
+                self.backend
+                    .set_obfuscation_port(self.port_method(), port)?;
+                self.open_bridges()?;
+            }
+            event::KeyCode::Esc => self.input_mode = InputMode::Normal,
+            event::KeyCode::Char(c) if c.is_ascii_digit() => self.port_input.push(c),
+            event::KeyCode::Backspace => {
+                self.port_input.pop();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_list_name(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        match event.code {
+            event::KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                let name = self.list_name.trim().to_string();
+                if !name.is_empty() {
+                    self.backend.create_custom_list(&name)?;
+                    self.set_custom_lists()?;
+                }
+            }
+            event::KeyCode::Esc => self.input_mode = InputMode::Normal,
+            event::KeyCode::Char(c) => self.list_name.push(c),
+            event::KeyCode::Backspace => {
+                self.list_name.pop();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_command_line(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        if event.code != event::KeyCode::Tab {
+            self.completions.clear();
+            self.completion_index = None;
+        }
+        match event.code {
+            event::KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                let line = std::mem::take(&mut self.command_line);
+                let line = line.trim();
+                if line.is_empty() {
+                    return Ok(());
+                }
+                let action = query::parse(line, &self.relay_list, &self.config.favorites);
+                // Typos are not worth recalling.
+                if action.is_ok() {
+                    if let Err(e) = self.history.push(line) {
+                        logging::warn("app", format!("Failed to save the command history: {}", e));
+                    }
+                } else {
+                    self.history.reset();
+                }
+                let action = action?;
+                self.dispatch(action)?;
+            }
+            event::KeyCode::Esc => {
+                self.command_line.clear();
+                self.history.reset();
+                self.input_mode = InputMode::Normal;
+            }
+            event::KeyCode::Tab => self.complete_command_line(),
+            event::KeyCode::Up => {
+                if let Some(command) = self.history.previous() {
+                    self.command_line = command.to_string();
+                }
+            }
+            event::KeyCode::Down => {
+                self.command_line = self.history.next().unwrap_or_default().to_string();
+            }
+            event::KeyCode::Char(c) => self.command_line.push(c),
+            event::KeyCode::Backspace => {
+                self.command_line.pop();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Completes a single candidate outright; with several, each Tab shows the next.
+    fn complete_command_line(&mut self) {
+        if let Some(i) = self.completion_index {
+            let i = (i + 1) % self.completions.len();
+            self.completion_index = Some(i);
+            self.command_line = self.completions[i].clone();
+            return;
+        }
+        let completions =
+            query::complete(&self.command_line, &self.relay_list, &self.config.favorites);
+        match completions.as_slice() {
+            [] => {}
+            [only] => self.command_line = format!("{} ", only),
+            [first, ..] => {
+                self.command_line = first.clone();
+                self.completion_index = Some(0);
+                self.completions = completions;
+            }
+        }
+    }
+
+    fn handle_list_picker(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        let Some(index) = self.list_picker else {
+            return Ok(());
+        };
+        match event.code {
+            event::KeyCode::Esc | event::KeyCode::Char('q') => self.list_picker = None,
+            event::KeyCode::Down | event::KeyCode::Char('j')
+                if index + 1 < self.custom_lists.len() =>
+            {
+                self.list_picker = Some(index + 1);
+            }
+            event::KeyCode::Up | event::KeyCode::Char('k') => {
+                self.list_picker = Some(index.saturating_sub(1));
+            }
+            event::KeyCode::Enter | event::KeyCode::Char(' ') => {
+                if let Some(location) = self.selected_location() {
+                    self.toggle_in_custom_list(index, &location)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_search_mode(&mut self, event: KeyEvent) -> Result<(), AppError> {
+        match event.code {
+            event::KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                match self.view_mode {
+                    // The tree already shows the matches in place.
+                    View::Countries | View::Cities | View::Relays if self.picker().is_tree() => {
+                        self.picker_mut().clear_filter()
+                    }
+                    View::Countries => {
+                        self.picker_mut().enter();
+                    }
+                    View::Cities | View::Relays => {}
+                    View::Connection
+                    | View::CustomLists
+                    | View::Bridges
+                    | View::ApiAccess
+                    | View::Map
+                    | View::Profiles => self.picker_mut().top(),
+                }
+                self.show_picker();
+            }
+            event::KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+            }
+            event::KeyCode::Char(c) => self.picker_mut().push_filter(c),
+            event::KeyCode::Backspace => self.picker_mut().pop_filter(),
+            _ => {}
+        }
+        if self.is_picking() {
+            self.show_picker();
+        }
+        Ok(())
+    }
+}
+
+/// A rectangle of the given width (in percent) and height centered in `area`.
+fn popup_area(area: Rect, percent_x: u16, height: u16) -> Rect {
+    let vertical = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center);
+    let horizontal = Layout::horizontal([Constraint::Percentage(percent_x)]).flex(Flex::Center);
+    let [area] = vertical.areas(area);
+    let [area] = horizontal.areas(area);
+    area
+}
+
+/// Formats a duration as `1h 02m 03s`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, s) => format!("{}h {:02}m {:02}s", h, m, s),
     }
 }
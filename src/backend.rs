@@ -0,0 +1,301 @@
+use std::{process::Command, time::Instant};
+
+use crate::{
+    api_access::{self, AccessMethod, Socks5},
+    bridge::{self, BridgeSettings, ObfuscationMethod, ObfuscationSettings},
+    data::{self, Country, CustomList, Location, TunnelStatus},
+    error::AppError,
+    logging::{self, Level},
+    profile::{self, DnsSettings},
+};
+
+/// Everything the app asks of the Mullvad daemon.
+///
+/// `CliBackend` talks to the real `mullvad` binary; tests can swap in
+/// their own implementation.
+pub trait Backend: std::fmt::Debug + Send + Sync {
+    fn relay_list(&self) -> Result<Vec<Country>, AppError>;
+    fn update_relays(&self) -> Result<(), AppError>;
+    fn status(&self) -> Result<TunnelStatus, AppError>;
+    fn set_location(&self, location: &Location) -> Result<(), AppError>;
+    fn connect(&self) -> Result<(), AppError>;
+    fn disconnect(&self) -> Result<(), AppError>;
+    fn reconnect(&self) -> Result<(), AppError>;
+    /// `wireguard`, `openvpn` or `any`.
+    fn tunnel_protocol(&self) -> Result<Option<String>, AppError>;
+    fn set_tunnel_protocol(&self, protocol: &str) -> Result<(), AppError>;
+    fn dns(&self) -> Result<DnsSettings, AppError>;
+    fn set_dns(&self, dns: &DnsSettings) -> Result<(), AppError>;
+    fn lockdown(&self) -> Result<bool, AppError>;
+    fn set_lockdown(&self, on: bool) -> Result<(), AppError>;
+    fn auto_connect(&self) -> Result<bool, AppError>;
+    fn set_auto_connect(&self, on: bool) -> Result<(), AppError>;
+
+    fn custom_lists(&self, countries: &[Country]) -> Result<Vec<CustomList>, AppError>;
+    fn create_custom_list(&self, name: &str) -> Result<(), AppError>;
+    fn delete_custom_list(&self, name: &str) -> Result<(), AppError>;
+    fn add_to_custom_list(&self, name: &str, location: &Location) -> Result<(), AppError>;
+    fn remove_from_custom_list(&self, name: &str, location: &Location) -> Result<(), AppError>;
+    fn set_custom_list(&self, name: &str) -> Result<(), AppError>;
+
+    fn bridge(&self) -> Result<BridgeSettings, AppError>;
+    /// `auto`, `on` or `off`.
+    fn set_bridge_state(&self, state: &str) -> Result<(), AppError>;
+    fn set_bridge_location(&self, location: &Location) -> Result<(), AppError>;
+    fn obfuscation(&self) -> Result<ObfuscationSettings, AppError>;
+    /// `auto`, `off`, `udp2tcp` or `shadowsocks`.
+    fn set_obfuscation_mode(&self, mode: &str) -> Result<(), AppError>;
+    /// `None` lets the daemon pick the port.
+    fn set_obfuscation_port(
+        &self,
+        method: ObfuscationMethod,
+        port: Option<u16>,
+    ) -> Result<(), AppError>;
+
+    fn access_methods(&self) -> Result<Vec<AccessMethod>, AppError>;
+    /// The name of the method the daemon currently reaches the API with.
+    fn access_method_in_use(&self) -> Result<Option<String>, AppError>;
+    fn set_access_method_enabled(&self, index: usize, enabled: bool) -> Result<(), AppError>;
+    /// Fails when the API cannot be reached with the method.
+    fn test_access_method(&self, index: usize) -> Result<(), AppError>;
+    fn add_socks5_access_method(&self, socks5: &Socks5) -> Result<(), AppError>;
+}
+
+#[derive(Debug)]
+pub struct CliBackend {
+    bin: String,
+}
+
+impl Default for CliBackend {
+    fn default() -> Self {
+        Self::new("mullvad")
+    }
+}
+
+impl CliBackend {
+    pub fn new(bin: &str) -> Self {
+        Self {
+            bin: bin.to_string(),
+        }
+    }
+
+    /// Runs `mullvad <args>` and returns its stdout, failing on a non-zero exit.
+    fn run(&self, args: &[&str]) -> Result<String, AppError> {
+        let argv = format!("{} {}", self.bin, args.join(" "));
+        let started = Instant::now();
+        let output = Command::new(&self.bin).args(args).output().map_err(|e| {
+            logging::log(
+                Level::Error,
+                "backend",
+                &argv,
+                vec![("error", e.to_string())],
+            );
+            AppError::Command(format!("Failed to run '{}': {}", self.bin, e))
+        })?;
+
+        let mut fields = vec![
+            (
+                "exit",
+                output
+                    .status
+                    .code()
+                    .map_or("signal".to_string(), |c| c.to_string()),
+            ),
+            ("duration_ms", started.elapsed().as_millis().to_string()),
+        ];
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !stderr.trim().is_empty() {
+            fields.push(("stderr", stderr.trim().to_string()));
+        }
+        let level = if output.status.success() {
+            Level::Debug
+        } else {
+            Level::Warn
+        };
+        logging::log(level, "backend", argv, fields);
+
+        if output.status.success() {
+            Ok(String::from_utf8(output.stdout)?)
+        } else {
+            let message = stderr.trim();
+            Err(AppError::Command(if message.is_empty() {
+                format!("mullvad {}: {}", args.join(" "), output.status)
+            } else {
+                format!("mullvad {}: {}", args.join(" "), message)
+            }))
+        }
+    }
+}
+
+impl Backend for CliBackend {
+    fn relay_list(&self) -> Result<Vec<Country>, AppError> {
+        let output = self.run(&["relay", "list"])?;
+        Ok(data::parse_relay_list(&output))
+    }
+
+    fn update_relays(&self) -> Result<(), AppError> {
+        self.run(&["relay", "update"]).map(|_| ())
+    }
+
+    fn status(&self) -> Result<TunnelStatus, AppError> {
+        let output = self.run(&["status", "-v"])?;
+        Ok(data::parse_status(&output))
+    }
+
+    fn set_location(&self, location: &Location) -> Result<(), AppError> {
+        let mut args = vec!["relay", "set", "location"];
+        args.extend(location.args());
+        self.run(&args).map(|_| ())
+    }
+
+    fn connect(&self) -> Result<(), AppError> {
+        self.run(&["connect"]).map(|_| ())
+    }
+
+    fn disconnect(&self) -> Result<(), AppError> {
+        self.run(&["disconnect"]).map(|_| ())
+    }
+
+    fn reconnect(&self) -> Result<(), AppError> {
+        self.run(&["reconnect"]).map(|_| ())
+    }
+
+    fn tunnel_protocol(&self) -> Result<Option<String>, AppError> {
+        Ok(profile::parse_tunnel_protocol(
+            &self.run(&["relay", "get"])?,
+        ))
+    }
+
+    fn set_tunnel_protocol(&self, protocol: &str) -> Result<(), AppError> {
+        self.run(&["relay", "set", "tunnel-protocol", protocol])
+            .map(|_| ())
+    }
+
+    fn dns(&self) -> Result<DnsSettings, AppError> {
+        Ok(profile::parse_dns(&self.run(&["dns", "get"])?))
+    }
+
+    fn set_dns(&self, dns: &DnsSettings) -> Result<(), AppError> {
+        let args = dns.args();
+        let mut command = vec!["dns", "set"];
+        command.extend(args.iter().map(String::as_str));
+        self.run(&command).map(|_| ())
+    }
+
+    fn lockdown(&self) -> Result<bool, AppError> {
+        Ok(profile::parse_toggle(&self.run(&["lockdown-mode", "get"])?))
+    }
+
+    fn set_lockdown(&self, on: bool) -> Result<(), AppError> {
+        self.run(&["lockdown-mode", "set", if on { "on" } else { "off" }])
+            .map(|_| ())
+    }
+
+    fn auto_connect(&self) -> Result<bool, AppError> {
+        Ok(profile::parse_toggle(&self.run(&["auto-connect", "get"])?))
+    }
+
+    fn set_auto_connect(&self, on: bool) -> Result<(), AppError> {
+        self.run(&["auto-connect", "set", if on { "on" } else { "off" }])
+            .map(|_| ())
+    }
+
+    fn custom_lists(&self, countries: &[Country]) -> Result<Vec<CustomList>, AppError> {
+        let output = self.run(&["custom-list", "list"])?;
+        Ok(data::parse_custom_lists(&output, countries))
+    }
+
+    fn create_custom_list(&self, name: &str) -> Result<(), AppError> {
+        self.run(&["custom-list", "new", name]).map(|_| ())
+    }
+
+    fn delete_custom_list(&self, name: &str) -> Result<(), AppError> {
+        self.run(&["custom-list", "delete", name]).map(|_| ())
+    }
+
+    fn add_to_custom_list(&self, name: &str, location: &Location) -> Result<(), AppError> {
+        let mut args = vec!["custom-list", "edit", "add", name];
+        args.extend(location.args());
+        self.run(&args).map(|_| ())
+    }
+
+    fn remove_from_custom_list(&self, name: &str, location: &Location) -> Result<(), AppError> {
+        let mut args = vec!["custom-list", "edit", "remove", name];
+        args.extend(location.args());
+        self.run(&args).map(|_| ())
+    }
+
+    fn set_custom_list(&self, name: &str) -> Result<(), AppError> {
+        self.run(&["relay", "set", "custom-list", name]).map(|_| ())
+    }
+
+    fn bridge(&self) -> Result<BridgeSettings, AppError> {
+        Ok(bridge::parse_bridge(&self.run(&["bridge", "get"])?))
+    }
+
+    fn set_bridge_state(&self, state: &str) -> Result<(), AppError> {
+        self.run(&["bridge", "set", "state", state]).map(|_| ())
+    }
+
+    fn set_bridge_location(&self, location: &Location) -> Result<(), AppError> {
+        let mut args = vec!["bridge", "set", "location"];
+        args.extend(location.args());
+        self.run(&args).map(|_| ())
+    }
+
+    fn obfuscation(&self) -> Result<ObfuscationSettings, AppError> {
+        Ok(bridge::parse_obfuscation(
+            &self.run(&["obfuscation", "get"])?,
+        ))
+    }
+
+    fn set_obfuscation_mode(&self, mode: &str) -> Result<(), AppError> {
+        self.run(&["obfuscation", "set", "mode", mode]).map(|_| ())
+    }
+
+    fn set_obfuscation_port(
+        &self,
+        method: ObfuscationMethod,
+        port: Option<u16>,
+    ) -> Result<(), AppError> {
+        let method = method.to_string();
+        let port = bridge::format_port(port);
+        self.run(&["obfuscation", "set", &method, "--port", &port])
+            .map(|_| ())
+    }
+
+    fn access_methods(&self) -> Result<Vec<AccessMethod>, AppError> {
+        Ok(api_access::parse_list(&self.run(&["api-access", "list"])?))
+    }
+
+    fn access_method_in_use(&self) -> Result<Option<String>, AppError> {
+        Ok(api_access::parse_current(
+            &self.run(&["api-access", "get"])?,
+        ))
+    }
+
+    fn set_access_method_enabled(&self, index: usize, enabled: bool) -> Result<(), AppError> {
+        let command = if enabled { "enable" } else { "disable" };
+        self.run(&["api-access", command, &index.to_string()])
+            .map(|_| ())
+    }
+
+    fn test_access_method(&self, index: usize) -> Result<(), AppError> {
+        self.run(&["api-access", "test", &index.to_string()])
+            .map(|_| ())
+    }
+
+    fn add_socks5_access_method(&self, socks5: &Socks5) -> Result<(), AppError> {
+        let (host, port) = (socks5.host.to_string(), socks5.port.to_string());
+        self.run(&[
+            "api-access",
+            "add",
+            "socks5",
+            "remote",
+            &socks5.name,
+            &host,
+            &port,
+        ])
+        .map(|_| ())
+    }
+}
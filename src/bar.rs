@@ -0,0 +1,148 @@
+use std::{
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use ratatui::style::Color;
+use serde_json::json;
+
+use crate::{
+    backend::Backend,
+    config::ColorsConfig,
+    data::{TunnelState, TunnelStatus},
+    error::AppError,
+    worker::{self, WorkerEvent},
+};
+
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BarFormat {
+    /// One JSON object per line for a waybar `custom` module
+    Waybar,
+    /// Text with `%{F#rrggbb}` color tags
+    Polybar,
+    /// One JSON object per line, for a block with `format=json`
+    I3blocks,
+    /// Plain text
+    Plain,
+}
+
+fn icon(state: &TunnelState) -> &'static str {
+    match state {
+        TunnelState::Connected => "🔒",
+        TunnelState::Connecting | TunnelState::Disconnecting => "…",
+        TunnelState::Disconnected => "🔓",
+        TunnelState::Blocked | TunnelState::Error(_) => "⚠",
+    }
+}
+
+/// CSS class for waybar and the name used in the `alt` field.
+fn class(state: &TunnelState) -> &'static str {
+    match state {
+        TunnelState::Connected => "connected",
+        TunnelState::Connecting => "connecting",
+        TunnelState::Disconnecting => "disconnecting",
+        TunnelState::Disconnected => "disconnected",
+        TunnelState::Blocked => "blocked",
+        TunnelState::Error(_) => "error",
+    }
+}
+
+fn color(state: &TunnelState, colors: &ColorsConfig) -> Option<String> {
+    let color = match state {
+        TunnelState::Connected => colors.connected,
+        TunnelState::Connecting | TunnelState::Disconnecting => colors.items,
+        _ => colors.disconnected,
+    };
+    match color {
+        Color::Rgb(r, g, b) => Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+        _ => None,
+    }
+}
+
+/// `🔒 Gothenburg, Sweden (se-got-wg-001)` or `🔓 Disconnected`
+fn text(status: &TunnelStatus) -> String {
+    match (&status.state, &status.location, &status.relay) {
+        (TunnelState::Connected, Some(location), Some(relay)) => {
+            format!("{} {} ({})", icon(&status.state), location, relay)
+        }
+        (TunnelState::Connected, _, Some(relay)) => format!("{} {}", icon(&status.state), relay),
+        (state, _, _) => format!("{} {}", icon(state), state),
+    }
+}
+
+fn tooltip(status: &TunnelStatus) -> String {
+    status
+        .details()
+        .into_iter()
+        .map(|(label, value)| format!("{}: {}", label, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Formats the status as one line of output for the given bar.
+pub fn render(status: &TunnelStatus, format: BarFormat, colors: &ColorsConfig) -> String {
+    let text = text(status);
+    let color = color(&status.state, colors);
+
+    match format {
+        BarFormat::Waybar => json!({
+            "text": text,
+            "tooltip": tooltip(status),
+            "class": class(&status.state),
+            "alt": class(&status.state),
+        })
+        .to_string(),
+        BarFormat::Polybar => match color {
+            Some(color) => format!("%{{F{}}}{}%{{F-}}", color, text),
+            None => text,
+        },
+        BarFormat::I3blocks => {
+            let mut block = json!({
+                "full_text": text,
+                "short_text": icon(&status.state),
+            });
+            if let Some(color) = color {
+                block["color"] = json!(color);
+            }
+            block.to_string()
+        }
+        BarFormat::Plain => text,
+    }
+}
+
+/// Prints the status once, or on every change when `follow` is set.
+pub fn run(
+    backend: Arc<dyn Backend>,
+    format: BarFormat,
+    follow: bool,
+    colors: &ColorsConfig,
+) -> Result<(), AppError> {
+    if !follow {
+        println!("{}", render(&backend.status()?, format, colors));
+        return Ok(());
+    }
+
+    let (tx, rx) = mpsc::channel();
+    worker::spawn_status_worker(backend, FOLLOW_INTERVAL, tx);
+
+    let mut last = None;
+    for event in rx {
+        let status = match event {
+            WorkerEvent::Status(status) => *status,
+            WorkerEvent::StatusError(e) => TunnelStatus {
+                state: TunnelState::Error(e),
+                ..TunnelStatus::default()
+            },
+            _ => continue,
+        };
+        let line = render(&status, format, colors);
+        if last.as_ref() != Some(&line) {
+            println!("{}", line);
+            last = Some(line);
+        }
+    }
+    Ok(())
+}
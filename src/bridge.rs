@@ -0,0 +1,127 @@
+use std::fmt::Display;
+
+use crate::data::TunnelStatus;
+
+pub const BRIDGE_MODES: [&str; 3] = ["auto", "on", "off"];
+pub const OBFUSCATION_MODES: [&str; 4] = ["auto", "off", "udp2tcp", "shadowsocks"];
+
+/// Bridges carry OpenVPN through Shadowsocks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BridgeSettings {
+    pub mode: String,
+    /// The location constraint as printed by the CLI, e.g. `any` or `Gothenburg, Sweden`.
+    pub location: String,
+}
+
+/// Obfuscation wraps WireGuard in TCP or Shadowsocks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObfuscationSettings {
+    pub mode: String,
+    /// `None` lets the daemon pick.
+    pub udp2tcp_port: Option<u16>,
+    pub shadowsocks_port: Option<u16>,
+}
+
+/// The methods that take a port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObfuscationMethod {
+    Udp2tcp,
+    Shadowsocks,
+}
+
+impl Display for ObfuscationMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObfuscationMethod::Udp2tcp => write!(f, "udp2tcp"),
+            ObfuscationMethod::Shadowsocks => write!(f, "shadowsocks"),
+        }
+    }
+}
+
+/// `Key: value` pairs with lowercase keys.
+fn fields(output: &str) -> impl Iterator<Item = (String, &str)> {
+    output.lines().filter_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        Some((key.trim().to_lowercase(), value.trim()))
+    })
+}
+
+/// Parses the output of `mullvad bridge get`:
+///
+/// ```text
+/// Bridge mode: auto
+/// Bridge constraints: any
+/// ```
+pub fn parse_bridge(output: &str) -> BridgeSettings {
+    let mut settings = BridgeSettings::default();
+    for (key, value) in fields(output) {
+        if key.contains("mode") || key.contains("state") {
+            settings.mode = value.to_lowercase();
+        } else if key.contains("location") || key.contains("constraints") {
+            settings.location = value.to_string();
+        }
+    }
+    settings
+}
+
+/// Parses the output of `mullvad obfuscation get`:
+///
+/// ```text
+/// Obfuscation mode: udp2tcp
+/// udp2tcp settings: port 80
+/// Shadowsocks settings: any port
+/// ```
+pub fn parse_obfuscation(output: &str) -> ObfuscationSettings {
+    let port = |value: &str| value.split_whitespace().find_map(|word| word.parse().ok());
+    let mut settings = ObfuscationSettings::default();
+    for (key, value) in fields(output) {
+        if key.contains("mode") {
+            settings.mode = value.to_lowercase();
+        } else if key.contains("udp2tcp") {
+            settings.udp2tcp_port = port(value);
+        } else if key.contains("shadowsocks") {
+            settings.shadowsocks_port = port(value);
+        }
+    }
+    settings
+}
+
+/// The value after `current` in `values`, wrapping around, or before it when
+/// `back` is set.
+pub fn cycle<'a>(values: &[&'a str], current: &str, back: bool) -> &'a str {
+    let i = values.iter().position(|v| *v == current).unwrap_or(0);
+    let n = values.len();
+    values[if back { (i + n - 1) % n } else { (i + 1) % n }]
+}
+
+pub fn format_port(port: Option<u16>) -> String {
+    port.map_or("any".to_string(), |p| p.to_string())
+}
+
+/// What the tunnel uses given the protocol it runs: bridges only apply to
+/// OpenVPN and obfuscation only to WireGuard.
+pub fn active(
+    status: &TunnelStatus,
+    bridge: &BridgeSettings,
+    obfuscation: &ObfuscationSettings,
+) -> String {
+    let protocol = match &status.tunnel_protocol {
+        Some(protocol) if status.is_connected() => protocol,
+        _ => return "Not connected, the settings apply to the next connection".to_string(),
+    };
+    if protocol.eq_ignore_ascii_case("openvpn") {
+        return match bridge.mode.as_str() {
+            "on" => format!("OpenVPN through a bridge in {}", bridge.location),
+            "auto" => "OpenVPN, through a bridge when direct connections fail".to_string(),
+            _ => "OpenVPN without a bridge".to_string(),
+        };
+    }
+    if let Some(active) = &status.obfuscation {
+        return format!("{} obfuscated with {}", protocol, active);
+    }
+    match obfuscation.mode.as_str() {
+        "udp2tcp" | "shadowsocks" => format!("{} obfuscated with {}", protocol, obfuscation.mode),
+        "auto" => format!("{}, obfuscated when direct connections fail", protocol),
+        _ => format!("{} without obfuscation", protocol),
+    }
+}
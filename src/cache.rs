@@ -0,0 +1,64 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config, data::Country, error::AppError};
+
+/// The parsed relay list as written to `$XDG_CACHE_HOME/mullvad-tui/relays.json`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RelayCache {
+    /// Seconds since the Unix epoch when the list was fetched.
+    pub updated: u64,
+    pub countries: Vec<Country>,
+}
+
+impl RelayCache {
+    pub fn new(countries: Vec<Country>, updated: SystemTime) -> Self {
+        Self {
+            updated: updated
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            countries,
+        }
+    }
+
+    /// `$XDG_CACHE_HOME/mullvad-tui/relays.json`, falling back to `~/.cache`.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config::xdg_dir("XDG_CACHE_HOME", &[".cache"])?.join("relays.json"))
+    }
+
+    pub fn load(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), AppError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string(self).map_err(|e| AppError::Parse(e.to_string()))?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    pub fn updated_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.updated)
+    }
+}
+
+/// `just now`, `5 minutes ago`, `3 hours ago`, `2 days ago`
+pub fn format_age(age: Duration) -> String {
+    let minutes = age.as_secs() / 60;
+    let (n, unit) = match minutes {
+        0 => return "just now".to_string(),
+        1..=59 => (minutes, "minute"),
+        60..=1439 => (minutes / 60, "hour"),
+        _ => (minutes / 1440, "day"),
+    };
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
@@ -1,4 +1,8 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::{bar::BarFormat, control::Request, export::ExportFormat};
 
 #[derive(Debug, Parser)]
 pub struct Cli {
@@ -6,7 +10,91 @@ pub struct Cli {
     #[arg(short, long)]
     pub config: Option<String>,
 
-    /// Verbosity level
+    /// Log more to the log file: `-v` for info, `-vv` for every mullvad command
     #[arg(short, long, action = clap::ArgAction::Count)]
     pub verbose: u8,
+
+    /// The mullvad executable to run
+    #[arg(long, env = "MULLVAD_BIN", default_value = "mullvad")]
+    pub mullvad_bin: String,
+
+    /// Accept commands from `mullvad-tui ctl` while the TUI runs
+    #[arg(long)]
+    pub listen: bool,
+
+    /// Control socket path [default: $XDG_RUNTIME_DIR/mullvad-tui.sock]
+    #[arg(long, env = "MULLVAD_TUI_SOCKET")]
+    pub socket: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Non-interactive commands; without one the TUI starts.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Print the tunnel state
+    Status,
+    /// List countries, or the cities of a country
+    List {
+        /// Country code, e.g. `se`
+        country: Option<String>,
+    },
+    /// Set the relay location and connect
+    Connect {
+        /// Country code, e.g. `se`
+        country: String,
+        /// City code, e.g. `got`
+        city: Option<String>,
+        /// Relay hostname, e.g. `se-got-wg-001`
+        hostname: Option<String>,
+    },
+    /// Disconnect from the VPN
+    Disconnect,
+    /// Print the tunnel state for a status bar
+    Bar {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = BarFormat::Plain)]
+        format: BarFormat,
+        /// Keep running and print a line on every change
+        #[arg(long)]
+        follow: bool,
+    },
+    /// Send a command to a running instance started with `--listen`
+    Ctl {
+        #[command(subcommand)]
+        request: Request,
+    },
+    /// List or apply the profiles from the config
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+    /// Write the daemon settings, favorites, profiles, theme and history as one document
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Toml)]
+        format: ExportFormat,
+        /// File to write instead of stdout
+        output: Option<PathBuf>,
+    },
+    /// Apply a document written by `export`, printing what changes
+    Import {
+        /// The exported file, read as JSON if it ends in `.json`
+        path: PathBuf,
+        /// Only print what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommand {
+    /// List the profiles
+    List,
+    /// Apply every setting of a profile and connect to its location
+    Apply {
+        /// Profile name, e.g. `work`
+        name: String,
+    },
 }
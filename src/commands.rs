@@ -0,0 +1,196 @@
+use std::{fs, io::ErrorKind, path::Path, sync::Arc};
+
+use crate::{
+    backend::Backend,
+    bar,
+    cli::{Command, ProfileCommand},
+    config::Config,
+    control,
+    data::Location,
+    error::AppError,
+    export::{Export, ExportFormat},
+    history::History,
+    profile::{self, DaemonSettings, Settings},
+};
+
+/// Runs a non-interactive subcommand, printing its result to stdout.
+pub fn run(
+    command: Command,
+    backend: Arc<dyn Backend>,
+    config: Option<&str>,
+    socket: Option<&Path>,
+) -> Result<(), AppError> {
+    match command {
+        Command::Status => {
+            for (label, value) in backend.status()?.details() {
+                println!("{}: {}", label, value);
+            }
+        }
+        Command::List { country: None } => {
+            for country in backend.relay_list()? {
+                println!("{}", country);
+            }
+        }
+        Command::List {
+            country: Some(code),
+        } => {
+            let country = backend
+                .relay_list()?
+                .into_iter()
+                .find(|c| c.code == code)
+                .ok_or_else(|| AppError::Parse(format!("Unknown country: {}", code)))?;
+            for city in country.cities {
+                println!("{}", city);
+            }
+        }
+        Command::Connect {
+            country,
+            city,
+            hostname,
+        } => {
+            let location = match (city, hostname) {
+                (Some(city), Some(hostname)) => Location::Relay(country, city, hostname),
+                (Some(city), None) => Location::City(country, city),
+                _ => Location::Country(country),
+            };
+            backend.set_location(&location)?;
+            backend.connect()?;
+            println!("Connecting to {}", location);
+        }
+        Command::Disconnect => {
+            backend.disconnect()?;
+            println!("Disconnected");
+        }
+        Command::Bar { format, follow } => {
+            // Bars start before anyone writes a config, so fall back to no colors.
+            let config = Config::load(config).unwrap_or_default();
+            bar::run(backend, format, follow, &config.colors)?;
+        }
+        Command::Ctl { request } => {
+            let socket = socket.ok_or_else(socket_unset)?;
+            control::send(socket, &request)?;
+        }
+        Command::Profile { command } => {
+            let config = Config::load(config).map_err(|e| AppError::Config(e.to_string()))?;
+            match command {
+                ProfileCommand::List => {
+                    for name in config.profiles.keys() {
+                        println!("{}", name);
+                    }
+                }
+                ProfileCommand::Apply { name } => apply_profile(backend.as_ref(), &config, &name)?,
+            }
+        }
+        Command::Export { format, output } => {
+            let config = load_existing(config)?;
+            let history = History::default_path()
+                .map(|p| History::load(&p))
+                .unwrap_or_default();
+            let export = Export::new(backend.as_ref(), config.as_ref(), history.entries())?;
+            let text = export.to_string(format)?;
+            match output {
+                Some(path) => fs::write(path, text)?,
+                None => print!("{}", text),
+            }
+        }
+        Command::Import { path, dry_run } => import(backend.as_ref(), config, &path, dry_run)?,
+    }
+    Ok(())
+}
+
+/// The config, or `None` on a machine that has none yet.
+fn load_existing(path: Option<&str>) -> Result<Option<Config>, AppError> {
+    match Config::load(path) {
+        Ok(config) => Ok(Some(config)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(AppError::Config(e.to_string())),
+    }
+}
+
+/// Prints what the document changes, then applies the daemon settings and
+/// merges the rest into the config file and the history.
+fn import(
+    backend: &dyn Backend,
+    config: Option<&str>,
+    path: &Path,
+    dry_run: bool,
+) -> Result<(), AppError> {
+    let format = match path.extension() {
+        Some(extension) if extension == "json" => ExportFormat::Json,
+        _ => ExportFormat::Toml,
+    };
+    let import = Export::parse(&fs::read_to_string(path)?, format)?;
+    let daemon = DaemonSettings::fetch(backend)?;
+    let mut history = History::default_path()
+        .map(|p| History::load(&p))
+        .unwrap_or_default();
+
+    let current = load_existing(config)?;
+    let changes = import.changes(&daemon, current.as_ref(), history.entries());
+    if changes.is_empty() {
+        println!("Nothing to change");
+        return Ok(());
+    }
+    for change in &changes {
+        println!("{}", change);
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    import.daemon.without_unchanged(&daemon).apply(backend)?;
+    let config_path = Config::path(config);
+    let text = match fs::read_to_string(&config_path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let merged = import.merge_config(&text, current.as_ref())?;
+    if merged != text {
+        if let Some(dir) = config_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&config_path, merged)?;
+    }
+    for command in import.new_history(history.entries()) {
+        history.push(command)?;
+    }
+    println!("Imported {}", path.display());
+    Ok(())
+}
+
+/// Prints what the profile changes, then applies it.
+fn apply_profile(backend: &dyn Backend, config: &Config, name: &str) -> Result<(), AppError> {
+    let profile = config
+        .profiles
+        .get(name)
+        .ok_or_else(|| AppError::Config(format!("Unknown profile: {}", name)))?;
+    let countries = backend.relay_list()?;
+    let location = profile.location(&countries, &config.favorites)?;
+    let current = Settings::fetch(backend, &countries, None)?;
+    for change in profile.changes(&current, location.as_ref()) {
+        println!("{}", change);
+    }
+    profile.daemon.apply(backend)?;
+    match &location {
+        Some(location)
+            if current
+                .location
+                .as_ref()
+                .is_some_and(|c| profile::is_within(c, location)) => {}
+        Some(location) => {
+            backend.set_location(location)?;
+            backend.connect()?;
+        }
+        None => {}
+    }
+    if profile.filter.is_some() {
+        println!("Skipped the filter, it only applies in the TUI");
+    }
+    println!("Applied profile {}", name);
+    Ok(())
+}
+
+pub fn socket_unset() -> AppError {
+    AppError::Config("XDG_RUNTIME_DIR is not set, pass --socket".to_string())
+}
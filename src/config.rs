@@ -1,44 +1,133 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
 use ratatui::style::Color;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Default)]
+use crate::{
+    filter::RelayFilter, hooks::HooksConfig, leak::LeakCheckConfig, notify::NotificationsConfig,
+    profile::Profile,
+};
+
+/// Colors left out keep the terminal's own (`Reset`) and are left out again
+/// when written, so an export only carries the colors that were set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct ColorsConfig {
-    #[serde(deserialize_with = "crate::config::hex_to_rgb")]
+    #[serde(
+        default,
+        deserialize_with = "crate::config::hex_to_rgb",
+        serialize_with = "crate::config::rgb_to_hex",
+        skip_serializing_if = "crate::config::is_reset"
+    )]
     pub connected: Color,
-    #[serde(deserialize_with = "crate::config::hex_to_rgb")]
+    #[serde(
+        default,
+        deserialize_with = "crate::config::hex_to_rgb",
+        serialize_with = "crate::config::rgb_to_hex",
+        skip_serializing_if = "crate::config::is_reset"
+    )]
     pub disconnected: Color,
-    #[serde(deserialize_with = "crate::config::hex_to_rgb")]
+    #[serde(
+        default,
+        deserialize_with = "crate::config::hex_to_rgb",
+        serialize_with = "crate::config::rgb_to_hex",
+        skip_serializing_if = "crate::config::is_reset"
+    )]
     pub items: Color,
-    #[serde(deserialize_with = "crate::config::hex_to_rgb")]
+    #[serde(
+        default,
+        deserialize_with = "crate::config::hex_to_rgb",
+        serialize_with = "crate::config::rgb_to_hex",
+        skip_serializing_if = "crate::config::is_reset"
+    )]
     pub items_selected: Color,
-    #[serde(deserialize_with = "crate::config::hex_to_rgb")]
+    #[serde(
+        default,
+        deserialize_with = "crate::config::hex_to_rgb",
+        serialize_with = "crate::config::rgb_to_hex",
+        skip_serializing_if = "crate::config::is_reset"
+    )]
     pub normal_mode: Color,
-    #[serde(deserialize_with = "crate::config::hex_to_rgb")]
+    #[serde(
+        default,
+        deserialize_with = "crate::config::hex_to_rgb",
+        serialize_with = "crate::config::rgb_to_hex",
+        skip_serializing_if = "crate::config::is_reset"
+    )]
     pub search_mode: Color,
-    #[serde(deserialize_with = "crate::config::hex_to_rgb")]
+    #[serde(
+        default,
+        deserialize_with = "crate::config::hex_to_rgb",
+        serialize_with = "crate::config::rgb_to_hex",
+        skip_serializing_if = "crate::config::is_reset"
+    )]
     pub connection_output: Color,
-    #[serde(deserialize_with = "crate::config::hex_to_rgb")]
+    #[serde(
+        default,
+        deserialize_with = "crate::config::hex_to_rgb",
+        serialize_with = "crate::config::rgb_to_hex",
+        skip_serializing_if = "crate::config::is_reset"
+    )]
     pub background: Color,
 }
 
 #[derive(Debug, Deserialize, Default)]
 pub struct Config {
+    /// Show the locations as one tree instead of a list per level.
+    #[serde(default)]
+    pub tree_view: bool,
+    /// The terminal's own colors without a `[colors]` table.
+    #[serde(default)]
     pub colors: ColorsConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    /// Named locations, e.g. `work = "se got"`, used as `fav:work`.
+    #[serde(default)]
+    pub favorites: BTreeMap<String, String>,
+    /// The relays the random actions start out picking from.
+    #[serde(default)]
+    pub filter: RelayFilter,
+    #[serde(default)]
+    pub leak_check: LeakCheckConfig,
+    /// Named bundles of settings, applied with `P` or `mullvad-tui profile apply`.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
 }
 
 impl Config {
+    /// `path`, or `~/.config/mullvad-tui/config.toml` without one.
+    pub fn path(path: Option<&str>) -> PathBuf {
+        match path {
+            Some(p) => PathBuf::from(p),
+            None => {
+                let mut p = std::env::var("HOME").unwrap_or("./".to_string());
+                p.push_str("/.config/mullvad-tui/config.toml");
+                PathBuf::from(p)
+            }
+        }
+    }
+
     pub fn load(path: Option<&str>) -> Result<Self, std::io::Error> {
-        let config = if let Some(p) = path {
-            std::fs::read_to_string(p)?
-        } else {
-            let mut p = std::env::var("HOME").unwrap_or("./".to_string());
-            p.push_str("/.config/mullvad-tui/config.toml");
-            std::fs::read_to_string(p)?
-        };
+        let config = std::fs::read_to_string(Self::path(path))?;
         toml::from_str(&config).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
     }
 }
 
+/// The `mullvad-tui` directory in `$<var>`, or in `~/<fallback>` when the
+/// variable is unset or empty.
+pub fn xdg_dir(var: &str, fallback: &[&str]) -> Option<PathBuf> {
+    let dir = match std::env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => fallback
+            .iter()
+            .fold(PathBuf::from(std::env::var_os("HOME")?), |dir, part| {
+                dir.join(part)
+            }),
+    };
+    Some(dir.join("mullvad-tui"))
+}
+
 fn hex_to_rgb<'de, D>(deserializer: D) -> Result<Color, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -59,3 +148,17 @@ where
 
     Ok(Color::Rgb(r, g, b))
 }
+
+fn is_reset(color: &Color) -> bool {
+    *color == Color::Reset
+}
+
+fn rgb_to_hex<S>(color: &Color, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match color {
+        Color::Rgb(r, g, b) => serializer.serialize_str(&format!("#{:02x}{:02x}{:02x}", r, g, b)),
+        other => serializer.serialize_str(&other.to_string()),
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+use crate::{data::TunnelStatus, error::AppError, worker::WorkerEvent};
+
+/// A request from a control client, one JSON object per line:
+/// `{"cmd": "connect", "location": ["se", "got"]}`
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+pub enum Request {
+    /// Print the tunnel state
+    State,
+    /// Connect to a location, e.g. `se got`, `se-got-wg-001` or `fav:work`
+    Connect {
+        #[arg(required = true, num_args = 1..=3)]
+        location: Vec<String>,
+    },
+    /// Disconnect from the VPN
+    Disconnect,
+    /// Select a location in the lists, e.g. `se`
+    Navigate {
+        #[arg(required = true, num_args = 1..=3)]
+        location: Vec<String>,
+    },
+    /// Print an event for every change of the tunnel state
+    Subscribe,
+}
+
+/// The answer to every request; `status` is the state after it was handled.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<TunnelStatus>,
+}
+
+impl Response {
+    pub fn ok(status: &TunnelStatus) -> Self {
+        Self {
+            ok: true,
+            status: Some(status.clone()),
+            ..Self::default()
+        }
+    }
+
+    pub fn error(error: impl ToString) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            ..Self::default()
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Sent to subscribers after the response to `subscribe`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event {
+    Status(TunnelStatus),
+}
+
+impl Event {
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/mullvad-tui.sock`
+pub fn default_path() -> Option<PathBuf> {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("mullvad-tui.sock")),
+        _ => None,
+    }
+}
+
+/// A listening control socket; the socket file is removed when it is dropped.
+#[derive(Debug)]
+pub struct ControlSocket {
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Listens on `path`, forwarding requests to the UI loop through `tx`.
+    pub fn bind(path: &Path, tx: mpsc::Sender<WorkerEvent>) -> Result<Self, AppError> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(AppError::Command(format!(
+                    "{} exists and is not a socket",
+                    path.display()
+                )));
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err(AppError::Command(format!(
+                    "Another instance is listening on {}",
+                    path.display()
+                )));
+            }
+            // Left behind by an instance that did not shut down cleanly.
+            fs::remove_file(path)?;
+        }
+        let listener = bind_private(path)?;
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let tx = tx.clone();
+                thread::spawn(move || handle_client(stream, tx));
+            }
+        });
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+/// Binds the socket as `path` with mode 0600. The socket connects and
+/// disconnects the VPN, so it is created in a fresh 0700 directory and only
+/// moved to `path`, wherever `--socket` puts it, once nobody else can use it.
+fn bind_private(path: &Path) -> Result<UnixListener, AppError> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = path.with_file_name(format!(".{}.{}", name, std::process::id()));
+    fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let private = dir.join("socket");
+    let result = UnixListener::bind(&private).and_then(|listener| {
+        fs::set_permissions(&private, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&private, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_dir_all(&dir);
+    Ok(result?)
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Answers the requests of one connection. After `subscribe`, events are
+/// written from another thread so the connection can keep sending requests.
+fn handle_client(stream: UnixStream, tx: mpsc::Sender<WorkerEvent>) -> Result<(), AppError> {
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                write_line(&writer, &Response::error(e).to_line())?;
+                continue;
+            }
+        };
+
+        let subscribe = request == Request::Subscribe;
+        let (reply, replies) = mpsc::channel();
+        if tx.send(WorkerEvent::Control { request, reply }).is_err() {
+            break;
+        }
+        if subscribe {
+            // The response comes first, then the events for as long as the client reads them.
+            if let Ok(line) = replies.recv() {
+                write_line(&writer, &line)?;
+            }
+            let writer = writer.clone();
+            thread::spawn(move || {
+                for line in replies {
+                    if write_line(&writer, &line).is_err() {
+                        break;
+                    }
+                }
+            });
+            continue;
+        }
+        // The UI loop drops `reply` after answering.
+        for line in replies {
+            write_line(&writer, &line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `line` in one piece, so events and responses never interleave.
+fn write_line(writer: &Mutex<UnixStream>, line: &str) -> io::Result<()> {
+    let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+    writer.write_all(format!("{}\n", line).as_bytes())
+}
+
+/// Sends one request to a running instance and prints what comes back.
+pub fn send(path: &Path, request: &Request) -> Result<(), AppError> {
+    let mut stream = UnixStream::connect(path).map_err(|e| {
+        AppError::Command(format!("Failed to connect to {}: {}", path.display(), e))
+    })?;
+    let json = serde_json::to_string(request).map_err(|e| AppError::Parse(e.to_string()))?;
+    writeln!(stream, "{}", json)?;
+
+    let mut lines = BufReader::new(stream).lines();
+    let line = lines
+        .next()
+        .ok_or_else(|| AppError::Command("Connection closed without a response".to_string()))??;
+    let response: Response =
+        serde_json::from_str(&line).map_err(|e| AppError::Parse(e.to_string()))?;
+    if let Some(error) = response.error {
+        return Err(AppError::Command(error));
+    }
+    println!("{}", line);
+
+    if *request == Request::Subscribe {
+        for line in lines {
+            println!("{}", line?);
+        }
+    }
+    Ok(())
+}
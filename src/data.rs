@@ -1,30 +1,583 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Country {
     pub name: String,
-    pub cities: Vec<String>,
+    pub code: String,
+    pub cities: Vec<City>,
 }
 
 impl std::fmt::Display for Country {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.name)
+        write!(f, "{} ({})", self.name, self.code)
     }
 }
 
 impl Country {
     #[allow(dead_code)]
-    pub fn new(name: &str) -> Self {
+    pub fn new(name: &str, code: &str) -> Self {
         Self {
             name: name.to_string(),
+            code: code.to_string(),
             cities: Vec::new(),
         }
     }
 
     #[allow(dead_code)]
-    pub fn add_cities(&mut self, cities: &[&str]) {
+    pub fn add_cities(&mut self, cities: &[City]) {
         for c in cities {
-            self.cities.push(c.to_string());
+            self.cities.push(c.clone());
+        }
+    }
+
+    pub fn relays(&self) -> impl Iterator<Item = &Relay> {
+        self.cities.iter().flat_map(|c| c.relays.iter())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct City {
+    pub name: String,
+    pub code: String,
+    /// Where the city is, when the relay list says.
+    #[serde(default)]
+    pub coordinates: Option<Coordinates>,
+    pub relays: Vec<Relay>,
+}
+
+/// Degrees north and east; south and west are negative.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    /// Parses `57.70887°N, 11.97456°E`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (latitude, longitude) = s.split_once(',')?;
+        let degrees = |s: &str, positive: char, negative: char| {
+            let s = s.trim();
+            let (value, hemisphere) = s.split_once('°')?;
+            let value: f64 = value.trim().parse().ok()?;
+            match hemisphere.trim().chars().next()? {
+                c if c == positive => Some(value),
+                c if c == negative => Some(-value),
+                _ => None,
+            }
+        };
+        Some(Self {
+            latitude: degrees(latitude, 'N', 'S')?,
+            longitude: degrees(longitude, 'E', 'W')?,
+        })
+    }
+}
+
+impl std::fmt::Display for City {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.code)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Relay {
+    pub hostname: String,
+    pub ipv4: String,
+    pub ipv6: Option<String>,
+    pub protocol: String,
+    pub provider: String,
+    pub owned: bool,
+}
+
+impl std::fmt::Display for Relay {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} ({})", self.hostname, self.protocol)
+    }
+}
+
+/// Aggregate facts about a group of relays, shown in the details pane.
+#[derive(Debug, Default)]
+pub struct RelaySummary {
+    pub relays: usize,
+    pub owned: usize,
+    pub providers: BTreeSet<String>,
+    pub protocols: BTreeMap<String, usize>,
+}
+
+impl RelaySummary {
+    pub fn new<'a>(relays: impl IntoIterator<Item = &'a Relay>) -> Self {
+        let mut summary = Self::default();
+        for relay in relays {
+            summary.relays += 1;
+            if relay.owned {
+                summary.owned += 1;
+            }
+            summary.providers.insert(relay.provider.clone());
+            *summary.protocols.entry(relay.protocol.clone()).or_default() += 1;
+        }
+        summary
+    }
+}
+
+/// A relay constraint as understood by `mullvad relay set location` and
+/// `mullvad custom-list edit`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Location {
+    Country(String),
+    City(String, String),
+    Relay(String, String, String),
+}
+
+impl Location {
+    pub fn args(&self) -> Vec<&str> {
+        match self {
+            Location::Country(country) => vec![country],
+            Location::City(country, city) => vec![country, city],
+            Location::Relay(country, city, hostname) => vec![country, city, hostname],
+        }
+    }
+
+    pub fn country_code(&self) -> &str {
+        self.args()[0]
+    }
+
+    pub fn city_code(&self) -> Option<&str> {
+        self.args().get(1).copied()
+    }
+
+    pub fn hostname(&self) -> Option<&str> {
+        self.args().get(2).copied()
+    }
+
+    /// The inverse of [`Location::args`]: `["se", "got"]` is a city.
+    pub fn from_args<S: AsRef<str>>(args: &[S]) -> Option<Self> {
+        let arg = |i: usize| args[i].as_ref().to_string();
+        match args.len() {
+            1 => Some(Location::Country(arg(0))),
+            2 => Some(Location::City(arg(0), arg(1))),
+            3 => Some(Location::Relay(arg(0), arg(1), arg(2))),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.args().join(" "))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomList {
+    pub name: String,
+    pub locations: Vec<CustomListEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomListEntry {
+    pub label: String,
+    pub location: Option<Location>,
+}
+
+/// Splits `Name (code)` into its two parts.
+fn split_code(s: &str) -> Option<(&str, &str)> {
+    let open = s.find('(')?;
+    let close = open + s[open..].find(')')?;
+    Some((s[..open].trim(), s[open + 1..close].trim()))
+}
+
+/// Parses the output of `mullvad relay list`.
+///
+/// Countries are unindented, cities are indented with one tab (`\t`) and
+/// relays with two:
+///
+/// ```text
+/// Sweden (se)
+/// \tGothenburg (got) @ 57.70887°N, 11.97456°E
+/// \t\tse-got-wg-001 (185.213.154.66, 2a03:1b20:5:f011::a01f) - WireGuard, hosted by 31173 (Mullvad-owned)
+/// ```
+pub fn parse_relay_list(output: &str) -> Vec<Country> {
+    let mut countries: Vec<Country> = Vec::new();
+
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let depth = line.chars().take_while(|c| *c == '\t').count();
+        let line = line.trim();
+
+        match depth {
+            0 => {
+                if let Some((name, code)) = split_code(line) {
+                    countries.push(Country::new(name, code));
+                }
+            }
+            1 => {
+                let Some(country) = countries.last_mut() else {
+                    continue;
+                };
+                let (head, coordinates) = match line.split_once(" @ ") {
+                    Some((head, coordinates)) => (head, Coordinates::parse(coordinates)),
+                    None => (line, None),
+                };
+                if let Some((name, code)) = split_code(head) {
+                    country.cities.push(City {
+                        name: name.to_string(),
+                        code: code.to_string(),
+                        coordinates,
+                        relays: Vec::new(),
+                    });
+                }
+            }
+            _ => {
+                let Some(city) = countries.last_mut().and_then(|c| c.cities.last_mut()) else {
+                    continue;
+                };
+                if let Some(relay) = parse_relay(line) {
+                    city.relays.push(relay);
+                }
+            }
+        }
+    }
+
+    countries
+}
+
+fn parse_relay(line: &str) -> Option<Relay> {
+    let (head, tail) = line.split_once(" - ").unwrap_or((line, ""));
+    let (hostname, addresses) = split_code(head)?;
+    let mut addresses = addresses.split(',').map(str::trim);
+    let ipv4 = addresses.next().unwrap_or_default().to_string();
+    let ipv6 = addresses.next().map(str::to_string);
+
+    let (protocol, hosting) = tail.split_once(", ").unwrap_or((tail, ""));
+    let hosting = hosting.trim_start_matches("hosted by ");
+    let (provider, ownership) = match hosting.rfind('(') {
+        Some(i) => (hosting[..i].trim(), &hosting[i..]),
+        None => (hosting.trim(), ""),
+    };
+
+    Some(Relay {
+        hostname: hostname.to_string(),
+        ipv4,
+        ipv6,
+        protocol: protocol.trim().to_string(),
+        provider: provider.to_string(),
+        owned: ownership.contains("Mullvad-owned"),
+    })
+}
+
+/// Parses the output of `mullvad custom-list list`.
+///
+/// List names are unindented and their locations are indented below them.
+/// Entries are resolved against `countries` where the output only gives
+/// display names.
+pub fn parse_custom_lists(output: &str, countries: &[Country]) -> Vec<CustomList> {
+    let mut lists: Vec<CustomList> = Vec::new();
+
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            lists.push(CustomList {
+                name: line.trim().to_string(),
+                locations: Vec::new(),
+            });
+            continue;
+        }
+        let Some(list) = lists.last_mut() else {
+            continue;
+        };
+        let label = line.trim().to_string();
+        let location = resolve_location(&label, countries);
+        list.locations.push(CustomListEntry { label, location });
+    }
+
+    lists
+}
+
+fn resolve_location(label: &str, countries: &[Country]) -> Option<Location> {
+    let first = label.split_whitespace().next()?;
+    let mut parts = first.splitn(3, '-');
+    if let (Some(country), Some(city), Some(_)) = (parts.next(), parts.next(), parts.next()) {
+        let is_code =
+            |s: &str, len: usize| s.len() == len && s.chars().all(|c| c.is_ascii_lowercase());
+        if is_code(country, 2) && is_code(city, 3) {
+            return Some(Location::Relay(
+                country.to_string(),
+                city.to_string(),
+                first.to_string(),
+            ));
+        }
+    }
+
+    let (name, code) = split_code(label)?;
+    if let Some(country) = countries.iter().find(|c| c.name == name && c.code == code) {
+        return Some(Location::Country(country.code.clone()));
+    }
+
+    // Cities are printed as `Gothenburg (got), Sweden`.
+    let country_name = label.rsplit_once("),").map(|(_, c)| c.trim());
+    countries
+        .iter()
+        .filter(|c| country_name.is_none_or(|n| c.name == n || n.contains(&c.code)))
+        .find(|c| c.cities.iter().any(|city| city.code == code))
+        .map(|c| Location::City(c.code.clone(), code.to_string()))
+        .or_else(|| {
+            countries
+                .iter()
+                .any(|c| c.code == code)
+                .then(|| Location::Country(code.to_string()))
+        })
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TunnelState {
+    Connected,
+    Connecting,
+    #[default]
+    Disconnected,
+    Disconnecting,
+    /// Disconnected, but lockdown mode keeps blocking traffic.
+    Blocked,
+    Error(String),
+}
+
+impl std::fmt::Display for TunnelState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TunnelState::Connected => write!(f, "Connected"),
+            TunnelState::Connecting => write!(f, "Connecting"),
+            TunnelState::Disconnected => write!(f, "Disconnected"),
+            TunnelState::Disconnecting => write!(f, "Disconnecting"),
+            TunnelState::Blocked => write!(f, "Blocked"),
+            TunnelState::Error(e) => write!(f, "Error: {}", e),
         }
     }
 }
+
+/// The tunnel as reported by `mullvad status -v`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TunnelStatus {
+    pub state: TunnelState,
+    pub relay: Option<String>,
+    /// Entry relay when multihop is enabled.
+    pub entry_relay: Option<String>,
+    pub location: Option<String>,
+    /// Address of the relay the tunnel connects to.
+    pub entry_ip: Option<String>,
+    pub tunnel_protocol: Option<String>,
+    pub obfuscation: Option<String>,
+    pub features: Option<String>,
+    /// The addresses the outside world sees.
+    pub visible_ipv4: Option<String>,
+    pub visible_ipv6: Option<String>,
+}
+
+impl TunnelStatus {
+    pub fn is_connected(&self) -> bool {
+        self.state == TunnelState::Connected
+    }
+
+    /// The address the outside world sees, IPv4 if there is one.
+    pub fn exit_ip(&self) -> Option<&str> {
+        self.visible_ipv4
+            .as_deref()
+            .or(self.visible_ipv6.as_deref())
+    }
+
+    /// Labelled fields that are known, in display order.
+    pub fn details(&self) -> Vec<(&'static str, String)> {
+        [
+            ("State", Some(self.state.to_string())),
+            ("Relay", self.relay.clone()),
+            ("Entry relay", self.entry_relay.clone()),
+            ("Location", self.location.clone()),
+            ("Entry IP", self.entry_ip.clone()),
+            ("Exit IPv4", self.visible_ipv4.clone()),
+            ("Exit IPv6", self.visible_ipv6.clone()),
+            ("Protocol", self.tunnel_protocol.clone()),
+            ("Obfuscation", self.obfuscation.clone()),
+            ("Features", self.features.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(label, value)| Some((label, value?)))
+        .collect()
+    }
+}
+
+/// How long to wait for a new exit IP after asking for one.
+const EXIT_IP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Follows the status after a reconnect until the exit IP is known to have
+/// changed, or not.
+#[derive(Debug, Clone)]
+pub struct ExitIpCheck {
+    old: String,
+    started: Instant,
+    /// Whether the tunnel left the connected state since, so the old
+    /// connection has really been torn down.
+    reconnected: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExitIpChange {
+    Changed { old: String, new: String },
+    Unchanged(String),
+}
+
+impl ExitIpCheck {
+    /// `None` when there is no exit IP to compare against.
+    pub fn start(status: &TunnelStatus, now: Instant) -> Option<Self> {
+        Some(Self {
+            old: status.exit_ip()?.to_string(),
+            started: now,
+            reconnected: false,
+        })
+    }
+
+    /// The outcome once the status tells, `None` while still waiting.
+    pub fn update(&mut self, status: &TunnelStatus, now: Instant) -> Option<ExitIpChange> {
+        if !status.is_connected() {
+            self.reconnected = true;
+            return None;
+        }
+        match status.exit_ip() {
+            Some(ip) if ip != self.old => Some(ExitIpChange::Changed {
+                old: self.old.clone(),
+                new: ip.to_string(),
+            }),
+            Some(_) if self.reconnected || now.duration_since(self.started) >= EXIT_IP_TIMEOUT => {
+                Some(ExitIpChange::Unchanged(self.old.clone()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parses the output of `mullvad status -v`.
+///
+/// The first line holds the state; the remaining lines are `Key: value` pairs:
+///
+/// ```text
+/// Connected to se-got-wg-001 in Gothenburg, Sweden
+/// Relay:                  se-got-wg-001 (185.213.154.66:51820/UDP)
+/// Tunnel type:            WireGuard
+/// Visible location:       Sweden, Gothenburg. IPv4: 185.213.154.68, IPv6: 2a03:1b20:5:f011::a01f
+/// ```
+pub fn parse_status(output: &str) -> TunnelStatus {
+    let mut status = TunnelStatus::default();
+    let mut lines = output.lines().map(str::trim).filter(|l| !l.is_empty());
+
+    let Some(first) = lines.next() else {
+        return status;
+    };
+    let lower = first.to_lowercase();
+    status.state = if lower.starts_with("connected") {
+        TunnelState::Connected
+    } else if lower.starts_with("connecting") {
+        TunnelState::Connecting
+    } else if lower.starts_with("disconnecting") {
+        TunnelState::Disconnecting
+    } else if lower.starts_with("disconnected") {
+        if lower.contains("block") || lower.contains("lockdown") {
+            TunnelState::Blocked
+        } else {
+            TunnelState::Disconnected
+        }
+    } else {
+        let reason = first.split_once(':').map_or(first, |(_, r)| r).trim();
+        TunnelState::Error(reason.to_string())
+    };
+
+    // "Connected to se-got-wg-001 in Gothenburg, Sweden"
+    if let Some((_, rest)) = first.split_once(" to ") {
+        let (relay, location) = rest.split_once(" in ").unwrap_or((rest, ""));
+        if !relay.contains(' ') {
+            status.relay = Some(relay.to_string());
+        }
+        if !location.is_empty() {
+            status.location = Some(location.trim().to_string());
+        }
+    }
+
+    for line in lines {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        match key.trim().to_lowercase().as_str() {
+            "relay" => {
+                let (relays, endpoint) = match value.split_once(" (") {
+                    Some((relays, endpoint)) => (relays, Some(endpoint.trim_end_matches(')'))),
+                    None => (value, None),
+                };
+                match relays.split_once(" via ") {
+                    Some((exit, entry)) => {
+                        status.relay = Some(exit.trim().to_string());
+                        status.entry_relay = Some(entry.trim().to_string());
+                    }
+                    None => status.relay = Some(relays.trim().to_string()),
+                }
+                if let Some(endpoint) = endpoint {
+                    status.entry_ip = Some(strip_port(endpoint).to_string());
+                }
+            }
+            "tunnel type" | "tunnel protocol" | "protocol" => {
+                status.tunnel_protocol = Some(value.to_string())
+            }
+            "obfuscation" => status.obfuscation = Some(value.to_string()),
+            "features" => status.features = Some(value.to_string()),
+            "location" => {
+                status.location.get_or_insert_with(|| value.to_string());
+            }
+            "visible location" | "your connection appears to be from" => {
+                let (location, addresses) = value.split_once(". ").unwrap_or((value, ""));
+                status.location.get_or_insert_with(|| location.to_string());
+                for address in addresses.split(", ") {
+                    if let Some(ip) = address.strip_prefix("IPv4: ") {
+                        status.visible_ipv4 = Some(ip.trim().to_string());
+                    } else if let Some(ip) = address.strip_prefix("IPv6: ") {
+                        status.visible_ipv6 = Some(ip.trim().to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    status
+}
+
+/// `185.213.154.66:51820/UDP` -> `185.213.154.66`, `[2a03::1]:51820` -> `2a03::1`
+fn strip_port(endpoint: &str) -> &str {
+    let endpoint = endpoint.split('/').next().unwrap_or(endpoint);
+    if let Some(rest) = endpoint.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    match endpoint.rsplit_once(':') {
+        Some((ip, _)) if !ip.contains(':') => ip,
+        _ => endpoint,
+    }
+}
+
+/// Extracts the round trip time from the output of `ping -c 1`.
+pub fn parse_ping(output: &str) -> Option<std::time::Duration> {
+    let time = output.split("time=").nth(1)?;
+    let millis: f64 = time
+        .split_whitespace()
+        .next()?
+        .trim_end_matches("ms")
+        .parse()
+        .ok()?;
+    Some(std::time::Duration::from_secs_f64(millis / 1000.0))
+}
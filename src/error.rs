@@ -9,6 +9,7 @@ pub enum AppError {
     ParseColor(ratatui::style::ParseColorError),
     Config(String),
     Parse(String),
+    Http(String),
 }
 
 impl From<std::io::Error> for AppError {
@@ -41,4 +42,23 @@ impl From<std::process::ExitStatus> for AppError {
     }
 }
 
+impl From<ureq::Error> for AppError {
+    fn from(e: ureq::Error) -> Self {
+        Self::Http(e.to_string())
+    }
+}
 
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Command(e) => write!(f, "{}", e),
+            Self::Utf8(e) => write!(f, "{}", e),
+            Self::Toml(e) => write!(f, "{}", e),
+            Self::ParseColor(e) => write!(f, "{}", e),
+            Self::Config(e) => write!(f, "{}", e),
+            Self::Parse(e) => write!(f, "{}", e),
+            Self::Http(e) => write!(f, "{}", e),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use toml_edit::{DocumentMut, InlineTable, Item, Table};
+
+use crate::{
+    backend::Backend,
+    config::{ColorsConfig, Config},
+    error::AppError,
+    profile::{Change, DaemonSettings, Profile},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Toml,
+    Json,
+}
+
+/// The daemon settings and the app's state, to set up another machine.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Export {
+    pub daemon: DaemonSettings,
+    pub favorites: BTreeMap<String, String>,
+    pub profiles: BTreeMap<String, Profile>,
+    /// The `[colors]` of the config, when it sets any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub theme: Option<ColorsConfig>,
+    /// `:` commands, oldest first.
+    pub history: Vec<String>,
+}
+
+impl Export {
+    /// Reads the daemon settings; the rest comes from `config`, if there is one.
+    pub fn new(
+        backend: &dyn Backend,
+        config: Option<&Config>,
+        history: &[String],
+    ) -> Result<Self, AppError> {
+        Ok(Self {
+            daemon: DaemonSettings::fetch(backend)?,
+            favorites: config.map(|c| c.favorites.clone()).unwrap_or_default(),
+            profiles: config.map(|c| c.profiles.clone()).unwrap_or_default(),
+            theme: config
+                .map(|c| c.colors.clone())
+                .filter(|colors| *colors != ColorsConfig::default()),
+            history: history.to_vec(),
+        })
+    }
+
+    pub fn to_string(&self, format: ExportFormat) -> Result<String, AppError> {
+        match format {
+            ExportFormat::Toml => toml::to_string(self).map_err(|e| AppError::Parse(e.to_string())),
+            ExportFormat::Json => serde_json::to_string_pretty(self)
+                .map(|json| json + "\n")
+                .map_err(|e| AppError::Parse(e.to_string())),
+        }
+    }
+
+    pub fn parse(text: &str, format: ExportFormat) -> Result<Self, AppError> {
+        match format {
+            ExportFormat::Toml => Ok(toml::from_str(text)?),
+            ExportFormat::Json => {
+                serde_json::from_str(text).map_err(|e| AppError::Parse(e.to_string()))
+            }
+        }
+    }
+
+    /// What importing would change. Favorites, profiles and commands that are
+    /// only on this machine are kept.
+    pub fn changes(
+        &self,
+        daemon: &DaemonSettings,
+        config: Option<&Config>,
+        history: &[String],
+    ) -> Vec<Change> {
+        let mut changes = self.daemon.changes(daemon);
+        let unset = || "unset".to_string();
+        for (name, location) in &self.favorites {
+            let from = config.and_then(|c| c.favorites.get(name));
+            if from != Some(location) {
+                let from = from.cloned().unwrap_or_else(unset);
+                changes.push(Change::new(
+                    format!("Favorite {}", name),
+                    from,
+                    location.clone(),
+                ));
+            }
+        }
+        for (name, profile) in &self.profiles {
+            let from = config.and_then(|c| c.profiles.get(name));
+            if from != Some(profile) {
+                let from = from.map_or_else(unset, summary);
+                changes.push(Change::new(
+                    format!("Profile {}", name),
+                    from,
+                    summary(profile),
+                ));
+            }
+        }
+        if let Some(theme) = &self.theme {
+            let current = config.map(|c| colors(&c.colors)).unwrap_or_default();
+            for (key, color) in colors(theme) {
+                let from = current.get(&key);
+                if from != Some(&color) {
+                    let from = from
+                        .and_then(toml::Value::as_str)
+                        .map_or_else(unset, String::from);
+                    let to = color.as_str().unwrap_or_default().to_string();
+                    changes.push(Change::new(format!("Color {}", key), from, to));
+                }
+            }
+        }
+        let added = self.new_history(history).len();
+        if added > 0 {
+            let count = |n: usize| format!("{} commands", n);
+            changes.push(Change::new(
+                "History",
+                count(history.len()),
+                count(history.len() + added),
+            ));
+        }
+        changes
+    }
+
+    /// Commands that are not in `history` yet.
+    pub fn new_history<'a>(&'a self, history: &[String]) -> Vec<&'a str> {
+        self.history
+            .iter()
+            .filter(|command| !history.contains(command))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Writes the favorites, profiles and colors that differ from `current`
+    /// into the text of its config file, keeping everything else in it,
+    /// comments included.
+    pub fn merge_config(&self, text: &str, current: Option<&Config>) -> Result<String, AppError> {
+        let mut doc: DocumentMut = text
+            .parse()
+            .map_err(|e| AppError::Config(format!("{}", e)))?;
+        let favorites = table(&mut doc, "favorites")?;
+        for (name, location) in &self.favorites {
+            if current.and_then(|c| c.favorites.get(name)) != Some(location) {
+                favorites[name.as_str()] = toml_edit::value(location.as_str());
+            }
+        }
+        let profiles = table(&mut doc, "profiles")?;
+        for (name, profile) in &self.profiles {
+            if current.and_then(|c| c.profiles.get(name)) == Some(profile) {
+                continue;
+            }
+            // Nested settings such as `dns` stay inline tables, as in the example config.
+            let mut table = Table::new();
+            for (key, value) in inline(profile)?.iter() {
+                let mut value = value.clone();
+                value.decor_mut().clear();
+                table.insert(key, Item::Value(value));
+            }
+            profiles.insert(name, Item::Table(table));
+        }
+        if let Some(theme) = &self.theme {
+            let current = current.map(|c| colors(&c.colors)).unwrap_or_default();
+            let table = table(&mut doc, "colors")?;
+            for (key, color) in colors(theme) {
+                if current.get(&key) != Some(&color) {
+                    table[key.as_str()] = toml_edit::value(color.as_str().unwrap_or_default());
+                }
+            }
+        }
+        Ok(doc.to_string())
+    }
+}
+
+/// `value` as an inline TOML table, keys in the order of its fields.
+fn inline(value: &impl Serialize) -> Result<InlineTable, AppError> {
+    let text = toml::to_string(value).map_err(|e| AppError::Parse(e.to_string()))?;
+    let doc: DocumentMut = text
+        .parse()
+        .map_err(|e| AppError::Parse(format!("{}", e)))?;
+    Ok(doc.as_table().clone().into_inline_table())
+}
+
+/// The colors as `#rrggbb` strings by name.
+fn colors(colors: &ColorsConfig) -> toml::Table {
+    toml::Table::try_from(colors).unwrap_or_default()
+}
+
+/// A profile on one line, e.g. `{ location = "se got", lockdown = true }`.
+fn summary(profile: &Profile) -> String {
+    inline(profile)
+        .map(|table| table.to_string())
+        .unwrap_or_default()
+}
+
+/// The table `key` of the document, added when missing.
+fn table<'a>(doc: &'a mut DocumentMut, key: &str) -> Result<&'a mut Table, AppError> {
+    let item = doc.entry(key).or_insert_with(|| {
+        let mut table = Table::new();
+        // No empty `[profiles]` header above the `[profiles.<name>]` ones.
+        table.set_implicit(true);
+        Item::Table(table)
+    });
+    item.as_table_mut()
+        .ok_or_else(|| AppError::Config(format!("`{}` in the config is not a table", key)))
+}
@@ -0,0 +1,77 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{data::Relay, error::AppError};
+
+/// Restricts the relays the random actions pick from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayFilter {
+    /// `WireGuard` or `OpenVPN`, any when unset.
+    pub protocol: Option<String>,
+    /// Only relays owned by Mullvad (`true`) or only rented ones (`false`).
+    pub owned: Option<bool>,
+    /// Hosting providers to pick from, all when empty.
+    pub providers: Vec<String>,
+}
+
+impl RelayFilter {
+    pub fn matches(&self, relay: &Relay) -> bool {
+        self.protocol
+            .as_ref()
+            .is_none_or(|p| p.eq_ignore_ascii_case(&relay.protocol))
+            && self.owned.is_none_or(|owned| owned == relay.owned)
+            && (self.providers.is_empty() || self.providers.contains(&relay.provider))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Parses the arguments of `:filter`, e.g. `wireguard owned provider 31173`.
+    /// No arguments clear the filter.
+    pub fn parse(args: &[&str]) -> Result<Self, AppError> {
+        let mut filter = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "wireguard" => filter.protocol = Some("WireGuard".to_string()),
+                "openvpn" => filter.protocol = Some("OpenVPN".to_string()),
+                "owned" => filter.owned = Some(true),
+                "rented" => filter.owned = Some(false),
+                "provider" => match args.next() {
+                    Some(provider) => filter.providers.push(provider.to_string()),
+                    None => {
+                        return Err(AppError::Parse("Usage: filter provider <name>".to_string()))
+                    }
+                },
+                _ => return Err(AppError::Parse(format!("Unknown filter: {}", arg))),
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl Display for RelayFilter {
+    /// `WireGuard, owned, 31173 or M247`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(protocol) = &self.protocol {
+            parts.push(protocol.clone());
+        }
+        match self.owned {
+            Some(true) => parts.push("owned".to_string()),
+            Some(false) => parts.push("rented".to_string()),
+            None => {}
+        }
+        if !self.providers.is_empty() {
+            parts.push(self.providers.join(" or "));
+        }
+        if parts.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{config, error::AppError};
+
+/// Commands kept in memory and in the history file.
+const MAX_ENTRIES: usize = 500;
+
+/// Previously run `:` commands, oldest first, with a cursor for Up/Down.
+#[derive(Debug, Default)]
+pub struct History {
+    path: Option<PathBuf>,
+    entries: Vec<String>,
+    /// Index into `entries` while browsing, `None` at the prompt.
+    cursor: Option<usize>,
+}
+
+impl History {
+    /// `$XDG_STATE_HOME/mullvad-tui/history`, falling back to `~/.local/state`.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(config::xdg_dir("XDG_STATE_HOME", &[".local", "state"])?.join("history"))
+    }
+
+    /// Reads the history file; a missing file is an empty history.
+    pub fn load(path: &Path) -> Self {
+        let entries: Vec<String> = fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(String::from)
+            .collect();
+        let skip = entries.len().saturating_sub(MAX_ENTRIES);
+        Self {
+            path: Some(path.to_path_buf()),
+            entries: entries.into_iter().skip(skip).collect(),
+            cursor: None,
+        }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Records a command, skipping repeats of the last one.
+    pub fn push(&mut self, command: &str) -> Result<(), AppError> {
+        self.cursor = None;
+        if self.entries.last().map(String::as_str) == Some(command) {
+            return Ok(());
+        }
+        self.entries.push(command.to_string());
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+            fs::write(path, self.entries.join("\n") + "\n")?;
+        } else {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", command)?;
+        }
+        Ok(())
+    }
+
+    /// The command before the one shown, stopping at the oldest.
+    pub fn previous(&mut self) -> Option<&str> {
+        let cursor = match self.cursor {
+            Some(i) => i.saturating_sub(1),
+            None => self.entries.len().checked_sub(1)?,
+        };
+        self.cursor = Some(cursor);
+        self.entries.get(cursor).map(String::as_str)
+    }
+
+    /// The command after the one shown, or `None` when back at the prompt.
+    pub fn next(&mut self) -> Option<&str> {
+        let cursor = self.cursor? + 1;
+        if cursor >= self.entries.len() {
+            self.cursor = None;
+            return None;
+        }
+        self.cursor = Some(cursor);
+        self.entries.get(cursor).map(String::as_str)
+    }
+
+    pub fn reset(&mut self) {
+        self.cursor = None;
+    }
+}
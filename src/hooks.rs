@@ -0,0 +1,243 @@
+use std::{
+    fmt::Display,
+    io::Read,
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    data::{TunnelState, TunnelStatus},
+    worker::WorkerEvent,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Commands run through `sh -c` when the tunnel state changes.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub on_connect: Option<String>,
+    pub on_disconnect: Option<String>,
+    pub on_error: Option<String>,
+    pub on_relay_change: Option<String>,
+    /// Seconds before a hook is killed.
+    pub timeout: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_connect: None,
+            on_disconnect: None,
+            on_error: None,
+            on_relay_change: None,
+            timeout: 10,
+        }
+    }
+}
+
+impl HooksConfig {
+    pub fn command(&self, event: HookEvent) -> Option<&str> {
+        match event {
+            HookEvent::Connect => self.on_connect.as_deref(),
+            HookEvent::Disconnect => self.on_disconnect.as_deref(),
+            HookEvent::Error => self.on_error.as_deref(),
+            HookEvent::RelayChange => self.on_relay_change.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    Connect,
+    Disconnect,
+    Error,
+    /// Still connected, but through a different relay.
+    RelayChange,
+}
+
+impl Display for HookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HookEvent::Connect => write!(f, "on_connect"),
+            HookEvent::Disconnect => write!(f, "on_disconnect"),
+            HookEvent::Error => write!(f, "on_error"),
+            HookEvent::RelayChange => write!(f, "on_relay_change"),
+        }
+    }
+}
+
+/// The hook to run for going from `old` to `new`, if any.
+pub fn event(old: &TunnelStatus, new: &TunnelStatus) -> Option<HookEvent> {
+    match (&old.state, &new.state) {
+        (TunnelState::Connected, TunnelState::Connected) if old.relay != new.relay => {
+            Some(HookEvent::RelayChange)
+        }
+        (TunnelState::Connected, TunnelState::Connected) => None,
+        (_, TunnelState::Connected) => Some(HookEvent::Connect),
+        (TunnelState::Disconnected, TunnelState::Disconnected) => None,
+        (_, TunnelState::Disconnected) => Some(HookEvent::Disconnect),
+        (TunnelState::Error(_), TunnelState::Error(_)) => None,
+        (_, TunnelState::Error(_)) => Some(HookEvent::Error),
+        _ => None,
+    }
+}
+
+/// Environment describing the new state, e.g. `MULLVAD_HOSTNAME=se-got-wg-001`.
+pub fn env(event: HookEvent, status: &TunnelStatus) -> Vec<(String, String)> {
+    let hostname = status.relay.clone().unwrap_or_default();
+    let mut codes = hostname.split('-');
+    let (country, city) = match (codes.next(), codes.next()) {
+        (Some(country), Some(city)) => (country.to_string(), city.to_string()),
+        _ => (String::new(), String::new()),
+    };
+    let error = match &status.state {
+        TunnelState::Error(e) => e.clone(),
+        _ => String::new(),
+    };
+    let or_empty = |value: &Option<String>| value.clone().unwrap_or_default();
+
+    [
+        ("MULLVAD_EVENT", event.to_string()),
+        ("MULLVAD_STATE", status.state.to_string()),
+        ("MULLVAD_ERROR", error),
+        ("MULLVAD_HOSTNAME", hostname),
+        ("MULLVAD_COUNTRY_CODE", country),
+        ("MULLVAD_CITY_CODE", city),
+        ("MULLVAD_LOCATION", or_empty(&status.location)),
+        ("MULLVAD_ENTRY_IP", or_empty(&status.entry_ip)),
+        ("MULLVAD_IPV4", or_empty(&status.visible_ipv4)),
+        ("MULLVAD_IPV6", or_empty(&status.visible_ipv6)),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value))
+    .collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HookStatus {
+    Exited(Option<i32>),
+    TimedOut,
+    /// The command could not be started.
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub struct HookResult {
+    pub event: HookEvent,
+    pub status: HookStatus,
+    pub duration: Duration,
+    /// Combined stdout and stderr.
+    pub output: String,
+}
+
+impl HookResult {
+    /// `on_connect exited with 0 after 0.2s`
+    pub fn summary(&self) -> String {
+        let secs = self.duration.as_secs_f64();
+        match &self.status {
+            HookStatus::Exited(Some(code)) => {
+                format!("{} exited with {} after {:.1}s", self.event, code, secs)
+            }
+            HookStatus::Exited(None) => format!("{} was killed by a signal", self.event),
+            HookStatus::TimedOut => format!("{} timed out after {:.1}s", self.event, secs),
+            HookStatus::Failed(e) => format!("{} failed to start: {}", self.event, e),
+        }
+    }
+}
+
+/// Runs `command` through `sh -c`, killing it after `timeout`.
+pub fn run(
+    event: HookEvent,
+    command: &str,
+    env: &[(String, String)],
+    timeout: Duration,
+) -> HookResult {
+    let started = Instant::now();
+    let result = |status, output| HookResult {
+        event,
+        status,
+        duration: started.elapsed(),
+        output,
+    };
+
+    let mut child = match Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => return result(HookStatus::Failed(e.to_string()), String::new()),
+    };
+
+    // Drain both pipes while waiting so a chatty hook cannot fill them and stall.
+    let (output_tx, output_rx) = mpsc::channel::<Vec<u8>>();
+    let pipes = [
+        child
+            .stdout
+            .take()
+            .map(|r| Box::new(r) as Box<dyn Read + Send>),
+        child
+            .stderr
+            .take()
+            .map(|r| Box::new(r) as Box<dyn Read + Send>),
+    ];
+    for mut pipe in pipes.into_iter().flatten() {
+        let output_tx = output_tx.clone();
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            while let Ok(n @ 1..) = pipe.read(&mut buf) {
+                if output_tx.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(output_tx);
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break HookStatus::Exited(status.code()),
+            Ok(None) if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break HookStatus::TimedOut;
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => break HookStatus::Failed(e.to_string()),
+        }
+    };
+
+    // Background processes started by the hook keep the pipes open, so
+    // collect only until the deadline and leave the readers to them.
+    let deadline = started + timeout;
+    let mut output = Vec::new();
+    while let Ok(chunk) = output_rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+    {
+        output.extend(chunk);
+    }
+    let output = String::from_utf8_lossy(&output).into_owned();
+    result(status, output)
+}
+
+/// Runs the hook on its own thread so a slow one never blocks the UI.
+pub fn spawn(
+    event: HookEvent,
+    command: String,
+    env: Vec<(String, String)>,
+    timeout: Duration,
+    tx: mpsc::Sender<WorkerEvent>,
+) {
+    thread::spawn(move || {
+        let result = run(event, &command, &env, timeout);
+        let _ = tx.send(WorkerEvent::Hook(result));
+    });
+}
@@ -0,0 +1,123 @@
+use std::{sync::mpsc, thread, time::Duration};
+
+use serde::Deserialize;
+
+use crate::{data::TunnelStatus, error::AppError, worker::WorkerEvent};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LeakCheckConfig {
+    /// Check after every connect and relay change; `V` checks on demand either way.
+    pub enabled: bool,
+    /// Answers with the exit IP and whether it belongs to Mullvad.
+    pub url: String,
+    /// Lists the resolvers that looked up the name, `{id}` being replaced by
+    /// a random one. No DNS check when empty.
+    pub dns_url: Option<String>,
+    /// Seconds to wait for each request.
+    pub timeout: u64,
+}
+
+impl Default for LeakCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: "https://am.i.mullvad.net/json".to_string(),
+            dns_url: Some("https://{id}.dnsleak.am.i.mullvad.net/".to_string()),
+            timeout: 5,
+        }
+    }
+}
+
+/// What the endpoint saw of this machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeakCheck {
+    pub ip: String,
+    pub mullvad_exit_ip: bool,
+    pub hostname: Option<String>,
+    /// Whether a resolver outside Mullvad answered, `None` without a DNS check.
+    pub dns_leaking: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExitResponse {
+    ip: String,
+    mullvad_exit_ip: bool,
+    mullvad_exit_ip_hostname: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Resolver {
+    mullvad_dns: bool,
+}
+
+/// Parses `{"ip": "...", "mullvad_exit_ip": true, "mullvad_exit_ip_hostname": "..."}`.
+pub fn parse(body: &str) -> Result<LeakCheck, AppError> {
+    let response: ExitResponse = serde_json::from_str(body)
+        .map_err(|e| AppError::Parse(format!("Unexpected leak check response: {}", e)))?;
+    Ok(LeakCheck {
+        ip: response.ip,
+        mullvad_exit_ip: response.mullvad_exit_ip,
+        hostname: response.mullvad_exit_ip_hostname,
+        dns_leaking: None,
+    })
+}
+
+/// Parses `[{"ip": "...", "mullvad_dns": true}, ...]` into whether any
+/// resolver is not Mullvad's.
+pub fn parse_dns(body: &str) -> Result<bool, AppError> {
+    let resolvers: Vec<Resolver> = serde_json::from_str(body)
+        .map_err(|e| AppError::Parse(format!("Unexpected DNS check response: {}", e)))?;
+    Ok(resolvers.iter().any(|r| !r.mullvad_dns))
+}
+
+pub fn check(config: &LeakCheckConfig) -> Result<LeakCheck, AppError> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(config.timeout))
+        .build();
+    let mut result = parse(&agent.get(&config.url).call()?.into_string()?)?;
+    if let Some(dns_url) = config.dns_url.as_deref().filter(|url| !url.is_empty()) {
+        let id = format!("{:016x}", fastrand::u64(..));
+        let body = agent
+            .get(&dns_url.replace("{id}", &id))
+            .call()?
+            .into_string()?;
+        result.dns_leaking = Some(parse_dns(&body)?);
+    }
+    Ok(result)
+}
+
+/// Runs the check in the background and reports it as a `WorkerEvent::LeakCheck`.
+pub fn spawn(config: LeakCheckConfig, tx: mpsc::Sender<WorkerEvent>) {
+    thread::spawn(move || {
+        let result = check(&config).map_err(|e| e.to_string());
+        let _ = tx.send(WorkerEvent::LeakCheck(result));
+    });
+}
+
+/// Why the check contradicts a daemon that says it is connected.
+pub fn disagreement(status: &TunnelStatus, check: &LeakCheck) -> Option<String> {
+    if !status.is_connected() {
+        return None;
+    }
+    if !check.mullvad_exit_ip {
+        return Some(format!(
+            "Mullvad reports connected, but traffic leaves from {}, which is not a Mullvad server",
+            check.ip
+        ));
+    }
+    let expected = [&status.visible_ipv4, &status.visible_ipv6];
+    if expected.iter().any(|ip| ip.is_some())
+        && !expected.iter().any(|ip| ip.as_deref() == Some(&check.ip))
+    {
+        return Some(format!(
+            "Mullvad reports exit IP {}, but traffic leaves from {}",
+            status.exit_ip().unwrap_or_default(),
+            check.ip
+        ));
+    }
+    if check.dns_leaking == Some(true) {
+        return Some("DNS requests are leaking outside the tunnel".to_string());
+    }
+    None
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{config, error::AppError};
+
+/// Entries kept in memory for the log pane.
+const MEMORY_ENTRIES: usize = 1000;
+
+/// The log file is rotated once it grows past this, keeping `ROTATIONS` old ones.
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+const ROTATIONS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    /// `-v` logs info, `-vv` everything; warnings and errors are always logged.
+    pub fn from_verbosity(verbose: u8) -> Self {
+        match verbose {
+            0 => Level::Warn,
+            1 => Level::Info,
+            _ => Level::Debug,
+        }
+    }
+
+    /// The next filter of the log pane: debug, info, warn, error, debug, ...
+    pub fn cycle(self) -> Self {
+        match self {
+            Level::Debug => Level::Info,
+            Level::Info => Level::Warn,
+            Level::Warn => Level::Error,
+            Level::Error => Level::Debug,
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub time: SystemTime,
+    pub level: Level,
+    /// What logged it: `backend`, `state`, `hook`, `app`.
+    pub target: &'static str,
+    pub message: String,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl Display for Entry {
+    /// `backend: mullvad status -v exit=0 duration_ms=12`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.target, self.message)?;
+        for (key, value) in &self.fields {
+            if value.contains(char::is_whitespace) || value.is_empty() {
+                write!(f, " {}={:?}", key, value)?;
+            } else {
+                write!(f, " {}={}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct LogFile {
+    path: PathBuf,
+    file: File,
+    level: Level,
+}
+
+static MEMORY: Mutex<VecDeque<Entry>> = Mutex::new(VecDeque::new());
+static FILE: OnceLock<Mutex<LogFile>> = OnceLock::new();
+
+/// `$XDG_STATE_HOME/mullvad-tui/mullvad-tui.log`, falling back to `~/.local/state`.
+pub fn default_path() -> Option<PathBuf> {
+    Some(config::xdg_dir("XDG_STATE_HOME", &[".local", "state"])?.join("mullvad-tui.log"))
+}
+
+/// Starts writing entries at `level` and above to the file at `path`.
+pub fn init(level: Level, path: &Path) -> Result<(), AppError> {
+    let _ = FILE.set(Mutex::new(LogFile::open(path, level)?));
+    Ok(())
+}
+
+pub fn log(
+    level: Level,
+    target: &'static str,
+    message: impl Into<String>,
+    fields: Vec<(&'static str, String)>,
+) {
+    let entry = Entry {
+        time: SystemTime::now(),
+        level,
+        target,
+        message: message.into(),
+        fields,
+    };
+
+    if let Some(file) = FILE.get() {
+        let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+        // Losing a log line is better than failing whatever was being logged.
+        let _ = file.write(&entry);
+    }
+
+    let mut memory = MEMORY.lock().unwrap_or_else(|e| e.into_inner());
+    if memory.len() == MEMORY_ENTRIES {
+        memory.pop_front();
+    }
+    memory.push_back(entry);
+}
+
+pub fn error(target: &'static str, message: impl Into<String>) {
+    log(Level::Error, target, message, Vec::new());
+}
+
+pub fn warn(target: &'static str, message: impl Into<String>) {
+    log(Level::Warn, target, message, Vec::new());
+}
+
+pub fn info(target: &'static str, message: impl Into<String>) {
+    log(Level::Info, target, message, Vec::new());
+}
+
+/// The last `count` entries at `level` or above, oldest first.
+pub fn recent(level: Level, count: usize) -> Vec<Entry> {
+    let memory = MEMORY.lock().unwrap_or_else(|e| e.into_inner());
+    let mut entries: Vec<Entry> = memory
+        .iter()
+        .rev()
+        .filter(|e| e.level <= level)
+        .take(count)
+        .cloned()
+        .collect();
+    entries.reverse();
+    entries
+}
+
+impl LogFile {
+    pub fn open(path: &Path, level: Level) -> Result<Self, AppError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: OpenOptions::new().create(true).append(true).open(path)?,
+            level,
+        })
+    }
+
+    pub fn write(&mut self, entry: &Entry) -> Result<(), AppError> {
+        if entry.level > self.level {
+            return Ok(());
+        }
+        if self.file.metadata()?.len() >= MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+        writeln!(
+            self.file,
+            "{} {:5} {}",
+            format_time(entry.time),
+            entry.level,
+            entry
+        )?;
+        Ok(())
+    }
+
+    /// `mullvad-tui.log` becomes `mullvad-tui.log.1`, `.1` becomes `.2`, ...
+    fn rotate(&mut self) -> Result<(), AppError> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        for n in (1..ROTATIONS).rev() {
+            if rotated(n).exists() {
+                fs::rename(rotated(n), rotated(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated(1))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        Ok(())
+    }
+}
+
+/// `2024-10-18T12:34:56Z`
+pub fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, rest) = (secs / 86400, secs % 86400);
+
+    // Days since the epoch to a civil date, after Howard Hinnant's `civil_from_days`.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    )
+}
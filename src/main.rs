@@ -1,14 +1,42 @@
+use std::sync::Arc;
+
 use app::App;
+use backend::CliBackend;
 use clap::Parser;
 use cli::Cli;
 use dotenv::dotenv;
 use error::AppError;
+use terminal::Signals;
 
+mod action;
+mod api_access;
 mod app;
+mod backend;
+mod bar;
+mod bridge;
+mod cache;
 mod cli;
+mod commands;
 mod config;
+mod control;
 mod data;
 mod error;
+mod export;
+mod filter;
+mod history;
+mod hooks;
+mod leak;
+mod logging;
+mod map;
+mod notify;
+mod picker;
+mod profile;
+mod query;
+mod random;
+mod rotation;
+mod terminal;
+mod traffic;
+mod worker;
 
 #[cfg(test)]
 mod tests;
@@ -17,13 +45,44 @@ fn main() -> Result<(), AppError> {
     dotenv().ok();
 
     let args = Cli::parse();
-    let mut terminal = ratatui::init();
-    let mut app = App::init(args.config).unwrap_or_else(|e| {
-        eprintln!("Error: {:?}", e);
+    if let Some(path) = logging::default_path() {
+        // Logging to the file is a convenience; run without it if the state dir is not writable.
+        let _ = logging::init(logging::Level::from_verbosity(args.verbose), &path);
+    }
+    let backend = Arc::new(CliBackend::new(&args.mullvad_bin));
+    let socket = args.socket.clone().or_else(control::default_path);
+
+    if let Some(command) = args.command {
+        if let Err(e) = commands::run(
+            command,
+            backend.clone(),
+            args.config.as_deref(),
+            socket.as_deref(),
+        ) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Probe the daemon before taking over the terminal so errors end up readable.
+    let mut app = App::init(args.config, backend).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
         std::process::exit(1);
     });
+    if args.listen {
+        // The TUI is still useful without the socket, so only report it.
+        let result = socket
+            .ok_or_else(commands::socket_unset)
+            .and_then(|socket| app.listen(&socket));
+        if let Err(e) = result {
+            app.show_error(e);
+        }
+    }
 
-    let app_result = app.run(&mut terminal);
-    ratatui::restore();
+    let signals = Signals::register()?;
+    let mut terminal = terminal::init()?;
+    let app_result = app.run(&mut terminal, &signals);
+    terminal::restore();
     app_result
 }
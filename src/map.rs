@@ -0,0 +1,171 @@
+use crate::data::{Coordinates, Country, Location};
+
+/// Where Mullvad's cities are, for relay lists printed without coordinates.
+/// Keyed by country and city code.
+const CITY_COORDINATES: &[(&str, &str, f64, f64)] = &[
+    ("ae", "dxb", 25.20, 55.27),
+    ("al", "tia", 41.33, 19.82),
+    ("ar", "bue", -34.60, -58.38),
+    ("at", "vie", 48.21, 16.37),
+    ("au", "adl", -34.93, 138.60),
+    ("au", "bne", -27.47, 153.03),
+    ("au", "mel", -37.81, 144.96),
+    ("au", "per", -31.95, 115.86),
+    ("au", "syd", -33.87, 151.21),
+    ("be", "bru", 50.85, 4.35),
+    ("bg", "sof", 42.70, 23.32),
+    ("br", "sao", -23.55, -46.63),
+    ("ca", "mtr", 45.50, -73.57),
+    ("ca", "tor", 43.65, -79.38),
+    ("ca", "van", 49.28, -123.12),
+    ("ca", "yyc", 51.05, -114.07),
+    ("ch", "zrh", 47.37, 8.55),
+    ("cl", "scl", -33.45, -70.67),
+    ("co", "bog", 4.71, -74.07),
+    ("cy", "nic", 35.19, 33.38),
+    ("cz", "prg", 50.08, 14.44),
+    ("de", "ber", 52.52, 13.40),
+    ("de", "dus", 51.23, 6.77),
+    ("de", "fra", 50.11, 8.68),
+    ("dk", "cph", 55.68, 12.57),
+    ("ee", "tll", 59.44, 24.75),
+    ("es", "bcn", 41.39, 2.17),
+    ("es", "mad", 40.42, -3.70),
+    ("es", "vlc", 39.47, -0.38),
+    ("fi", "hel", 60.17, 24.94),
+    ("fr", "bod", 44.84, -0.58),
+    ("fr", "mrs", 43.30, 5.37),
+    ("fr", "par", 48.86, 2.35),
+    ("gb", "glw", 55.86, -4.25),
+    ("gb", "lon", 51.51, -0.13),
+    ("gb", "mnc", 53.48, -2.24),
+    ("gr", "ath", 37.98, 23.73),
+    ("hk", "hkg", 22.32, 114.17),
+    ("hr", "zag", 45.81, 15.98),
+    ("hu", "bud", 47.50, 19.04),
+    ("id", "jpu", -6.21, 106.85),
+    ("ie", "dub", 53.35, -6.26),
+    ("il", "tlv", 32.09, 34.78),
+    ("it", "mil", 45.46, 9.19),
+    ("it", "pmo", 38.12, 13.36),
+    ("jp", "osa", 34.69, 135.50),
+    ("jp", "tyo", 35.68, 139.69),
+    ("lv", "rix", 56.95, 24.11),
+    ("mx", "qro", 20.59, -100.39),
+    ("ng", "los", 6.52, 3.38),
+    ("nl", "ams", 52.37, 4.90),
+    ("no", "osl", 59.91, 10.75),
+    ("no", "svg", 58.97, 5.73),
+    ("nz", "akl", -36.85, 174.76),
+    ("pe", "lim", -12.05, -77.04),
+    ("ph", "mnl", 14.60, 120.98),
+    ("pl", "waw", 52.23, 21.01),
+    ("pt", "lis", 38.72, -9.14),
+    ("ro", "buh", 44.43, 26.10),
+    ("rs", "beg", 44.79, 20.45),
+    ("se", "got", 57.71, 11.97),
+    ("se", "mma", 55.61, 13.00),
+    ("se", "sto", 59.33, 18.07),
+    ("sg", "sin", 1.35, 103.82),
+    ("si", "lju", 46.06, 14.51),
+    ("sk", "bts", 48.15, 17.11),
+    ("th", "bkk", 13.76, 100.50),
+    ("tr", "ist", 41.01, 28.98),
+    ("ua", "iev", 50.45, 30.52),
+    ("us", "atl", 33.75, -84.39),
+    ("us", "bos", 42.36, -71.06),
+    ("us", "chi", 41.88, -87.63),
+    ("us", "dal", 32.78, -96.80),
+    ("us", "den", 39.74, -104.99),
+    ("us", "det", 42.33, -83.05),
+    ("us", "hou", 29.76, -95.37),
+    ("us", "lax", 34.05, -118.24),
+    ("us", "mia", 25.76, -80.19),
+    ("us", "mkc", 39.10, -94.58),
+    ("us", "nyc", 40.71, -74.01),
+    ("us", "phx", 33.45, -112.07),
+    ("us", "rag", 35.78, -78.64),
+    ("us", "sea", 47.61, -122.33),
+    ("us", "sjc", 37.34, -121.89),
+    ("us", "slc", 40.76, -111.89),
+    ("us", "uyk", 40.79, -74.06),
+    ("us", "was", 38.91, -77.04),
+    ("za", "jnb", -26.20, 28.05),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// A city plotted on the map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapCity {
+    pub location: Location,
+    pub name: String,
+    pub coordinates: Coordinates,
+}
+
+/// The bundled coordinates of a city.
+pub fn known_coordinates(country: &str, city: &str) -> Option<Coordinates> {
+    CITY_COORDINATES
+        .iter()
+        .find(|(c, code, _, _)| *c == country && *code == city)
+        .map(|(_, _, latitude, longitude)| Coordinates {
+            latitude: *latitude,
+            longitude: *longitude,
+        })
+}
+
+/// Every city that can be placed, from the relay list or the bundled table.
+pub fn cities(countries: &[Country]) -> Vec<MapCity> {
+    countries
+        .iter()
+        .flat_map(|country| {
+            country.cities.iter().filter_map(|city| {
+                let coordinates = city
+                    .coordinates
+                    .or_else(|| known_coordinates(&country.code, &city.code))?;
+                Some(MapCity {
+                    location: Location::City(country.code.clone(), city.code.clone()),
+                    name: city.name.clone(),
+                    coordinates,
+                })
+            })
+        })
+        .collect()
+}
+
+/// The closest city to `from` in `direction`. Cities within 45 degrees of
+/// the direction win over ones further off to the side.
+pub fn nearest(cities: &[MapCity], from: usize, direction: Direction) -> Option<usize> {
+    let origin = cities.get(from)?.coordinates;
+    let candidates: Vec<(usize, f64, f64)> = cities
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != from)
+        .filter_map(|(i, city)| {
+            let dx = city.coordinates.longitude - origin.longitude;
+            let dy = city.coordinates.latitude - origin.latitude;
+            // Distance along the direction and off to the side.
+            let (ahead, aside) = match direction {
+                Direction::Up => (dy, dx),
+                Direction::Down => (-dy, dx),
+                Direction::Left => (-dx, dy),
+                Direction::Right => (dx, dy),
+            };
+            (ahead > 0.0).then_some((i, ahead, aside.abs()))
+        })
+        .collect();
+    let in_view = candidates.iter().filter(|(_, ahead, aside)| aside <= ahead);
+    closest(in_view).or_else(|| closest(candidates.iter()))
+}
+
+fn closest<'a>(candidates: impl Iterator<Item = &'a (usize, f64, f64)>) -> Option<usize> {
+    candidates
+        .min_by(|a, b| a.1.hypot(a.2).total_cmp(&b.1.hypot(b.2)))
+        .map(|(i, _, _)| *i)
+}
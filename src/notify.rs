@@ -0,0 +1,154 @@
+use std::{fmt::Debug, process::Command, thread};
+
+use serde::Deserialize;
+
+use crate::{
+    data::{TunnelState, TunnelStatus},
+    error::AppError,
+    logging,
+};
+
+/// State transitions worth a desktop notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyEvent {
+    Connected,
+    /// The tunnel went down without the user asking for it.
+    Disconnected,
+    Error,
+    /// Lockdown mode is blocking traffic.
+    Blocked,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub event: NotifyEvent,
+    pub summary: String,
+    pub body: String,
+}
+
+pub trait Notifier: Debug + Send {
+    fn notify(&self, notification: &Notification) -> Result<(), AppError>;
+}
+
+/// Runs `<command> <summary> <body>`, e.g. `notify-send`, without waiting for it.
+///
+/// This is the only backend: `notify-send` already hands the notification to
+/// the notification daemon over D-Bus, and going through it keeps a D-Bus
+/// client and its system library out of the build. `command` covers setups
+/// without it, such as `dunstify` or a script.
+#[derive(Debug)]
+pub struct CommandNotifier {
+    command: Vec<String>,
+}
+
+impl CommandNotifier {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.split_whitespace().map(String::from).collect(),
+        }
+    }
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&self, notification: &Notification) -> Result<(), AppError> {
+        let Some((program, args)) = self.command.split_first() else {
+            return Ok(());
+        };
+        let mut child = Command::new(program)
+            .args(args)
+            .arg(&notification.summary)
+            .arg(&notification.body)
+            .spawn()
+            .map_err(|e| AppError::Command(format!("Failed to run '{}': {}", program, e)))?;
+        let program = program.clone();
+        thread::spawn(move || match child.wait() {
+            Ok(status) if !status.success() => {
+                logging::warn("notify", format!("'{}' failed with {}", program, status))
+            }
+            Ok(_) => {}
+            Err(e) => logging::warn("notify", format!("Failed to wait for '{}': {}", program, e)),
+        });
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    pub enabled: bool,
+    /// Command that receives the summary and body as its last two arguments.
+    pub command: String,
+    pub connected: bool,
+    pub disconnected: bool,
+    pub error: bool,
+    pub blocked: bool,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            command: "notify-send --app-name=mullvad-tui".to_string(),
+            connected: true,
+            disconnected: true,
+            error: true,
+            blocked: true,
+        }
+    }
+}
+
+impl NotificationsConfig {
+    pub fn allows(&self, event: NotifyEvent) -> bool {
+        self.enabled
+            && match event {
+                NotifyEvent::Connected => self.connected,
+                NotifyEvent::Disconnected => self.disconnected,
+                NotifyEvent::Error => self.error,
+                NotifyEvent::Blocked => self.blocked,
+            }
+    }
+}
+
+/// The notification for going from `old` to `new`, if any. Disconnects the
+/// user asked for (`requested`) are not reported.
+pub fn transition(old: &TunnelStatus, new: &TunnelStatus, requested: bool) -> Option<Notification> {
+    let notification = |event, summary: &str, body: String| {
+        Some(Notification {
+            event,
+            summary: summary.to_string(),
+            body,
+        })
+    };
+
+    match (&old.state, &new.state) {
+        (TunnelState::Connected, TunnelState::Connected) if old.relay == new.relay => None,
+        (_, TunnelState::Connected) => notification(
+            NotifyEvent::Connected,
+            "Mullvad VPN connected",
+            match (&new.relay, &new.location) {
+                (Some(relay), Some(location)) => format!("{} in {}", relay, location),
+                (Some(relay), None) => relay.clone(),
+                _ => String::new(),
+            },
+        ),
+        (
+            TunnelState::Connected | TunnelState::Connecting | TunnelState::Disconnecting,
+            TunnelState::Disconnected,
+        ) if !requested => notification(
+            NotifyEvent::Disconnected,
+            "Mullvad VPN disconnected",
+            "The tunnel went down unexpectedly".to_string(),
+        ),
+        (TunnelState::Error(_), TunnelState::Error(_)) => None,
+        (_, TunnelState::Error(e)) => {
+            notification(NotifyEvent::Error, "Mullvad VPN error", e.clone())
+        }
+        (TunnelState::Blocked, TunnelState::Blocked) => None,
+        (_, TunnelState::Blocked) => notification(
+            NotifyEvent::Blocked,
+            "Mullvad VPN is blocking traffic",
+            "Lockdown mode blocks all connections until the tunnel is up".to_string(),
+        ),
+        _ => None,
+    }
+}
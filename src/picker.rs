@@ -0,0 +1,630 @@
+use std::collections::HashSet;
+
+use crossterm::event::KeyCode;
+use ratatui::{
+    layout::{Alignment, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{List, ListItem, ListState},
+    Frame,
+};
+
+use crate::{
+    data::{Country, Location, Relay},
+    error::AppError,
+};
+
+/// Rows kept between the selection and the edge of the tree while scrolling.
+const SCROLL_PADDING: usize = 3;
+
+/// Which list of the tree is shown, or what kind of row is selected in tree mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Level {
+    #[default]
+    Countries,
+    Cities,
+    Relays,
+}
+
+/// A country, city or relay, by index into the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Node {
+    country: usize,
+    city: Option<usize>,
+    relay: Option<usize>,
+}
+
+impl Node {
+    fn level(&self) -> Level {
+        match (self.city, self.relay) {
+            (None, _) => Level::Countries,
+            (Some(_), None) => Level::Cities,
+            (Some(_), Some(_)) => Level::Relays,
+        }
+    }
+
+    fn parent(&self) -> Option<Node> {
+        match (self.city, self.relay) {
+            (None, _) => None,
+            (Some(_), None) => Some(Node {
+                city: None,
+                ..*self
+            }),
+            (Some(_), Some(_)) => Some(Node {
+                relay: None,
+                ..*self
+            }),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Row {
+    node: Node,
+    label: String,
+}
+
+/// Browses countries, their cities and their relays, either one list at a
+/// time or as a tree that expands inline.
+///
+/// Owns the relay tree, the search filter, the selection and the scroll
+/// position, so any view that picks a location can embed one.
+#[derive(Debug, Default)]
+pub struct LocationPicker {
+    tree: Vec<Country>,
+    level: Level,
+    /// Lowercase; only rows containing it are shown.
+    filter: String,
+    /// Indices into the tree of the country and city being browsed.
+    country: usize,
+    city: usize,
+    /// The selected row among the shown ones, and the scroll offset.
+    state: ListState,
+    /// Set by a first `g`, so a second one jumps to the top.
+    pending_g: bool,
+    tree_mode: bool,
+    /// Countries and cities opened in tree mode.
+    expanded: HashSet<Location>,
+}
+
+impl LocationPicker {
+    pub fn new(tree: Vec<Country>) -> Self {
+        Self {
+            tree,
+            state: ListState::default().with_selected(Some(0)),
+            ..Self::default()
+        }
+    }
+
+    /// Replaces the tree, keeping the selection if it still exists.
+    pub fn set_tree(&mut self, tree: Vec<Country>) {
+        let location = self.selected_location();
+        self.tree = tree;
+        let kept = location.is_some_and(|location| self.select(&location).is_ok());
+        if !kept {
+            self.level = Level::Countries;
+            self.state.select(Some(0));
+        }
+    }
+
+    pub fn is_tree(&self) -> bool {
+        self.tree_mode
+    }
+
+    /// Switches between the tree and the separate lists, keeping the selection.
+    pub fn set_tree_mode(&mut self, tree_mode: bool) {
+        let node = self.selected();
+        self.tree_mode = tree_mode;
+        match node {
+            Some(node) => self.show(node),
+            None => self.top(),
+        }
+    }
+
+    /// The shown list, or the kind of the selected row in tree mode.
+    pub fn level(&self) -> Level {
+        if self.tree_mode {
+            self.selected()
+                .map_or(Level::Countries, |node| node.level())
+        } else {
+            self.level
+        }
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    fn location(&self, node: Node) -> Option<Location> {
+        let country = self.tree.get(node.country)?;
+        let Some(city) = node.city else {
+            return Some(Location::Country(country.code.clone()));
+        };
+        let city = country.cities.get(city)?;
+        let Some(relay) = node.relay else {
+            return Some(Location::City(country.code.clone(), city.code.clone()));
+        };
+        let relay = city.relays.get(relay)?;
+        Some(Location::Relay(
+            country.code.clone(),
+            city.code.clone(),
+            relay.hostname.clone(),
+        ))
+    }
+
+    /// The name of the country, city or relay as listed.
+    fn name(&self, node: Node) -> Option<String> {
+        let country = self.tree.get(node.country)?;
+        let Some(city) = node.city else {
+            return Some(country.to_string());
+        };
+        let city = country.cities.get(city)?;
+        match node.relay {
+            Some(relay) => city.relays.get(relay).map(ToString::to_string),
+            None => Some(city.to_string()),
+        }
+    }
+
+    fn is_expanded(&self, node: Node) -> bool {
+        self.location(node)
+            .is_some_and(|location| self.expanded.contains(&location))
+    }
+
+    fn set_expanded(&mut self, node: Node, expanded: bool) {
+        if let Some(location) = self.location(node) {
+            if expanded {
+                self.expanded.insert(location);
+            } else {
+                self.expanded.remove(&location);
+            }
+        }
+    }
+
+    /// Whether a row label matches a non-empty filter.
+    fn hit(&self, label: &str) -> bool {
+        !self.filter.is_empty() && label.to_lowercase().contains(&self.filter)
+    }
+
+    /// The shown rows, top to bottom.
+    fn rows(&self) -> Vec<Row> {
+        if self.tree_mode {
+            return self.tree_rows();
+        }
+        let row = |node: Node, label: String| Row { node, label };
+        let rows: Vec<Row> = match self.level {
+            Level::Countries => self
+                .tree
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    row(
+                        Node {
+                            country: i,
+                            city: None,
+                            relay: None,
+                        },
+                        c.to_string(),
+                    )
+                })
+                .collect(),
+            Level::Cities => self
+                .tree
+                .get(self.country)
+                .map(|c| c.cities.as_slice())
+                .unwrap_or_default()
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    row(
+                        Node {
+                            country: self.country,
+                            city: Some(i),
+                            relay: None,
+                        },
+                        c.to_string(),
+                    )
+                })
+                .collect(),
+            Level::Relays => self
+                .tree
+                .get(self.country)
+                .and_then(|c| c.cities.get(self.city))
+                .map(|c| c.relays.as_slice())
+                .unwrap_or_default()
+                .iter()
+                .enumerate()
+                .map(|(i, r)| {
+                    let node = Node {
+                        country: self.country,
+                        city: Some(self.city),
+                        relay: Some(i),
+                    };
+                    row(node, r.to_string())
+                })
+                .collect(),
+        };
+        rows.into_iter()
+            .filter(|row| row.label.to_lowercase().contains(&self.filter))
+            .collect()
+    }
+
+    /// The tree with expanded nodes open. While searching, branches leading
+    /// to a match open by themselves.
+    fn tree_rows(&self) -> Vec<Row> {
+        let branch = |node: Node, open: bool, name: String, relays: usize| {
+            let indent = "  ".repeat(node.level() as usize);
+            let marker = if open { '▾' } else { '▸' };
+            Row {
+                node,
+                label: format!("{}{} {} [{}]", indent, marker, name, relays),
+            }
+        };
+        let mut rows = Vec::new();
+        for (c, country) in self.tree.iter().enumerate() {
+            let country_node = Node {
+                country: c,
+                city: None,
+                relay: None,
+            };
+            let country_open = self.is_expanded(country_node)
+                && (self.filter.is_empty() || self.hit(&country.to_string()));
+            let mut children = Vec::new();
+            for (i, city) in country.cities.iter().enumerate() {
+                let city_node = Node {
+                    city: Some(i),
+                    ..country_node
+                };
+                let city_hit = self.hit(&city.to_string());
+                let city_open = self.is_expanded(city_node) && (country_open || city_hit);
+                let relays: Vec<Row> = city
+                    .relays
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, relay)| city_open || self.hit(&relay.to_string()))
+                    .map(|(j, relay)| Row {
+                        node: Node {
+                            relay: Some(j),
+                            ..city_node
+                        },
+                        label: format!("      {}", relay),
+                    })
+                    .collect();
+                if country_open || city_hit || !relays.is_empty() {
+                    children.push(branch(
+                        city_node,
+                        !relays.is_empty(),
+                        city.to_string(),
+                        city.relays.len(),
+                    ));
+                    children.extend(relays);
+                }
+            }
+            if self.filter.is_empty() || self.hit(&country.to_string()) || !children.is_empty() {
+                rows.push(branch(
+                    country_node,
+                    !children.is_empty(),
+                    country.to_string(),
+                    country.relays().count(),
+                ));
+                rows.extend(children);
+            }
+        }
+        rows
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows().len()
+    }
+
+    fn selected(&self) -> Option<Node> {
+        self.rows().get(self.state.selected()?).map(|row| row.node)
+    }
+
+    /// Selects the row of `node`, or the first row if it is not shown.
+    fn select_node(&mut self, node: Node) {
+        let row = self
+            .rows()
+            .iter()
+            .position(|row| row.node == node)
+            .unwrap_or(0);
+        self.state.select(Some(row));
+    }
+
+    /// Makes `node` visible and selects it: opens its parents in tree mode,
+    /// or switches to its list otherwise.
+    fn show(&mut self, node: Node) {
+        if self.tree_mode {
+            let mut parent = node.parent();
+            while let Some(node) = parent {
+                self.set_expanded(node, true);
+                parent = node.parent();
+            }
+        } else {
+            self.level = node.level();
+            self.country = node.country;
+            self.city = node.city.unwrap_or(0);
+        }
+        self.select_node(node);
+    }
+
+    pub fn offset(&self) -> usize {
+        self.state.offset()
+    }
+
+    pub fn select_row(&mut self, row: usize) {
+        if row < self.len() {
+            self.state.select(Some(row));
+        }
+    }
+
+    pub fn next(&mut self) {
+        let last = self.len().saturating_sub(1);
+        let row = self.state.selected().map_or(0, |row| row + 1);
+        self.state.select(Some(row.min(last)));
+    }
+
+    pub fn previous(&mut self) {
+        let row = self.state.selected().unwrap_or(0);
+        self.state.select(Some(row.saturating_sub(1)));
+    }
+
+    pub fn first(&mut self) {
+        self.state.select(Some(0));
+    }
+
+    pub fn last(&mut self) {
+        self.state.select(Some(self.len().saturating_sub(1)));
+    }
+
+    /// Moves the selection with `j`/`k`, the arrows, `G` and `gg`. Returns
+    /// whether the key was one of them.
+    pub fn handle_key(&mut self, code: KeyCode) -> bool {
+        let pending_g = std::mem::take(&mut self.pending_g);
+        match code {
+            KeyCode::Down | KeyCode::Char('j') => self.next(),
+            KeyCode::Up | KeyCode::Char('k') => self.previous(),
+            KeyCode::Char('G') => self.last(),
+            KeyCode::Char('g') if pending_g => self.first(),
+            KeyCode::Char('g') => self.pending_g = true,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Narrows the rows down to those containing one more character.
+    pub fn push_filter(&mut self, c: char) {
+        self.filter.extend(c.to_lowercase());
+        self.first_match();
+    }
+
+    pub fn pop_filter(&mut self) {
+        self.filter.pop();
+        self.first_match();
+    }
+
+    /// Selects the first row matching the filter itself, rather than a
+    /// branch opened on the way to it.
+    fn first_match(&mut self) {
+        let row = self
+            .rows()
+            .iter()
+            .position(|row| self.name(row.node).is_some_and(|name| self.hit(&name)))
+            .unwrap_or(0);
+        self.state.select(Some(row));
+    }
+
+    /// Shows every row again, keeping the selected one.
+    pub fn clear_filter(&mut self) {
+        let node = self.selected();
+        self.filter.clear();
+        match node {
+            Some(node) => self.show(node),
+            None => self.first(),
+        }
+    }
+
+    /// Back to the top of the country list, keeping the filter.
+    pub fn top(&mut self) {
+        self.level = Level::Countries;
+        self.first();
+    }
+
+    /// Back to the top of the whole country list.
+    pub fn reset(&mut self) {
+        self.filter.clear();
+        self.top();
+    }
+
+    /// Opens the selected country or city, or in tree mode moves into it
+    /// once open. Returns whether there was one.
+    pub fn enter(&mut self) -> bool {
+        let Some(node) = self.selected() else {
+            return false;
+        };
+        if node.relay.is_some() {
+            return false;
+        }
+        if self.tree_mode {
+            let row = self.state.selected().unwrap_or(0);
+            let rows = self.rows();
+            match rows.get(row + 1) {
+                Some(next) if next.node.parent() == Some(node) => self.state.select(Some(row + 1)),
+                _ => self.set_expanded(node, true),
+            }
+            return true;
+        }
+        match node.city {
+            None => {
+                self.country = node.country;
+                self.level = Level::Cities;
+            }
+            Some(city) => {
+                self.city = city;
+                self.level = Level::Relays;
+            }
+        }
+        self.filter.clear();
+        self.first();
+        true
+    }
+
+    /// Goes up a level with the country or city left selected, or in tree
+    /// mode closes the selected node or moves to its parent. Returns whether
+    /// there was somewhere to go.
+    pub fn back(&mut self) -> bool {
+        if self.tree_mode {
+            let Some(node) = self.selected() else {
+                return false;
+            };
+            if self.is_expanded(node) {
+                self.set_expanded(node, false);
+                self.select_node(node);
+                return true;
+            }
+            return match node.parent() {
+                Some(parent) => {
+                    self.select_node(parent);
+                    true
+                }
+                None => false,
+            };
+        }
+        let parent = match self.level {
+            Level::Countries => return false,
+            Level::Cities => {
+                self.level = Level::Countries;
+                Node {
+                    country: self.country,
+                    city: None,
+                    relay: None,
+                }
+            }
+            Level::Relays => {
+                self.level = Level::Cities;
+                Node {
+                    country: self.country,
+                    city: Some(self.city),
+                    relay: None,
+                }
+            }
+        };
+        self.filter.clear();
+        self.select_node(parent);
+        true
+    }
+
+    /// Shows the list the location is in with the location selected.
+    pub fn navigate(&mut self, location: &Location) -> Result<(), AppError> {
+        // Resolve against the whole lists.
+        let filter = std::mem::take(&mut self.filter);
+        let result = self.select(location);
+        if result.is_err() {
+            self.filter = filter;
+        }
+        result
+    }
+
+    /// Like `navigate` but rows hidden by the filter stay hidden.
+    fn select(&mut self, location: &Location) -> Result<(), AppError> {
+        let unknown = || AppError::Parse(format!("Unknown location: {}", location));
+        // Look everything up first so an unknown location leaves the picker alone.
+        let country = self
+            .tree
+            .iter()
+            .position(|c| c.code == location.country_code())
+            .ok_or_else(unknown)?;
+        let cities = &self.tree[country].cities;
+        let city = match location.city_code() {
+            Some(code) => Some(
+                cities
+                    .iter()
+                    .position(|c| c.code == code)
+                    .ok_or_else(unknown)?,
+            ),
+            None => None,
+        };
+        let relay = match (city, location.hostname()) {
+            (Some(i), Some(hostname)) => Some(
+                cities[i]
+                    .relays
+                    .iter()
+                    .position(|r| r.hostname == hostname)
+                    .ok_or_else(unknown)?,
+            ),
+            _ => None,
+        };
+
+        self.pending_g = false;
+        self.show(Node {
+            country,
+            city,
+            relay,
+        });
+        Ok(())
+    }
+
+    /// The country under the cursor, or the one being browsed.
+    pub fn selected_country(&self) -> Option<&Country> {
+        match self.selected() {
+            Some(node) => self.tree.get(node.country),
+            None if self.level == Level::Countries => None,
+            None => self.tree.get(self.country),
+        }
+    }
+
+    /// The location under the cursor.
+    pub fn selected_location(&self) -> Option<Location> {
+        self.location(self.selected()?)
+    }
+
+    /// Relays behind the highlighted country, city or relay.
+    pub fn selected_relays(&self) -> Vec<&Relay> {
+        let Some(node) = self.selected() else {
+            return Vec::new();
+        };
+        let Some(country) = self.tree.get(node.country) else {
+            return Vec::new();
+        };
+        let Some(city) = node.city.and_then(|i| country.cities.get(i)) else {
+            return country.relays().collect();
+        };
+        match node.relay {
+            Some(i) => city.relays.get(i).into_iter().collect(),
+            None => city.relays.iter().collect(),
+        }
+    }
+
+    /// Draws the shown rows, the selected one in `selected`. The lists are
+    /// centered and the tree is indented.
+    pub fn draw(&mut self, f: &mut Frame, area: Rect, items: Color, selected: Color) {
+        let row = self.state.selected();
+        let alignment = if self.tree_mode {
+            Alignment::Left
+        } else {
+            Alignment::Center
+        };
+        let list: Vec<ListItem> = self
+            .rows()
+            .into_iter()
+            .enumerate()
+            .map(|(i, Row { label, .. })| {
+                let color = if Some(i) == row { selected } else { items };
+                ListItem::new(
+                    Line::from(Span::from(label))
+                        .alignment(alignment)
+                        .style(Style::default().fg(color)),
+                )
+            })
+            .collect();
+
+        let mut list = List::new(list).highlight_style(
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .add_modifier(Modifier::ITALIC),
+        );
+        if self.tree_mode {
+            list = list.scroll_padding(SCROLL_PADDING);
+        }
+        f.render_stateful_widget(list, area, &mut self.state);
+    }
+}
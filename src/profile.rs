@@ -0,0 +1,369 @@
+use std::{collections::BTreeMap, fmt::Display, net::IpAddr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::Backend,
+    data::{Country, Location},
+    error::AppError,
+    filter::RelayFilter,
+    query,
+};
+
+/// The content blockers and resolvers of `mullvad dns`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DnsSettings {
+    pub block_ads: bool,
+    pub block_trackers: bool,
+    pub block_malware: bool,
+    pub block_adult_content: bool,
+    pub block_gambling: bool,
+    pub block_social_media: bool,
+    /// Resolvers to use instead of Mullvad's; the blockers are ignored then.
+    pub custom: Vec<String>,
+}
+
+impl DnsSettings {
+    fn blockers(&self) -> [(&'static str, bool); 6] {
+        [
+            ("ads", self.block_ads),
+            ("trackers", self.block_trackers),
+            ("malware", self.block_malware),
+            ("adult-content", self.block_adult_content),
+            ("gambling", self.block_gambling),
+            ("social-media", self.block_social_media),
+        ]
+    }
+
+    /// The arguments of `mullvad dns set`.
+    pub fn args(&self) -> Vec<String> {
+        if !self.custom.is_empty() {
+            return std::iter::once("custom".to_string())
+                .chain(self.custom.iter().cloned())
+                .collect();
+        }
+        let mut args = vec!["default".to_string()];
+        for (name, on) in self.blockers() {
+            if on {
+                args.push(format!("--block-{}", name));
+            }
+        }
+        args
+    }
+}
+
+impl Display for DnsSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.custom.is_empty() {
+            return write!(f, "custom {}", self.custom.join(", "));
+        }
+        let blocked: Vec<&str> = self
+            .blockers()
+            .iter()
+            .filter(|(_, on)| *on)
+            .map(|(name, _)| *name)
+            .collect();
+        if blocked.is_empty() {
+            write!(f, "no blocking")
+        } else {
+            write!(f, "blocks {}", blocked.join(", "))
+        }
+    }
+}
+
+/// Parses the output of `mullvad dns get`:
+///
+/// ```text
+/// Custom DNS: no
+/// Block ads: true
+/// Block trackers: false
+/// ```
+///
+/// With custom DNS the servers follow on their own lines.
+pub fn parse_dns(output: &str) -> DnsSettings {
+    let mut dns = DnsSettings::default();
+    let mut custom = false;
+    for line in output.lines().map(str::trim) {
+        // Checked first since IPv6 servers contain colons too.
+        if custom && line.parse::<IpAddr>().is_ok() {
+            dns.custom.push(line.to_string());
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let on = matches!(value.trim(), "true" | "yes" | "on");
+        match key.trim().to_lowercase().as_str() {
+            "custom dns" => custom = on,
+            "block ads" => dns.block_ads = on,
+            "block trackers" => dns.block_trackers = on,
+            "block malware" => dns.block_malware = on,
+            "block adult content" => dns.block_adult_content = on,
+            "block gambling" => dns.block_gambling = on,
+            "block social media" => dns.block_social_media = on,
+            _ => {}
+        }
+    }
+    dns
+}
+
+/// Parses on/off settings such as `Autoconnect: on` or
+/// `Block traffic when the VPN is disconnected: off`.
+pub fn parse_toggle(output: &str) -> bool {
+    let value = output.trim().rsplit(':').next().unwrap_or_default().trim();
+    matches!(value, "on" | "true" | "yes")
+}
+
+/// The tunnel protocol from `mullvad relay get`, lowercase.
+pub fn parse_tunnel_protocol(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case("tunnel protocol")
+            .then(|| value.trim().to_lowercase())
+    })
+}
+
+/// Settings of the daemon; `None` leaves one as it is.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DaemonSettings {
+    /// `wireguard`, `openvpn` or `any`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tunnel_protocol: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns: Option<DnsSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lockdown: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_connect: Option<bool>,
+    /// `auto`, `on` or `off`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bridge_mode: Option<String>,
+    /// `auto`, `off`, `udp2tcp` or `shadowsocks`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub obfuscation_mode: Option<String>,
+}
+
+impl DaemonSettings {
+    /// Reads every setting from the daemon.
+    pub fn fetch(backend: &dyn Backend) -> Result<Self, AppError> {
+        Ok(Self {
+            tunnel_protocol: Some(
+                backend
+                    .tunnel_protocol()?
+                    .unwrap_or_else(|| "any".to_string()),
+            ),
+            dns: Some(backend.dns()?),
+            lockdown: Some(backend.lockdown()?),
+            auto_connect: Some(backend.auto_connect()?),
+            bridge_mode: Some(backend.bridge()?.mode),
+            obfuscation_mode: Some(backend.obfuscation()?.mode),
+        })
+    }
+
+    /// The settings that differ from `current`.
+    pub fn changes(&self, current: &Self) -> Vec<Change> {
+        let on_off = |on: Option<bool>| match on {
+            Some(true) => "on".to_string(),
+            Some(false) => "off".to_string(),
+            None => "unknown".to_string(),
+        };
+        let text = |value: &Option<String>| value.clone().unwrap_or_else(|| "unknown".to_string());
+        let mut changes = Vec::new();
+        if let Some(protocol) = &self.tunnel_protocol {
+            changes.push(Change::new(
+                "Tunnel protocol",
+                text(&current.tunnel_protocol),
+                protocol.to_lowercase(),
+            ));
+        }
+        if let Some(dns) = &self.dns {
+            let from = current
+                .dns
+                .as_ref()
+                .map_or("unknown".to_string(), ToString::to_string);
+            changes.push(Change::new("DNS", from, dns.to_string()));
+        }
+        if self.lockdown.is_some() {
+            changes.push(Change::new(
+                "Lockdown mode",
+                on_off(current.lockdown),
+                on_off(self.lockdown),
+            ));
+        }
+        if self.auto_connect.is_some() {
+            changes.push(Change::new(
+                "Auto-connect",
+                on_off(current.auto_connect),
+                on_off(self.auto_connect),
+            ));
+        }
+        if let Some(mode) = &self.bridge_mode {
+            changes.push(Change::new(
+                "Bridge mode",
+                text(&current.bridge_mode),
+                mode.clone(),
+            ));
+        }
+        if let Some(mode) = &self.obfuscation_mode {
+            changes.push(Change::new(
+                "Obfuscation",
+                text(&current.obfuscation_mode),
+                mode.clone(),
+            ));
+        }
+        changes.retain(|c| c.from != c.to);
+        changes
+    }
+
+    /// Only the settings that differ from `current`.
+    pub fn without_unchanged(&self, current: &Self) -> Self {
+        fn keep<T: Clone + PartialEq>(new: &Option<T>, current: &Option<T>) -> Option<T> {
+            new.clone().filter(|_| new != current)
+        }
+        Self {
+            tunnel_protocol: keep(&self.tunnel_protocol, &current.tunnel_protocol),
+            dns: keep(&self.dns, &current.dns),
+            lockdown: keep(&self.lockdown, &current.lockdown),
+            auto_connect: keep(&self.auto_connect, &current.auto_connect),
+            bridge_mode: keep(&self.bridge_mode, &current.bridge_mode),
+            obfuscation_mode: keep(&self.obfuscation_mode, &current.obfuscation_mode),
+        }
+    }
+
+    pub fn apply(&self, backend: &dyn Backend) -> Result<(), AppError> {
+        if let Some(protocol) = &self.tunnel_protocol {
+            backend.set_tunnel_protocol(&protocol.to_lowercase())?;
+        }
+        if let Some(dns) = &self.dns {
+            backend.set_dns(dns)?;
+        }
+        if let Some(lockdown) = self.lockdown {
+            backend.set_lockdown(lockdown)?;
+        }
+        if let Some(auto_connect) = self.auto_connect {
+            backend.set_auto_connect(auto_connect)?;
+        }
+        if let Some(mode) = &self.bridge_mode {
+            backend.set_bridge_state(mode)?;
+        }
+        if let Some(mode) = &self.obfuscation_mode {
+            backend.set_obfuscation_mode(mode)?;
+        }
+        Ok(())
+    }
+}
+
+/// A named bundle of settings, applied in one go. Anything left out stays
+/// as it is.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// Where to connect, as typed on the command line: `se got` or `fav:work`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<RelayFilter>,
+    #[serde(flatten)]
+    pub daemon: DaemonSettings,
+}
+
+impl Profile {
+    pub fn location(
+        &self,
+        countries: &[Country],
+        favorites: &BTreeMap<String, String>,
+    ) -> Result<Option<Location>, AppError> {
+        let Some(location) = &self.location else {
+            return Ok(None);
+        };
+        let args: Vec<&str> = location.split_whitespace().collect();
+        query::resolve(&args, countries, favorites).map(Some)
+    }
+
+    /// What applying the profile would change, given the current settings.
+    pub fn changes(&self, current: &Settings, location: Option<&Location>) -> Vec<Change> {
+        let mut changes = Vec::new();
+        if let Some(location) = location {
+            let from = current
+                .location
+                .as_ref()
+                .map_or("not connected".to_string(), ToString::to_string);
+            let to = match &current.location {
+                Some(current) if is_within(current, location) => current.to_string(),
+                _ => location.to_string(),
+            };
+            changes.push(Change::new("Location", from, to));
+        }
+        if let (Some(filter), Some(current)) = (&self.filter, &current.filter) {
+            changes.push(Change::new(
+                "Filter",
+                current.to_string(),
+                filter.to_string(),
+            ));
+        }
+        changes.retain(|c| c.from != c.to);
+        changes.extend(self.daemon.changes(&current.daemon));
+        changes
+    }
+}
+
+/// Whether the relay `current` is already in `location`. Applying a profile
+/// then keeps it rather than reconnecting to another relay there.
+pub fn is_within(current: &Location, location: &Location) -> bool {
+    current.args().starts_with(&location.args())
+}
+
+/// The settings a profile can change, as they are now.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Settings {
+    /// The relay the tunnel uses.
+    pub location: Option<Location>,
+    /// The app's filter; unknown outside the TUI.
+    pub filter: Option<RelayFilter>,
+    pub daemon: DaemonSettings,
+}
+
+impl Settings {
+    pub fn fetch(
+        backend: &dyn Backend,
+        countries: &[Country],
+        filter: Option<&RelayFilter>,
+    ) -> Result<Self, AppError> {
+        let status = backend.status()?;
+        Ok(Self {
+            location: status
+                .relay
+                .as_deref()
+                .and_then(|h| query::find_relay(h, countries)),
+            filter: filter.cloned(),
+            daemon: DaemonSettings::fetch(backend)?,
+        })
+    }
+}
+
+/// One line of a preview: a setting with its current and new value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub setting: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl Change {
+    pub fn new(setting: impl Into<String>, from: String, to: String) -> Self {
+        Self {
+            setting: setting.into(),
+            from,
+            to,
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} -> {}", self.setting, self.from, self.to)
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    action::Action,
+    data::{Country, Location},
+    error::AppError,
+    filter::RelayFilter,
+    random::Scope,
+    rotation::Plan,
+};
+
+const COMMANDS: [&str; 8] = [
+    "connect",
+    "disconnect",
+    "reconnect",
+    "random",
+    "filter",
+    "rotate",
+    "check",
+    "set",
+];
+const PROTOCOLS: [&str; 3] = ["wireguard", "openvpn", "any"];
+const SCOPES: [&str; 3] = ["city", "country", "favorite"];
+const ROTATE: [&str; 3] = ["stop", "pause", "resume"];
+const POOLS: [&str; 3] = ["country", "list", "favorites"];
+const FILTERS: [&str; 5] = ["wireguard", "openvpn", "owned", "rented", "provider"];
+
+/// Favorites are written as `fav:<name>`.
+const FAVORITE: &str = "fav:";
+
+/// Parses a `:` command line into an action:
+///
+/// - `connect se`, `connect se mma`, `connect se got se-got-wg-001`
+/// - `connect se-got-wg-001`, found in the relay list
+/// - `connect fav:work`, a location from `[favorites]`
+/// - `disconnect`, `reconnect`
+/// - `random city|country|favorite`
+/// - `filter [wireguard|openvpn] [owned|rented] [provider <name>]...`, no
+///   arguments to clear it
+/// - `rotate <minutes> [random|round-robin] country <code> | list <name> | favorites`,
+///   `rotate stop|pause|resume`
+/// - `check`, the leak check
+/// - `set protocol wireguard|openvpn|any`
+pub fn parse(
+    input: &str,
+    countries: &[Country],
+    favorites: &BTreeMap<String, String>,
+) -> Result<Action, AppError> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    match words.as_slice() {
+        ["connect", args @ ..] => resolve(args, countries, favorites).map(Action::Connect),
+        ["disconnect"] => Ok(Action::Disconnect),
+        ["reconnect"] => Ok(Action::Reconnect),
+        ["random", "city"] => Ok(Action::Random(Scope::City)),
+        ["random", "country"] => Ok(Action::Random(Scope::Country)),
+        ["random", "favorite"] => Ok(Action::Random(Scope::Favorite)),
+        ["random", ..] => Err(AppError::Parse(format!(
+            "Usage: random {}",
+            SCOPES.join("|")
+        ))),
+        ["filter", args @ ..] => RelayFilter::parse(args).map(Action::SetFilter),
+        ["check"] => Ok(Action::CheckLeaks),
+        ["rotate", "stop"] => Ok(Action::StopRotation),
+        ["rotate", "pause"] => Ok(Action::PauseRotation(true)),
+        ["rotate", "resume"] => Ok(Action::PauseRotation(false)),
+        ["rotate", args @ ..] => Plan::parse(args).map(Action::Rotate),
+        ["set", "protocol", protocol] if PROTOCOLS.contains(protocol) => {
+            Ok(Action::SetProtocol(protocol.to_string()))
+        }
+        ["set", "protocol", ..] => Err(AppError::Parse(format!(
+            "Usage: set protocol {}",
+            PROTOCOLS.join("|")
+        ))),
+        [] => Err(AppError::Parse("Empty command".to_string())),
+        [command, ..] => Err(AppError::Parse(format!("Unknown command: {}", command))),
+    }
+}
+
+/// Turns the arguments of `connect` into a location that exists in the relay list.
+pub fn resolve(
+    args: &[&str],
+    countries: &[Country],
+    favorites: &BTreeMap<String, String>,
+) -> Result<Location, AppError> {
+    let unknown = || AppError::Parse(format!("Unknown location: {}", args.join(" ")));
+
+    if let [arg] = args {
+        if let Some(name) = arg.strip_prefix(FAVORITE) {
+            let location = favorites
+                .get(name)
+                .ok_or_else(|| AppError::Parse(format!("Unknown favorite: {}", name)))?;
+            let args: Vec<&str> = location.split_whitespace().collect();
+            return resolve(&args, countries, &BTreeMap::new());
+        }
+        if arg.contains('-') {
+            return find_relay(arg, countries).ok_or_else(unknown);
+        }
+    }
+
+    let location = Location::from_args(args).ok_or_else(|| {
+        AppError::Parse(
+            "Usage: connect <country> [city] [hostname] | <hostname> | fav:<name>".to_string(),
+        )
+    })?;
+    let country = countries
+        .iter()
+        .find(|c| c.code == args[0])
+        .ok_or_else(unknown)?;
+    if let Some(city) = args.get(1) {
+        let city = country
+            .cities
+            .iter()
+            .find(|c| c.code == *city)
+            .ok_or_else(unknown)?;
+        if let Some(hostname) = args.get(2) {
+            city.relays
+                .iter()
+                .find(|r| r.hostname == *hostname)
+                .ok_or_else(unknown)?;
+        }
+    }
+    Ok(location)
+}
+
+/// The full location of a relay given only its hostname.
+pub fn find_relay(hostname: &str, countries: &[Country]) -> Option<Location> {
+    countries.iter().find_map(|country| {
+        country.cities.iter().find_map(|city| {
+            city.relays
+                .iter()
+                .find(|r| r.hostname == hostname)
+                .map(|r| {
+                    Location::Relay(country.code.clone(), city.code.clone(), r.hostname.clone())
+                })
+        })
+    })
+}
+
+/// Candidates for the word being typed at the end of `input`, as whole
+/// command lines.
+pub fn complete(
+    input: &str,
+    countries: &[Country],
+    favorites: &BTreeMap<String, String>,
+) -> Vec<String> {
+    let (head, word) = match input.rfind(' ') {
+        Some(i) => (&input[..=i], &input[i + 1..]),
+        None => ("", input),
+    };
+    let done: Vec<&str> = head.split_whitespace().collect();
+
+    let options: Vec<String> = match done.as_slice() {
+        [] => COMMANDS.iter().map(|c| c.to_string()).collect(),
+        ["connect"] if word.starts_with(FAVORITE) => favorites
+            .keys()
+            .map(|name| format!("{}{}", FAVORITE, name))
+            .collect(),
+        ["connect"] if word.contains('-') => countries
+            .iter()
+            .flat_map(|c| c.relays())
+            .map(|r| r.hostname.clone())
+            .collect(),
+        ["connect"] => countries.iter().map(|c| c.code.clone()).collect(),
+        ["connect", country] => countries
+            .iter()
+            .filter(|c| c.code == *country)
+            .flat_map(|c| c.cities.iter().map(|city| city.code.clone()))
+            .collect(),
+        ["connect", country, city] => countries
+            .iter()
+            .filter(|c| c.code == *country)
+            .flat_map(|c| c.cities.iter().filter(|ci| ci.code == *city))
+            .flat_map(|city| city.relays.iter().map(|r| r.hostname.clone()))
+            .collect(),
+        ["random"] => SCOPES.iter().map(|s| s.to_string()).collect(),
+        ["filter", .., "provider"] => countries
+            .iter()
+            .flat_map(|c| c.relays())
+            .map(|r| r.provider.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect(),
+        ["filter", ..] => FILTERS.iter().map(|f| f.to_string()).collect(),
+        ["rotate"] => ROTATE.iter().map(|r| r.to_string()).collect(),
+        ["rotate", _] => ["random", "round-robin"]
+            .iter()
+            .chain(&POOLS)
+            .map(|p| p.to_string())
+            .collect(),
+        ["rotate", _, "random" | "round-robin"] => POOLS.iter().map(|p| p.to_string()).collect(),
+        ["rotate", .., "country"] => countries.iter().map(|c| c.code.clone()).collect(),
+        ["set"] => vec!["protocol".to_string()],
+        ["set", "protocol"] => PROTOCOLS.iter().map(|p| p.to_string()).collect(),
+        _ => Vec::new(),
+    };
+
+    options
+        .into_iter()
+        .filter(|option| option.starts_with(word))
+        .map(|option| format!("{}{}", head, option))
+        .collect()
+}
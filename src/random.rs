@@ -0,0 +1,133 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{
+    data::{Country, Location},
+    error::AppError,
+    filter::RelayFilter,
+    query,
+};
+
+/// Where a random relay is picked from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Another relay in the city of the current one.
+    City,
+    /// Another relay in the country of the current one.
+    Country,
+    /// A relay in one of the favorites, preferring another country.
+    Favorite,
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scope::City => write!(f, "city"),
+            Scope::Country => write!(f, "country"),
+            Scope::Favorite => write!(f, "favorite"),
+        }
+    }
+}
+
+/// Picks a relay other than `current` that matches the filter.
+pub fn pick(
+    scope: Scope,
+    current: Option<&Location>,
+    countries: &[Country],
+    favorites: &BTreeMap<String, String>,
+    filter: &RelayFilter,
+    rng: &mut fastrand::Rng,
+) -> Result<Location, AppError> {
+    let current_hostname = match current {
+        Some(Location::Relay(_, _, hostname)) => Some(hostname.as_str()),
+        _ => None,
+    };
+    let candidates = |location: &Location| -> Vec<Location> {
+        relays_in(location, countries, filter)
+            .into_iter()
+            .filter(|relay| relay.hostname() != current_hostname)
+            .collect()
+    };
+
+    let candidates = match (scope, current) {
+        (Scope::City, Some(Location::Relay(country, city, _))) => {
+            candidates(&Location::City(country.clone(), city.clone()))
+        }
+        (Scope::Country, Some(Location::Relay(country, _, _))) => {
+            candidates(&Location::Country(country.clone()))
+        }
+        (Scope::City | Scope::Country, _) => {
+            return Err(AppError::Parse("Not connected to a relay".to_string()))
+        }
+        (Scope::Favorite, _) => {
+            if favorites.is_empty() {
+                return Err(AppError::Parse("No favorites configured".to_string()));
+            }
+            let mut locations = Vec::new();
+            for location in favorites.values() {
+                let args: Vec<&str> = location.split_whitespace().collect();
+                locations.push(query::resolve(&args, countries, &BTreeMap::new())?);
+            }
+            let options: Vec<Vec<Location>> = locations
+                .iter()
+                .map(candidates)
+                .filter(|relays| !relays.is_empty())
+                .collect();
+            // Another country if any favorite is in one, the current one otherwise.
+            let current_country = current.map(Location::country_code);
+            let elsewhere: Vec<&Vec<Location>> = options
+                .iter()
+                .filter(|relays| Some(relays[0].country_code()) != current_country)
+                .collect();
+            let options = if elsewhere.is_empty() {
+                options.iter().collect()
+            } else {
+                elsewhere
+            };
+            match options.get(rng.usize(..options.len().max(1))) {
+                Some(relays) => relays.to_vec(),
+                None => Vec::new(),
+            }
+        }
+    };
+
+    if candidates.is_empty() {
+        return Err(AppError::Parse(format!(
+            "No other relay in this {} matches the filter ({})",
+            scope, filter
+        )));
+    }
+    Ok(candidates[rng.usize(..candidates.len())].clone())
+}
+
+/// Every relay in the country, city or relay that matches the filter.
+pub fn relays_in(
+    location: &Location,
+    countries: &[Country],
+    filter: &RelayFilter,
+) -> Vec<Location> {
+    let mut relays = Vec::new();
+    for country in countries
+        .iter()
+        .filter(|c| c.code == location.country_code())
+    {
+        for city in &country.cities {
+            if location.city_code().is_some_and(|code| code != city.code) {
+                continue;
+            }
+            for relay in city.relays.iter().filter(|r| filter.matches(r)) {
+                if location
+                    .hostname()
+                    .is_some_and(|hostname| hostname != relay.hostname)
+                {
+                    continue;
+                }
+                relays.push(Location::Relay(
+                    country.code.clone(),
+                    city.code.clone(),
+                    relay.hostname.clone(),
+                ));
+            }
+        }
+    }
+    relays
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Debug, Display},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    data::{Country, CustomList, Location},
+    error::AppError,
+    filter::RelayFilter,
+    query, random,
+};
+
+/// Where the time comes from, so the schedule can be tested without waiting.
+pub trait Clock: Debug + Send {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// The relays a rotation draws from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pool {
+    Country(String),
+    CustomList(String),
+    Favorites,
+}
+
+impl Display for Pool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pool::Country(code) => write!(f, "country {}", code),
+            Pool::CustomList(name) => write!(f, "list {}", name),
+            Pool::Favorites => write!(f, "favorites"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Random,
+    /// Every relay of the pool in turn, in relay list order.
+    RoundRobin,
+}
+
+/// What `:rotate` asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub pool: Pool,
+    pub order: Order,
+    pub interval: Duration,
+}
+
+impl Plan {
+    /// Parses the arguments of `:rotate`, e.g. `10 round-robin list work`.
+    pub fn parse(args: &[&str]) -> Result<Self, AppError> {
+        let usage = || {
+            AppError::Parse(
+                "Usage: rotate <minutes> [random|round-robin] country <code> | list <name> | favorites".to_string(),
+            )
+        };
+        let (minutes, rest) = args.split_first().ok_or_else(usage)?;
+        let minutes: u64 = minutes.parse().ok().filter(|m| *m > 0).ok_or_else(usage)?;
+        let (order, rest) = match rest {
+            ["random", rest @ ..] => (Order::Random, rest),
+            ["round-robin", rest @ ..] => (Order::RoundRobin, rest),
+            rest => (Order::Random, rest),
+        };
+        let pool = match rest {
+            ["country", code] => Pool::Country(code.to_string()),
+            ["list", name @ ..] if !name.is_empty() => Pool::CustomList(name.join(" ")),
+            ["favorites"] => Pool::Favorites,
+            _ => return Err(usage()),
+        };
+        Ok(Self {
+            pool,
+            order,
+            interval: Duration::from_secs(minutes * 60),
+        })
+    }
+}
+
+/// A running schedule of relay changes.
+#[derive(Debug)]
+pub struct Rotation {
+    pub plan: Plan,
+    /// When the next rotation is due, `None` while paused.
+    next_at: Option<Instant>,
+    /// Time left when paused, so resuming picks up where it stopped.
+    left: Duration,
+    /// Position in the pool for round-robin.
+    position: usize,
+}
+
+impl Rotation {
+    pub fn new(plan: Plan, now: Instant) -> Self {
+        Self {
+            next_at: Some(now + plan.interval),
+            left: plan.interval,
+            plan,
+            position: 0,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.next_at.is_none()
+    }
+
+    /// Time until the next rotation.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        match self.next_at {
+            Some(at) => at.saturating_duration_since(now),
+            None => self.left,
+        }
+    }
+
+    pub fn pause(&mut self, now: Instant) {
+        if let Some(at) = self.next_at.take() {
+            self.left = at.saturating_duration_since(now);
+        }
+    }
+
+    pub fn resume(&mut self, now: Instant) {
+        if self.next_at.is_none() {
+            self.next_at = Some(now + self.left);
+        }
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.next_at.is_some_and(|at| now >= at)
+    }
+
+    /// The relay to rotate to, which also schedules the one after it.
+    pub fn next(
+        &mut self,
+        now: Instant,
+        current: Option<&str>,
+        pool: &[Location],
+        rng: &mut fastrand::Rng,
+    ) -> Result<Location, AppError> {
+        self.next_at = Some(now + self.plan.interval);
+        let candidates: Vec<&Location> = pool.iter().filter(|l| l.hostname() != current).collect();
+        if candidates.is_empty() {
+            return Err(AppError::Parse(format!(
+                "No other relay to rotate to in {}",
+                self.plan.pool
+            )));
+        }
+        let location = match self.plan.order {
+            Order::Random => candidates[rng.usize(..candidates.len())],
+            Order::RoundRobin => {
+                // Skip the current relay without losing the place in the pool.
+                let location = pool
+                    .iter()
+                    .cycle()
+                    .skip(self.position % pool.len())
+                    .find(|l| l.hostname() != current)
+                    .unwrap_or(candidates[0]);
+                let index = pool.iter().position(|l| l == location).unwrap_or(0);
+                self.position = index + 1;
+                location
+            }
+        };
+        Ok(location.clone())
+    }
+}
+
+/// Every relay of the pool matching the filter, in relay list order.
+pub fn pool_relays(
+    pool: &Pool,
+    countries: &[Country],
+    custom_lists: &[CustomList],
+    favorites: &BTreeMap<String, String>,
+    filter: &RelayFilter,
+) -> Result<Vec<Location>, AppError> {
+    let locations: Vec<Location> = match pool {
+        Pool::Country(code) => vec![query::resolve(&[code], countries, &BTreeMap::new())?],
+        Pool::CustomList(name) => custom_lists
+            .iter()
+            .find(|list| list.name == *name)
+            .ok_or_else(|| AppError::Parse(format!("Unknown custom list: {}", name)))?
+            .locations
+            .iter()
+            .filter_map(|entry| entry.location.clone())
+            .collect(),
+        Pool::Favorites => favorites
+            .values()
+            .map(|location| {
+                let args: Vec<&str> = location.split_whitespace().collect();
+                query::resolve(&args, countries, &BTreeMap::new())
+            })
+            .collect::<Result<_, _>>()?,
+    };
+    let mut relays = Vec::new();
+    for location in &locations {
+        for relay in random::relays_in(location, countries, filter) {
+            if !relays.contains(&relay) {
+                relays.push(relay);
+            }
+        }
+    }
+    Ok(relays)
+}
+
+/// `04:59`
+pub fn format_countdown(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    backtrace::Backtrace,
+    fs,
+    io::{self, stdout},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crossterm::{
+    event::{DisableMouseCapture, EnableMouseCapture},
+    execute,
+    terminal::{self, EnterAlternateScreen},
+};
+use ratatui::DefaultTerminal;
+use signal_hook::consts::{SIGHUP, SIGSTOP, SIGTERM, SIGTSTP};
+
+use crate::logging;
+
+/// Enters the alternate screen and makes sure a panic leaves the terminal usable.
+pub fn init() -> io::Result<DefaultTerminal> {
+    let terminal = ratatui::init();
+    execute!(stdout(), EnableMouseCapture)?;
+
+    let previous = std::panic::take_hook();
+    let main = std::thread::current().id();
+    std::panic::set_hook(Box::new(move |info| {
+        // The UI keeps drawing when a worker thread dies, so leave the
+        // terminal to it and only log the panic.
+        if std::thread::current().id() != main {
+            logging::error("app", info.to_string());
+            return;
+        }
+        restore();
+        logging::error("app", info.to_string());
+        match crash_dir().map(|dir| write_crash_report(&dir, &info.to_string())) {
+            Some(Ok(path)) => {
+                eprintln!("mullvad-tui crashed, report written to {}", path.display())
+            }
+            Some(Err(e)) => eprintln!("mullvad-tui crashed, failed to write a report: {}", e),
+            None => {}
+        }
+        previous(info);
+    }));
+    Ok(terminal)
+}
+
+pub fn restore() {
+    let _ = execute!(stdout(), DisableMouseCapture);
+    ratatui::restore();
+}
+
+/// Gives the terminal back to the shell, stops the process until it is
+/// continued (`fg`) and then takes the terminal over again.
+pub fn suspend(terminal: &mut DefaultTerminal) -> io::Result<()> {
+    restore();
+    // SIGTSTP is handled by `Signals`, SIGSTOP always stops.
+    signal_hook::low_level::raise(SIGSTOP)?;
+
+    terminal::enable_raw_mode()?;
+    execute!(stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+    terminal.clear()
+}
+
+/// Signals the UI loop reacts to between frames.
+#[derive(Debug, Default)]
+pub struct Signals {
+    terminate: Arc<AtomicBool>,
+    suspend: Arc<AtomicBool>,
+}
+
+impl Signals {
+    /// Turns SIGTERM and SIGHUP into a clean exit and SIGTSTP into a suspend.
+    pub fn register() -> io::Result<Self> {
+        let signals = Self::default();
+        for signal in [SIGTERM, SIGHUP] {
+            signal_hook::flag::register(signal, signals.terminate.clone())?;
+        }
+        signal_hook::flag::register(SIGTSTP, signals.suspend.clone())?;
+        Ok(signals)
+    }
+
+    pub fn terminated(&self) -> bool {
+        self.terminate.load(Ordering::Relaxed)
+    }
+
+    /// Whether a suspend was requested since the last call.
+    pub fn take_suspend(&self) -> bool {
+        self.suspend.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Crash reports go next to the log file.
+fn crash_dir() -> Option<PathBuf> {
+    Some(logging::default_path()?.parent()?.to_path_buf())
+}
+
+/// Writes `crash-<unix time>.txt` with the panic message and a backtrace.
+pub fn write_crash_report(dir: &Path, panic: &str) -> io::Result<PathBuf> {
+    let now = SystemTime::now();
+    let secs = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let path = dir.join(format!("crash-{}.txt", secs));
+
+    let report = format!(
+        "mullvad-tui {} crashed at {}\n\n{}\n\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        logging::format_time(now),
+        panic,
+        Backtrace::force_capture()
+    );
+    fs::create_dir_all(dir)?;
+    fs::write(&path, report)?;
+    Ok(path)
+}
@@ -0,0 +1,210 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+
+use crate::{
+    app::{App, View},
+    config::Config,
+};
+
+use super::fake::FakeBackend;
+
+fn app(backend: FakeBackend) -> (App, Arc<FakeBackend>, tempfile::TempDir, PathBuf) {
+    app_with_config(Config::default(), backend)
+}
+
+fn app_with_config(
+    config: Config,
+    backend: FakeBackend,
+) -> (App, Arc<FakeBackend>, tempfile::TempDir, PathBuf) {
+    let backend = Arc::new(backend);
+    let mut app = App::new(config, backend.clone()).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("mullvad-tui.sock");
+    app.listen(&path).unwrap();
+    (app, backend, dir, path)
+}
+
+struct Client {
+    writer: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl Client {
+    fn connect(path: &Path) -> Self {
+        let writer = UnixStream::connect(path).unwrap();
+        writer
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let reader = BufReader::new(writer.try_clone().unwrap());
+        Self { writer, reader }
+    }
+
+    fn send(&mut self, request: &str) {
+        writeln!(self.writer, "{}", request).unwrap();
+    }
+
+    fn read(&mut self) -> Value {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+}
+
+/// Runs `client` on another thread while pumping the app's event loop.
+fn with_client<T: Send + 'static>(app: &mut App, client: impl FnOnce() -> T + Send + 'static) -> T {
+    let handle = thread::spawn(client);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !handle.is_finished() && Instant::now() < deadline {
+        app.handle_worker_events();
+        thread::sleep(Duration::from_millis(5));
+    }
+    handle.join().unwrap()
+}
+
+#[test]
+fn connect_over_socket() {
+    let (mut app, backend, _dir, path) = app(FakeBackend::default());
+    let response = with_client(&mut app, move || {
+        let mut client = Client::connect(&path);
+        client.send(r#"{"cmd": "connect", "location": ["se", "got"]}"#);
+        client.read()
+    });
+
+    assert_eq!(response["ok"], true);
+    assert_eq!(response["status"]["relay"], "se-got-wg-001");
+    assert_eq!(
+        backend.calls(),
+        vec!["relay set location se got", "connect"]
+    );
+    assert_eq!(*app.view_mode(), View::Connection);
+}
+
+#[test]
+fn connect_to_favorite_and_hostname_over_socket() {
+    let config = Config {
+        favorites: [("work".to_string(), "ch zrh".to_string())].into(),
+        ..Config::default()
+    };
+    let (mut app, backend, _dir, path) = app_with_config(config, FakeBackend::default());
+    let responses = with_client(&mut app, move || {
+        let mut client = Client::connect(&path);
+        client.send(r#"{"cmd": "connect", "location": ["fav:work"]}"#);
+        let favorite = client.read();
+        client.send(r#"{"cmd": "connect", "location": ["se-mma-wg-001"]}"#);
+        let hostname = client.read();
+        client.send(r#"{"cmd": "connect", "location": ["fav:home"]}"#);
+        (favorite, hostname, client.read())
+    });
+
+    assert_eq!(responses.0["ok"], true);
+    assert_eq!(responses.1["ok"], true);
+    assert_eq!(responses.2["error"], "Unknown favorite: home");
+    assert_eq!(
+        backend.calls(),
+        vec![
+            "relay set location ch zrh",
+            "connect",
+            "relay set location se mma se-mma-wg-001",
+            "connect",
+        ]
+    );
+}
+
+#[test]
+fn navigate_over_socket() {
+    let (mut app, backend, _dir, path) = app(FakeBackend::default());
+    let responses = with_client(&mut app, move || {
+        let mut client = Client::connect(&path);
+        client.send(r#"{"cmd": "navigate", "location": ["se", "mma"]}"#);
+        let found = client.read();
+        client.send(r#"{"cmd": "navigate", "location": ["se", "xyz"]}"#);
+        let missing = client.read();
+        client.send("not json");
+        (found, missing, client.read())
+    });
+
+    assert_eq!(responses.0["ok"], true);
+    assert_eq!(responses.1["ok"], false);
+    assert_eq!(responses.1["error"], "Unknown location: se xyz");
+    assert_eq!(responses.2["ok"], false);
+    assert!(backend.calls().is_empty());
+    assert_eq!(*app.view_mode(), View::Cities);
+}
+
+#[test]
+fn subscribe_to_state_changes() {
+    let (mut app, _, _dir, path) = app(FakeBackend::connected());
+    let (subscribed, event) = with_client(&mut app, move || {
+        let mut subscriber = Client::connect(&path);
+        subscriber.send(r#"{"cmd": "subscribe"}"#);
+        let subscribed = subscriber.read();
+
+        let mut client = Client::connect(&path);
+        client.send(r#"{"cmd": "disconnect"}"#);
+        client.read();
+        (subscribed, subscriber.read())
+    });
+
+    assert_eq!(subscribed["status"]["state"], "Connected");
+    assert_eq!(event["event"], "status");
+    assert_eq!(event["state"], "Disconnected");
+}
+
+#[test]
+fn subscriber_keeps_sending_requests() {
+    let (mut app, _, _dir, path) = app(FakeBackend::connected());
+    let lines = with_client(&mut app, move || {
+        let mut client = Client::connect(&path);
+        client.send(r#"{"cmd": "subscribe"}"#);
+        client.read();
+        client.send(r#"{"cmd": "disconnect"}"#);
+        [client.read(), client.read()]
+    });
+
+    let response = lines.iter().find(|l| l.get("ok").is_some()).unwrap();
+    assert_eq!(response["ok"], true);
+    let event = lines.iter().find(|l| l.get("event").is_some()).unwrap();
+    assert_eq!(event["state"], "Disconnected");
+}
+
+#[test]
+fn socket_is_private() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (_app, _, _dir, path) = app(FakeBackend::default());
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    // The private directory it was bound in is gone.
+    let entries: Vec<_> = std::fs::read_dir(path.parent().unwrap())
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(entries, vec!["mullvad-tui.sock"]);
+}
+
+#[test]
+fn socket_file_is_removed_on_exit() {
+    let (app, _, _dir, path) = app(FakeBackend::default());
+    assert!(path.exists());
+    drop(app);
+    assert!(!path.exists());
+}
+
+#[test]
+fn does_not_replace_other_files() {
+    let mut app = App::new(Config::default(), Arc::new(FakeBackend::default())).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, "keep me").unwrap();
+    let error = app.listen(&path).unwrap_err();
+    assert!(error.to_string().contains("is not a socket"), "{}", error);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+}
@@ -0,0 +1,383 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    api_access::{self, AccessMethod, Socks5},
+    backend::Backend,
+    bridge::{self, BridgeSettings, ObfuscationMethod, ObfuscationSettings},
+    data::{self, Country, CustomList, Location, TunnelStatus},
+    error::AppError,
+    notify::{Notification, Notifier},
+    profile::DnsSettings,
+    rotation::Clock,
+};
+
+use super::RELAY_LIST;
+
+pub(crate) const CONNECTED: &str = "\
+Connected to se-got-wg-001 in Gothenburg, Sweden
+Relay:                  se-got-wg-001 (185.213.154.66:51820/UDP)
+Tunnel type:            WireGuard
+Visible location:       Sweden, Gothenburg. IPv4: 185.213.154.68
+";
+
+/// An in-memory daemon that records every command it receives.
+#[derive(Debug)]
+pub(crate) struct FakeBackend {
+    pub relay_list: String,
+    pub status: Mutex<String>,
+    pub custom_lists: Mutex<String>,
+    pub bridge: Mutex<BridgeSettings>,
+    pub obfuscation: Mutex<ObfuscationSettings>,
+    pub access_methods: Mutex<Vec<AccessMethod>>,
+    pub access_method_in_use: Mutex<Option<String>>,
+    pub tunnel_protocol: Mutex<Option<String>>,
+    pub dns: Mutex<DnsSettings>,
+    pub lockdown: Mutex<bool>,
+    pub auto_connect: Mutex<bool>,
+    /// Commands that fail, matched against the recorded command line.
+    pub failing: Vec<String>,
+    pub calls: Mutex<Vec<String>>,
+}
+
+impl Default for FakeBackend {
+    fn default() -> Self {
+        Self {
+            relay_list: RELAY_LIST.to_string(),
+            status: Mutex::new("Disconnected\n".to_string()),
+            custom_lists: Mutex::new(String::new()),
+            bridge: Mutex::new(bridge::parse_bridge("Bridge mode: auto\nBridge constraints: any\n")),
+            obfuscation: Mutex::new(bridge::parse_obfuscation(
+                "Obfuscation mode: auto\nudp2tcp settings: any port\nShadowsocks settings: any port\n",
+            )),
+            access_methods: Mutex::new(api_access::parse_list(
+                "1. Direct\n2. Mullvad Bridges\n3. Encrypted DNS proxy (disabled)\n",
+            )),
+            access_method_in_use: Mutex::new(Some("Direct".to_string())),
+            tunnel_protocol: Mutex::new(Some("any".to_string())),
+            dns: Mutex::new(DnsSettings::default()),
+            lockdown: Mutex::new(false),
+            auto_connect: Mutex::new(false),
+            failing: Vec::new(),
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl FakeBackend {
+    pub fn connected() -> Self {
+        let backend = Self::default();
+        *backend.status.lock().unwrap() = CONNECTED.to_string();
+        backend
+    }
+
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn record(&self, call: String) -> Result<(), AppError> {
+        self.calls.lock().unwrap().push(call.clone());
+        if self.failing.iter().any(|f| call.starts_with(f.as_str())) {
+            return Err(AppError::Command(format!("mullvad {}: failed", call)));
+        }
+        Ok(())
+    }
+}
+
+impl Backend for FakeBackend {
+    fn relay_list(&self) -> Result<Vec<Country>, AppError> {
+        Ok(data::parse_relay_list(&self.relay_list))
+    }
+
+    fn update_relays(&self) -> Result<(), AppError> {
+        self.record("relay update".to_string())
+    }
+
+    fn status(&self) -> Result<TunnelStatus, AppError> {
+        Ok(data::parse_status(&self.status.lock().unwrap()))
+    }
+
+    fn set_location(&self, location: &Location) -> Result<(), AppError> {
+        self.record(format!("relay set location {}", location))
+    }
+
+    fn connect(&self) -> Result<(), AppError> {
+        self.record("connect".to_string())?;
+        *self.status.lock().unwrap() = CONNECTED.to_string();
+        Ok(())
+    }
+
+    fn disconnect(&self) -> Result<(), AppError> {
+        self.record("disconnect".to_string())?;
+        *self.status.lock().unwrap() = "Disconnected\n".to_string();
+        Ok(())
+    }
+
+    fn reconnect(&self) -> Result<(), AppError> {
+        self.record("reconnect".to_string())?;
+        *self.status.lock().unwrap() = CONNECTED.to_string();
+        Ok(())
+    }
+
+    fn tunnel_protocol(&self) -> Result<Option<String>, AppError> {
+        Ok(self.tunnel_protocol.lock().unwrap().clone())
+    }
+
+    fn set_tunnel_protocol(&self, protocol: &str) -> Result<(), AppError> {
+        self.record(format!("relay set tunnel-protocol {}", protocol))?;
+        *self.tunnel_protocol.lock().unwrap() = Some(protocol.to_string());
+        Ok(())
+    }
+
+    fn dns(&self) -> Result<DnsSettings, AppError> {
+        Ok(self.dns.lock().unwrap().clone())
+    }
+
+    fn set_dns(&self, dns: &DnsSettings) -> Result<(), AppError> {
+        self.record(format!("dns set {}", dns.args().join(" ")))?;
+        *self.dns.lock().unwrap() = dns.clone();
+        Ok(())
+    }
+
+    fn lockdown(&self) -> Result<bool, AppError> {
+        Ok(*self.lockdown.lock().unwrap())
+    }
+
+    fn set_lockdown(&self, on: bool) -> Result<(), AppError> {
+        self.record(format!(
+            "lockdown-mode set {}",
+            if on { "on" } else { "off" }
+        ))?;
+        *self.lockdown.lock().unwrap() = on;
+        Ok(())
+    }
+
+    fn auto_connect(&self) -> Result<bool, AppError> {
+        Ok(*self.auto_connect.lock().unwrap())
+    }
+
+    fn set_auto_connect(&self, on: bool) -> Result<(), AppError> {
+        self.record(format!(
+            "auto-connect set {}",
+            if on { "on" } else { "off" }
+        ))?;
+        *self.auto_connect.lock().unwrap() = on;
+        Ok(())
+    }
+
+    fn custom_lists(&self, countries: &[Country]) -> Result<Vec<CustomList>, AppError> {
+        Ok(data::parse_custom_lists(
+            &self.custom_lists.lock().unwrap(),
+            countries,
+        ))
+    }
+
+    fn create_custom_list(&self, name: &str) -> Result<(), AppError> {
+        self.record(format!("custom-list new {}", name))?;
+        self.custom_lists
+            .lock()
+            .unwrap()
+            .push_str(&format!("{}\n", name));
+        Ok(())
+    }
+
+    fn delete_custom_list(&self, name: &str) -> Result<(), AppError> {
+        self.record(format!("custom-list delete {}", name))?;
+        let mut lists = self.custom_lists.lock().unwrap();
+        let mut keep = true;
+        *lists = lists
+            .lines()
+            .filter(|line| {
+                if !line.starts_with('\t') {
+                    keep = *line != name;
+                }
+                keep
+            })
+            .map(|line| format!("{}\n", line))
+            .collect();
+        Ok(())
+    }
+
+    fn add_to_custom_list(&self, name: &str, location: &Location) -> Result<(), AppError> {
+        self.record(format!("custom-list edit add {} {}", name, location))
+    }
+
+    fn remove_from_custom_list(&self, name: &str, location: &Location) -> Result<(), AppError> {
+        self.record(format!("custom-list edit remove {} {}", name, location))
+    }
+
+    fn set_custom_list(&self, name: &str) -> Result<(), AppError> {
+        self.record(format!("relay set custom-list {}", name))
+    }
+
+    fn bridge(&self) -> Result<BridgeSettings, AppError> {
+        Ok(self.bridge.lock().unwrap().clone())
+    }
+
+    fn set_bridge_state(&self, state: &str) -> Result<(), AppError> {
+        self.record(format!("bridge set state {}", state))?;
+        self.bridge.lock().unwrap().mode = state.to_string();
+        Ok(())
+    }
+
+    fn set_bridge_location(&self, location: &Location) -> Result<(), AppError> {
+        self.record(format!("bridge set location {}", location))?;
+        self.bridge.lock().unwrap().location = location.to_string();
+        Ok(())
+    }
+
+    fn obfuscation(&self) -> Result<ObfuscationSettings, AppError> {
+        Ok(self.obfuscation.lock().unwrap().clone())
+    }
+
+    fn set_obfuscation_mode(&self, mode: &str) -> Result<(), AppError> {
+        self.record(format!("obfuscation set mode {}", mode))?;
+        self.obfuscation.lock().unwrap().mode = mode.to_string();
+        Ok(())
+    }
+
+    fn set_obfuscation_port(
+        &self,
+        method: ObfuscationMethod,
+        port: Option<u16>,
+    ) -> Result<(), AppError> {
+        self.record(format!(
+            "obfuscation set {} --port {}",
+            method,
+            bridge::format_port(port)
+        ))?;
+        let mut settings = self.obfuscation.lock().unwrap();
+        match method {
+            ObfuscationMethod::Udp2tcp => settings.udp2tcp_port = port,
+            ObfuscationMethod::Shadowsocks => settings.shadowsocks_port = port,
+        }
+        Ok(())
+    }
+
+    fn access_methods(&self) -> Result<Vec<AccessMethod>, AppError> {
+        Ok(self.access_methods.lock().unwrap().clone())
+    }
+
+    fn access_method_in_use(&self) -> Result<Option<String>, AppError> {
+        Ok(self.access_method_in_use.lock().unwrap().clone())
+    }
+
+    fn set_access_method_enabled(&self, index: usize, enabled: bool) -> Result<(), AppError> {
+        let command = if enabled { "enable" } else { "disable" };
+        self.record(format!("api-access {} {}", command, index))?;
+        let mut methods = self.access_methods.lock().unwrap();
+        if let Some(method) = methods.iter_mut().find(|m| m.index == index) {
+            method.enabled = enabled;
+        }
+        Ok(())
+    }
+
+    fn test_access_method(&self, index: usize) -> Result<(), AppError> {
+        self.record(format!("api-access test {}", index))
+    }
+
+    fn add_socks5_access_method(&self, socks5: &Socks5) -> Result<(), AppError> {
+        self.record(format!(
+            "api-access add socks5 remote {} {} {}",
+            socks5.name, socks5.host, socks5.port
+        ))?;
+        let mut methods = self.access_methods.lock().unwrap();
+        let index = methods.len() + 1;
+        methods.push(AccessMethod {
+            index,
+            name: socks5.name.clone(),
+            enabled: true,
+            details: vec![(
+                "Peer".to_string(),
+                format!("{}:{}", socks5.host, socks5.port),
+            )],
+        });
+        Ok(())
+    }
+}
+
+/// Keeps notifications instead of showing them.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FakeNotifier {
+    pub sent: Arc<Mutex<Vec<Notification>>>,
+}
+
+impl FakeNotifier {
+    pub fn summaries(&self) -> Vec<String> {
+        self.sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|n| n.summary.clone())
+            .collect()
+    }
+}
+
+impl Notifier for FakeNotifier {
+    fn notify(&self, notification: &Notification) -> Result<(), AppError> {
+        self.sent.lock().unwrap().push(notification.clone());
+        Ok(())
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug, Clone)]
+pub(crate) struct FakeClock {
+    pub now: Arc<Mutex<Instant>>,
+}
+
+impl Default for FakeClock {
+    fn default() -> Self {
+        Self {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+}
+
+impl FakeClock {
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// Serves fixed JSON bodies by path on a local port, for as long as the
+/// test runs. Returns the base URL, e.g. `http://127.0.0.1:40000`.
+pub(crate) fn http_stub(routes: Vec<(&'static str, String)>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            // Skip the headers.
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 2 {
+                line.clear();
+            }
+            let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+            let response = match routes.iter().find(|(prefix, _)| path.starts_with(prefix)) {
+                Some((_, body)) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                ),
+                None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            };
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    url
+}
@@ -0,0 +1,1199 @@
+mod control;
+mod fake;
+mod ui;
+
+#[test]
+fn load_config() {
+    dotenv::dotenv().ok();
+    // let cli = <crate::cli::Cli as clap::Parser>::parse();
+    let config = crate::config::Config::load(Some("config-example.toml")).unwrap();
+    assert_eq!(
+        config.colors.connected,
+        ratatui::style::Color::Rgb(0x85, 0x99, 0x00)
+    );
+}
+
+pub(crate) const RELAY_LIST: &str = "\
+Sweden (se)
+\tGothenburg (got) @ 57.70887°N, 11.97456°E
+\t\tse-got-wg-001 (185.213.154.66, 2a03:1b20:5:f011::a01f) - WireGuard, hosted by 31173 (Mullvad-owned)
+\t\tse-got-ovpn-001 (185.213.154.131, 2a03:1b20:5:f011:31::a03f) - OpenVPN, hosted by 31173 (rented)
+\tMalmö (mma) @ 55.60587°N, 13.00073°E
+\t\tse-mma-wg-001 (193.138.218.220, 2a03:1b20:1:f011::a01f) - WireGuard, hosted by 31173 (Mullvad-owned)
+Switzerland (ch)
+\tZurich (zrh) @ 47.36667°N, 8.55000°E
+\t\tch-zrh-wg-001 (193.32.127.66, 2a03:1b20:9:f011::a01f) - WireGuard, hosted by M247 (rented)
+";
+
+#[test]
+fn parse_relay_list() {
+    let countries = crate::data::parse_relay_list(RELAY_LIST);
+    assert_eq!(countries.len(), 2);
+    assert_eq!(countries[0].to_string(), "Sweden (se)");
+    assert_eq!(countries[0].cities[1].to_string(), "Malmö (mma)");
+
+    let relay = &countries[0].cities[0].relays[1];
+    assert_eq!(relay.hostname, "se-got-ovpn-001");
+    assert_eq!(relay.ipv4, "185.213.154.131");
+    assert_eq!(relay.protocol, "OpenVPN");
+    assert_eq!(relay.provider, "31173");
+    assert!(!relay.owned);
+    assert!(countries[0].cities[0].relays[0].owned);
+}
+
+#[test]
+fn parse_custom_lists() {
+    use crate::data::Location;
+
+    let countries = crate::data::parse_relay_list(RELAY_LIST);
+    let output = "\
+EU low-latency
+\tSwitzerland (ch)
+\tMalmö (mma), Sweden
+\tse-got-wg-001 (Gothenburg, Sweden)
+empty
+";
+    let lists = crate::data::parse_custom_lists(output, &countries);
+    assert_eq!(lists.len(), 2);
+    assert_eq!(lists[0].name, "EU low-latency");
+    assert!(lists[1].locations.is_empty());
+
+    let locations: Vec<_> = lists[0]
+        .locations
+        .iter()
+        .map(|e| e.location.clone())
+        .collect();
+    assert_eq!(
+        locations,
+        vec![
+            Some(Location::Country("ch".to_string())),
+            Some(Location::City("se".to_string(), "mma".to_string())),
+            Some(Location::Relay(
+                "se".to_string(),
+                "got".to_string(),
+                "se-got-wg-001".to_string()
+            )),
+        ]
+    );
+}
+
+#[test]
+fn parse_status() {
+    use crate::data::TunnelState;
+
+    let output = "\
+Connected to se-got-wg-001 in Gothenburg, Sweden
+Relay:                  se-got-wg-001 via ch-zrh-wg-001 (185.213.154.66:51820/UDP)
+Tunnel type:            WireGuard
+Obfuscation:            udp2tcp
+Visible location:       Sweden, Gothenburg. IPv4: 185.213.154.68, IPv6: 2a03:1b20:5:f011::a01f
+";
+    let status = crate::data::parse_status(output);
+    assert_eq!(status.state, TunnelState::Connected);
+    assert_eq!(status.relay.as_deref(), Some("se-got-wg-001"));
+    assert_eq!(status.entry_relay.as_deref(), Some("ch-zrh-wg-001"));
+    assert_eq!(status.entry_ip.as_deref(), Some("185.213.154.66"));
+    assert_eq!(status.location.as_deref(), Some("Gothenburg, Sweden"));
+    assert_eq!(status.tunnel_protocol.as_deref(), Some("WireGuard"));
+    assert_eq!(status.obfuscation.as_deref(), Some("udp2tcp"));
+    assert_eq!(status.visible_ipv4.as_deref(), Some("185.213.154.68"));
+    assert_eq!(
+        status.visible_ipv6.as_deref(),
+        Some("2a03:1b20:5:f011::a01f")
+    );
+
+    let status = crate::data::parse_status("Disconnected\n");
+    assert_eq!(status.state, TunnelState::Disconnected);
+    assert!(status.relay.is_none());
+
+    let status = crate::data::parse_status("Blocked: Failed to set up firewall rules\n");
+    assert_eq!(
+        status.state,
+        TunnelState::Error("Failed to set up firewall rules".to_string())
+    );
+}
+
+#[test]
+fn traffic_rates_from_sysfs() {
+    use std::time::{Duration, Instant};
+
+    let root = tempfile::tempdir().unwrap();
+    let stats = root.path().join("wg0-mullvad").join("statistics");
+    std::fs::create_dir_all(&stats).unwrap();
+    let write = |rx: u64, tx: u64| {
+        std::fs::write(stats.join("rx_bytes"), format!("{}\n", rx)).unwrap();
+        std::fs::write(stats.join("tx_bytes"), format!("{}\n", tx)).unwrap();
+    };
+
+    let mut monitor = crate::traffic::TrafficMonitor::new(root.path());
+    let start = Instant::now();
+    write(1000, 500);
+    monitor.tick(start);
+    assert_eq!(monitor.interface(), Some("wg0-mullvad"));
+    assert_eq!(monitor.rx_rate(), 0);
+
+    write(5000, 1500);
+    // Ticks faster than the sample interval are ignored.
+    monitor.tick(start + Duration::from_millis(500));
+    assert!(monitor.rx_rates.is_empty());
+
+    monitor.tick(start + Duration::from_secs(2));
+    assert_eq!(monitor.rx_rate(), 2000);
+    assert_eq!(monitor.tx_rate(), 500);
+
+    let totals = monitor.session_totals();
+    assert_eq!((totals.rx_bytes, totals.tx_bytes), (4000, 1000));
+
+    monitor.reset();
+    assert_eq!(monitor.session_totals().rx_bytes, 0);
+}
+
+#[test]
+fn parse_ping() {
+    let output = "\
+PING 185.213.154.66 (185.213.154.66) 56(84) bytes of data.
+64 bytes from 185.213.154.66: icmp_seq=1 ttl=52 time=23.4 ms
+";
+    assert_eq!(
+        crate::data::parse_ping(output),
+        Some(std::time::Duration::from_micros(23_400))
+    );
+    assert_eq!(
+        crate::data::parse_ping("1 packets transmitted, 0 received"),
+        None
+    );
+}
+
+#[test]
+fn relay_cache_round_trip() {
+    use crate::cache::RelayCache;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("mullvad-tui").join("relays.json");
+    assert!(RelayCache::load(&path).is_none());
+
+    let updated = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let countries = crate::data::parse_relay_list(RELAY_LIST);
+    RelayCache::new(countries, updated).save(&path).unwrap();
+
+    let cache = RelayCache::load(&path).unwrap();
+    assert_eq!(cache.updated_at(), updated);
+    assert_eq!(cache.countries.len(), 2);
+    assert_eq!(
+        cache.countries[0].cities[0].relays[0].hostname,
+        "se-got-wg-001"
+    );
+}
+
+#[test]
+fn format_cache_age() {
+    use crate::cache::format_age;
+    use std::time::Duration;
+
+    assert_eq!(format_age(Duration::from_secs(30)), "just now");
+    assert_eq!(format_age(Duration::from_secs(60)), "1 minute ago");
+    assert_eq!(format_age(Duration::from_secs(45 * 60)), "45 minutes ago");
+    assert_eq!(format_age(Duration::from_secs(3 * 3600)), "3 hours ago");
+    assert_eq!(format_age(Duration::from_secs(2 * 86400)), "2 days ago");
+}
+
+#[test]
+fn bar_output() {
+    use crate::bar::{render, BarFormat};
+    use crate::config::Config;
+
+    let colors = Config::load(Some("config-example.toml")).unwrap().colors;
+    let connected = crate::data::parse_status(fake::CONNECTED);
+    let disconnected = crate::data::parse_status("Disconnected\n");
+
+    assert_eq!(
+        render(&connected, BarFormat::Plain, &colors),
+        "🔒 Gothenburg, Sweden (se-got-wg-001)"
+    );
+    assert_eq!(
+        render(&disconnected, BarFormat::Plain, &colors),
+        "🔓 Disconnected"
+    );
+    assert_eq!(
+        render(&connected, BarFormat::Polybar, &colors),
+        "%{F#859900}🔒 Gothenburg, Sweden (se-got-wg-001)%{F-}"
+    );
+
+    let waybar: serde_json::Value =
+        serde_json::from_str(&render(&connected, BarFormat::Waybar, &colors)).unwrap();
+    assert_eq!(waybar["class"], "connected");
+    assert!(waybar["tooltip"]
+        .as_str()
+        .unwrap()
+        .contains("185.213.154.68"));
+
+    let i3blocks: serde_json::Value =
+        serde_json::from_str(&render(&disconnected, BarFormat::I3blocks, &colors)).unwrap();
+    assert_eq!(i3blocks["full_text"], "🔓 Disconnected");
+    assert!(i3blocks["color"].as_str().unwrap().starts_with('#'));
+
+    // Without a config there are no colors to emit.
+    let plain = render(&connected, BarFormat::Polybar, &Config::default().colors);
+    assert!(!plain.contains("%{F"));
+}
+
+#[test]
+fn notification_transitions() {
+    use crate::data::{parse_status, TunnelState, TunnelStatus};
+    use crate::notify::{transition, NotifyEvent};
+
+    let connected = parse_status(fake::CONNECTED);
+    let disconnected = parse_status("Disconnected\n");
+    let blocked = parse_status("Disconnected (blocking all traffic, lockdown mode)\n");
+    let error = TunnelStatus {
+        state: TunnelState::Error("daemon not running".to_string()),
+        ..TunnelStatus::default()
+    };
+    let event = |old, new, requested| transition(old, new, requested).map(|n| n.event);
+
+    let notification = transition(&disconnected, &connected, false).unwrap();
+    assert_eq!(notification.event, NotifyEvent::Connected);
+    assert_eq!(notification.body, "se-got-wg-001 in Gothenburg, Sweden");
+    assert_eq!(event(&connected, &connected, false), None);
+
+    assert_eq!(
+        event(&connected, &disconnected, false),
+        Some(NotifyEvent::Disconnected)
+    );
+    assert_eq!(event(&connected, &disconnected, true), None);
+    // The daemon usually reports the drop in two steps.
+    let disconnecting = TunnelStatus {
+        state: TunnelState::Disconnecting,
+        ..TunnelStatus::default()
+    };
+    assert_eq!(event(&connected, &disconnecting, false), None);
+    assert_eq!(
+        event(&disconnecting, &disconnected, false),
+        Some(NotifyEvent::Disconnected)
+    );
+    assert_eq!(event(&disconnecting, &disconnected, true), None);
+    assert_eq!(event(&connected, &error, false), Some(NotifyEvent::Error));
+    assert_eq!(event(&error, &error, false), None);
+    assert_eq!(
+        event(&disconnected, &blocked, false),
+        Some(NotifyEvent::Blocked)
+    );
+}
+
+#[test]
+fn hook_events_and_env() {
+    use crate::data::parse_status;
+    use crate::hooks::{env, event, HookEvent};
+
+    let connected = parse_status(fake::CONNECTED);
+    let disconnected = parse_status("Disconnected\n");
+    let mut moved = connected.clone();
+    moved.relay = Some("se-mma-wg-001".to_string());
+
+    assert_eq!(event(&disconnected, &connected), Some(HookEvent::Connect));
+    assert_eq!(event(&connected, &moved), Some(HookEvent::RelayChange));
+    assert_eq!(event(&connected, &connected), None);
+    assert_eq!(
+        event(&connected, &disconnected),
+        Some(HookEvent::Disconnect)
+    );
+
+    let env = env(HookEvent::Connect, &connected);
+    let get = |key: &str| env.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+    assert_eq!(get("MULLVAD_HOSTNAME"), Some("se-got-wg-001"));
+    assert_eq!(get("MULLVAD_COUNTRY_CODE"), Some("se"));
+    assert_eq!(get("MULLVAD_CITY_CODE"), Some("got"));
+    assert_eq!(get("MULLVAD_IPV4"), Some("185.213.154.68"));
+}
+
+#[test]
+fn run_hook_with_timeout() {
+    use crate::hooks::{run, HookEvent, HookStatus};
+    use std::time::Duration;
+
+    let env = vec![("MULLVAD_HOSTNAME".to_string(), "se-got-wg-001".to_string())];
+    let result = run(
+        HookEvent::Connect,
+        "echo up $MULLVAD_HOSTNAME; exit 3",
+        &env,
+        Duration::from_secs(5),
+    );
+    assert_eq!(result.status, HookStatus::Exited(Some(3)));
+    assert_eq!(result.output, "up se-got-wg-001\n");
+
+    let result = run(
+        HookEvent::Connect,
+        "sleep 5",
+        &env,
+        Duration::from_millis(100),
+    );
+    assert_eq!(result.status, HookStatus::TimedOut);
+    assert!(result.duration < Duration::from_secs(5));
+
+    // A background process keeps the pipes open past the hook's own exit.
+    let started = std::time::Instant::now();
+    let result = run(
+        HookEvent::Connect,
+        "echo started; sleep 30 &",
+        &env,
+        Duration::from_millis(300),
+    );
+    assert_eq!(result.status, HookStatus::Exited(Some(0)));
+    assert_eq!(result.output, "started\n");
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn log_file_levels_and_rotation() {
+    use crate::logging::{Entry, Level, LogFile};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state").join("mullvad-tui.log");
+    let mut file = LogFile::open(&path, Level::from_verbosity(1)).unwrap();
+    let entry = |level, message: &str| Entry {
+        time: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        level,
+        target: "backend",
+        message: message.to_string(),
+        fields: vec![
+            ("exit", "1".to_string()),
+            ("stderr", "no route".to_string()),
+        ],
+    };
+
+    file.write(&entry(Level::Debug, "mullvad status -v"))
+        .unwrap();
+    file.write(&entry(Level::Warn, "mullvad connect")).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "2023-11-14T22:13:20Z WARN  backend: mullvad connect exit=1 stderr=\"no route\"\n"
+    );
+
+    file.write(&entry(Level::Info, &"x".repeat(1024 * 1024)))
+        .unwrap();
+    file.write(&entry(Level::Info, "after rotation")).unwrap();
+    let rotated = path.with_file_name("mullvad-tui.log.1");
+    assert!(std::fs::read_to_string(rotated)
+        .unwrap()
+        .contains("mullvad connect"));
+    assert!(std::fs::read_to_string(&path)
+        .unwrap()
+        .contains("after rotation"));
+}
+
+#[test]
+fn format_log_time() {
+    use std::time::{Duration, UNIX_EPOCH};
+    let format = |secs| crate::logging::format_time(UNIX_EPOCH + Duration::from_secs(secs));
+    assert_eq!(format(0), "1970-01-01T00:00:00Z");
+    assert_eq!(format(951_782_400), "2000-02-29T00:00:00Z");
+    assert_eq!(format(1_729_254_896), "2024-10-18T12:34:56Z");
+}
+
+#[test]
+fn crash_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = crate::terminal::write_crash_report(dir.path(), "panicked at src/app.rs:1:1:\nboom")
+        .unwrap();
+    let report = std::fs::read_to_string(path).unwrap();
+    assert!(report.starts_with(concat!(
+        "mullvad-tui ",
+        env!("CARGO_PKG_VERSION"),
+        " crashed at "
+    )));
+    assert!(report.contains("boom"));
+}
+
+#[test]
+fn parse_commands() {
+    use std::collections::BTreeMap;
+
+    use crate::{action::Action, data::Location, query};
+
+    let countries = crate::data::parse_relay_list(RELAY_LIST);
+    let favorites = BTreeMap::from([("home".to_string(), "se mma".to_string())]);
+    let parse = |input| query::parse(input, &countries, &favorites).map_err(|e| e.to_string());
+
+    assert_eq!(
+        parse("connect se"),
+        Ok(Action::Connect(Location::Country("se".into())))
+    );
+    assert_eq!(
+        parse("connect  ch-zrh-wg-001"),
+        Ok(Action::Connect(Location::Relay(
+            "ch".into(),
+            "zrh".into(),
+            "ch-zrh-wg-001".into()
+        )))
+    );
+    assert_eq!(
+        parse("connect fav:home"),
+        Ok(Action::Connect(Location::City("se".into(), "mma".into())))
+    );
+    assert_eq!(
+        parse("set protocol any"),
+        Ok(Action::SetProtocol("any".into()))
+    );
+    assert_eq!(parse("disconnect"), Ok(Action::Disconnect));
+    assert_eq!(
+        parse("connect se sto"),
+        Err("Unknown location: se sto".into())
+    );
+    assert_eq!(
+        parse("connect fav:gym"),
+        Err("Unknown favorite: gym".into())
+    );
+    assert_eq!(
+        parse("set protocol ipsec"),
+        Err("Usage: set protocol wireguard|openvpn|any".into())
+    );
+    assert_eq!(parse("jump"), Err("Unknown command: jump".into()));
+    assert_eq!(parse(""), Err("Empty command".into()));
+
+    assert_eq!(
+        query::complete("d", &countries, &favorites),
+        vec!["disconnect"]
+    );
+    assert_eq!(
+        query::complete("connect ", &countries, &favorites),
+        vec!["connect se", "connect ch"]
+    );
+    assert_eq!(
+        query::complete("connect fav:", &countries, &favorites),
+        vec!["connect fav:home"]
+    );
+    assert_eq!(
+        query::complete("connect se-got", &countries, &favorites),
+        vec!["connect se-got-wg-001", "connect se-got-ovpn-001"]
+    );
+    assert_eq!(
+        query::complete("connect se got se-got-w", &countries, &favorites),
+        vec!["connect se got se-got-wg-001"]
+    );
+    assert_eq!(
+        query::complete("set protocol o", &countries, &favorites),
+        vec!["set protocol openvpn"]
+    );
+}
+
+#[test]
+fn command_history() {
+    use crate::history::History;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("mullvad-tui").join("history");
+    let mut history = History::load(&path);
+    assert_eq!(history.previous(), None);
+
+    history.push("connect se").unwrap();
+    history.push("disconnect").unwrap();
+    history.push("disconnect").unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "connect se\ndisconnect\n"
+    );
+
+    let mut history = History::load(&path);
+    assert_eq!(history.previous(), Some("disconnect"));
+    assert_eq!(history.previous(), Some("connect se"));
+    assert_eq!(history.previous(), Some("connect se"));
+    assert_eq!(history.next(), Some("disconnect"));
+    assert_eq!(history.next(), None);
+}
+
+#[test]
+fn relay_filter() {
+    use crate::filter::RelayFilter;
+
+    let countries = crate::data::parse_relay_list(RELAY_LIST);
+    let relays: Vec<_> = countries.iter().flat_map(|c| c.relays()).collect();
+    let matching = |filter: &RelayFilter| -> Vec<&str> {
+        relays
+            .iter()
+            .filter(|r| filter.matches(r))
+            .map(|r| r.hostname.as_str())
+            .collect()
+    };
+
+    assert_eq!(matching(&RelayFilter::default()).len(), 4);
+    let filter = RelayFilter::parse(&["openvpn"]).unwrap();
+    assert_eq!(matching(&filter), vec!["se-got-ovpn-001"]);
+    let filter = RelayFilter::parse(&["wireguard", "rented", "provider", "M247"]).unwrap();
+    assert_eq!(filter.to_string(), "WireGuard, rented, M247");
+    assert_eq!(matching(&filter), vec!["ch-zrh-wg-001"]);
+    assert!(RelayFilter::parse(&[]).unwrap().is_empty());
+    assert_eq!(
+        RelayFilter::parse(&["fast"]).unwrap_err().to_string(),
+        "Unknown filter: fast"
+    );
+}
+
+#[test]
+fn random_relay_picks() {
+    use std::collections::BTreeMap;
+
+    use crate::{
+        data::Location,
+        filter::RelayFilter,
+        random::{self, Scope},
+    };
+
+    let countries = crate::data::parse_relay_list(RELAY_LIST);
+    let current = Location::Relay("se".into(), "got".into(), "se-got-wg-001".into());
+    let favorites = BTreeMap::from([("home".to_string(), "se".to_string())]);
+    let filter = RelayFilter::default();
+    let mut rng = fastrand::Rng::with_seed(7);
+
+    for _ in 0..20 {
+        let relay = random::pick(
+            Scope::Country,
+            Some(&current),
+            &countries,
+            &favorites,
+            &filter,
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(relay.country_code(), "se");
+        assert_ne!(relay, current);
+        // The only favorite is in the current country, so it is used anyway.
+        let relay = random::pick(
+            Scope::Favorite,
+            Some(&current),
+            &countries,
+            &favorites,
+            &filter,
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(relay.country_code(), "se");
+        assert_ne!(relay, current);
+    }
+    // Seeded picks are repeatable.
+    let pick = |seed| {
+        let mut rng = fastrand::Rng::with_seed(seed);
+        random::pick(
+            Scope::Country,
+            Some(&current),
+            &countries,
+            &favorites,
+            &filter,
+            &mut rng,
+        )
+        .unwrap()
+    };
+    assert_eq!(pick(3), pick(3));
+
+    let error =
+        random::pick(Scope::City, None, &countries, &favorites, &filter, &mut rng).unwrap_err();
+    assert_eq!(error.to_string(), "Not connected to a relay");
+    let error = random::pick(
+        Scope::Favorite,
+        None,
+        &countries,
+        &BTreeMap::new(),
+        &filter,
+        &mut rng,
+    )
+    .unwrap_err();
+    assert_eq!(error.to_string(), "No favorites configured");
+}
+
+#[test]
+fn exit_ip_check() {
+    use std::time::{Duration, Instant};
+
+    use crate::data::{parse_status, ExitIpChange, ExitIpCheck};
+
+    let now = Instant::now();
+    let connected = parse_status(fake::CONNECTED);
+    let mut check = ExitIpCheck::start(&connected, now).unwrap();
+    assert_eq!(check.update(&connected, now), None);
+    assert_eq!(
+        check.update(&connected, now + Duration::from_secs(30)),
+        Some(ExitIpChange::Unchanged("185.213.154.68".to_string()))
+    );
+
+    let mut check = ExitIpCheck::start(&connected, now).unwrap();
+    assert_eq!(
+        check.update(&parse_status("Connecting to se-got-wg-001\n"), now),
+        None
+    );
+    assert_eq!(
+        check.update(&connected, now),
+        Some(ExitIpChange::Unchanged("185.213.154.68".to_string()))
+    );
+    assert!(ExitIpCheck::start(&parse_status("Disconnected\n"), now).is_none());
+}
+
+#[test]
+fn rotation_schedule() {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        data::Location,
+        rotation::{self, Order, Plan, Pool, Rotation},
+    };
+
+    let plan = Plan::parse(&["10", "favorites"]).unwrap();
+    assert_eq!(plan.order, Order::Random);
+    assert_eq!(plan.interval, Duration::from_secs(600));
+    assert_eq!(
+        Plan::parse(&["1", "round-robin", "country", "se"])
+            .unwrap()
+            .pool,
+        Pool::Country("se".into())
+    );
+    assert!(Plan::parse(&["0", "favorites"]).is_err());
+    assert!(Plan::parse(&["5", "city", "got"]).is_err());
+
+    let now = Instant::now();
+    let mut rotation = Rotation::new(
+        Plan::parse(&["1", "round-robin", "country", "se"]).unwrap(),
+        now,
+    );
+    assert!(!rotation.is_due(now + Duration::from_secs(59)));
+    rotation.pause(now + Duration::from_secs(20));
+    assert!(!rotation.is_due(now + Duration::from_secs(100)));
+    assert_eq!(
+        rotation.remaining(now + Duration::from_secs(100)),
+        Duration::from_secs(40)
+    );
+    rotation.resume(now + Duration::from_secs(100));
+    assert!(rotation.is_due(now + Duration::from_secs(140)));
+
+    let relay = |host: &str| {
+        let parts: Vec<&str> = host.split('-').collect();
+        Location::Relay(parts[0].into(), parts[1].into(), host.into())
+    };
+    let pool = vec![
+        relay("se-got-wg-001"),
+        relay("se-got-ovpn-001"),
+        relay("se-mma-wg-001"),
+    ];
+    let mut rng = fastrand::Rng::with_seed(1);
+    let mut next = |current| rotation.next(now, current, &pool, &mut rng).unwrap();
+    assert_eq!(next(None), pool[0]);
+    assert_eq!(next(Some("se-got-wg-001")), pool[1]);
+    assert_eq!(next(Some("se-got-ovpn-001")), pool[2]);
+    assert_eq!(next(Some("se-mma-wg-001")), pool[0]);
+
+    assert_eq!(
+        rotation::format_countdown(Duration::from_secs(299)),
+        "04:59"
+    );
+    assert_eq!(
+        rotation::format_countdown(Duration::from_secs(3661)),
+        "1:01:01"
+    );
+}
+
+#[test]
+fn leak_check_against_stub() {
+    use crate::leak::{self, LeakCheckConfig};
+
+    let exit = r#"{"ip": "185.213.154.68", "country": "Sweden", "mullvad_exit_ip": true,
+        "mullvad_exit_ip_hostname": "se-got-wg-001", "mullvad_server_type": "WireGuard"}"#;
+    let resolvers =
+        r#"[{"ip": "10.64.0.1", "mullvad_dns": true}, {"ip": "192.0.2.53", "mullvad_dns": false}]"#;
+    let url = fake::http_stub(vec![
+        ("/json", exit.to_string()),
+        ("/dns/", resolvers.to_string()),
+    ]);
+
+    let mut config = LeakCheckConfig {
+        url: format!("{}/json", url),
+        dns_url: None,
+        ..LeakCheckConfig::default()
+    };
+    let check = leak::check(&config).unwrap();
+    assert_eq!(check.ip, "185.213.154.68");
+    assert!(check.mullvad_exit_ip);
+    assert_eq!(check.hostname.as_deref(), Some("se-got-wg-001"));
+    assert_eq!(check.dns_leaking, None);
+
+    let connected = crate::data::parse_status(fake::CONNECTED);
+    assert_eq!(leak::disagreement(&connected, &check), None);
+
+    config.dns_url = Some(format!("{}/dns/{{id}}", url));
+    let check = leak::check(&config).unwrap();
+    assert_eq!(check.dns_leaking, Some(true));
+    assert_eq!(
+        leak::disagreement(&connected, &check).unwrap(),
+        "DNS requests are leaking outside the tunnel"
+    );
+
+    config.url = format!("{}/missing", url);
+    assert!(leak::check(&config)
+        .unwrap_err()
+        .to_string()
+        .contains("404"));
+}
+
+#[test]
+fn leak_check_disagreements() {
+    use crate::leak::{self, LeakCheck};
+
+    let connected = crate::data::parse_status(fake::CONNECTED);
+    let check = LeakCheck {
+        ip: "203.0.113.7".to_string(),
+        mullvad_exit_ip: false,
+        hostname: None,
+        dns_leaking: None,
+    };
+    assert_eq!(
+        leak::disagreement(&connected, &check).unwrap(),
+        "Mullvad reports connected, but traffic leaves from 203.0.113.7, which is not a Mullvad server"
+    );
+    let other_relay = LeakCheck {
+        mullvad_exit_ip: true,
+        ..check.clone()
+    };
+    assert_eq!(
+        leak::disagreement(&connected, &other_relay).unwrap(),
+        "Mullvad reports exit IP 185.213.154.68, but traffic leaves from 203.0.113.7"
+    );
+    // Not being on Mullvad is expected while disconnected.
+    let disconnected = crate::data::parse_status("Disconnected\n");
+    assert_eq!(leak::disagreement(&disconnected, &check), None);
+    assert!(leak::parse("<html>").is_err());
+}
+
+#[test]
+fn parse_bridge_and_obfuscation() {
+    use crate::bridge::{self, BridgeSettings};
+
+    let settings =
+        bridge::parse_bridge("Bridge mode: on\nBridge constraints: Gothenburg, Sweden\n");
+    assert_eq!(settings.mode, "on");
+    assert_eq!(settings.location, "Gothenburg, Sweden");
+
+    let obfuscation = bridge::parse_obfuscation(
+        "Obfuscation mode: udp2tcp\nudp2tcp settings: port 80\nShadowsocks settings: any port\n",
+    );
+    assert_eq!(obfuscation.mode, "udp2tcp");
+    assert_eq!(obfuscation.udp2tcp_port, Some(80));
+    assert_eq!(obfuscation.shadowsocks_port, None);
+
+    assert_eq!(bridge::cycle(&bridge::BRIDGE_MODES, "off", false), "auto");
+    assert_eq!(bridge::cycle(&bridge::BRIDGE_MODES, "auto", true), "off");
+
+    let mut status = crate::data::parse_status(fake::CONNECTED);
+    assert_eq!(
+        bridge::active(&status, &settings, &obfuscation),
+        "WireGuard obfuscated with udp2tcp"
+    );
+    status.tunnel_protocol = Some("OpenVPN".to_string());
+    assert_eq!(
+        bridge::active(&status, &settings, &obfuscation),
+        "OpenVPN through a bridge in Gothenburg, Sweden"
+    );
+    let off = BridgeSettings {
+        mode: "off".to_string(),
+        ..settings
+    };
+    assert_eq!(
+        bridge::active(&status, &off, &obfuscation),
+        "OpenVPN without a bridge"
+    );
+}
+
+#[test]
+fn parse_api_access_methods() {
+    use crate::api_access::{self, Socks5Form};
+
+    let output = "\
+1. Direct
+2. Mullvad Bridges
+3. Encrypted DNS proxy (disabled)
+4. office
+    Protocol: Socks5 (remote)
+    Peer: 192.0.2.10:1080
+";
+    let methods = api_access::parse_list(output);
+    assert_eq!(methods.len(), 4);
+    assert_eq!(methods[2].name, "Encrypted DNS proxy");
+    assert!(!methods[2].enabled);
+    assert_eq!(methods[3].index, 4);
+    assert_eq!(
+        methods[3].details[1],
+        ("Peer".to_string(), "192.0.2.10:1080".to_string())
+    );
+
+    assert_eq!(
+        api_access::parse_current("Mullvad Bridges\n").as_deref(),
+        Some("Mullvad Bridges")
+    );
+    assert_eq!(
+        api_access::parse_current("Name: office\nEnabled: true\n").as_deref(),
+        Some("office")
+    );
+
+    let form = |name: &str, host: &str, port: &str| Socks5Form {
+        name: name.to_string(),
+        host: host.to_string(),
+        port: port.to_string(),
+        field: 0,
+    };
+    let socks5 = form("office", "2001:db8::1", "1080").validate().unwrap();
+    assert_eq!(socks5.port, 1080);
+    assert_eq!(
+        form("", "192.0.2.1", "1080")
+            .validate()
+            .unwrap_err()
+            .to_string(),
+        "The method needs a name"
+    );
+    assert_eq!(
+        form("x", "192.0.2.1", "0")
+            .validate()
+            .unwrap_err()
+            .to_string(),
+        "Port must be between 1 and 65535: 0"
+    );
+}
+
+#[test]
+fn location_picker_navigation() {
+    use crate::{
+        data::Location,
+        picker::{Level, LocationPicker},
+    };
+    use crossterm::event::KeyCode;
+
+    // Nothing to move through, nothing selected, and no panics.
+    let mut empty = LocationPicker::new(Vec::new());
+    for code in [
+        KeyCode::Char('G'),
+        KeyCode::Char('j'),
+        KeyCode::Char('k'),
+        KeyCode::Char('g'),
+        KeyCode::Char('g'),
+    ] {
+        assert!(empty.handle_key(code));
+    }
+    assert_eq!(empty.selected_location(), None);
+    assert!(!empty.enter());
+    assert!(!empty.back());
+
+    let mut picker = LocationPicker::new(crate::data::parse_relay_list(RELAY_LIST));
+    let selected = |picker: &LocationPicker| picker.selected_location().map(|l| l.to_string());
+
+    // Moving stops at both ends.
+    picker.handle_key(KeyCode::Char('k'));
+    assert_eq!(selected(&picker).as_deref(), Some("se"));
+    picker.handle_key(KeyCode::Char('G'));
+    picker.handle_key(KeyCode::Char('j'));
+    assert_eq!(selected(&picker).as_deref(), Some("ch"));
+    // A lone `g` waits for the second one; anything in between cancels it.
+    picker.handle_key(KeyCode::Char('g'));
+    assert!(!picker.handle_key(KeyCode::Enter));
+    picker.handle_key(KeyCode::Char('g'));
+    assert_eq!(selected(&picker).as_deref(), Some("ch"));
+    picker.handle_key(KeyCode::Char('g'));
+    assert_eq!(selected(&picker).as_deref(), Some("se"));
+
+    // The filter hides rows without touching the tree.
+    for c in "MALM".chars() {
+        picker.push_filter(c);
+    }
+    assert_eq!(picker.len(), 0);
+    picker.handle_key(KeyCode::Char('G'));
+    assert_eq!(selected(&picker), None);
+    assert!(!picker.enter());
+    picker.reset();
+    assert_eq!(picker.len(), 2);
+
+    assert!(picker.enter());
+    assert_eq!(picker.level(), Level::Cities);
+    picker.push_filter('m');
+    assert_eq!(picker.len(), 1);
+    assert_eq!(selected(&picker).as_deref(), Some("se mma"));
+    picker.pop_filter();
+    assert_eq!(picker.len(), 2);
+
+    // Into the relays of the last city.
+    picker.handle_key(KeyCode::Char('G'));
+    assert!(picker.enter());
+    assert_eq!(selected(&picker).as_deref(), Some("se mma se-mma-wg-001"));
+    assert_eq!(picker.selected_relays().len(), 1);
+
+    // Going back selects where we came from.
+    assert!(picker.back());
+    assert_eq!(selected(&picker).as_deref(), Some("se mma"));
+    assert!(picker.back());
+    assert_eq!(selected(&picker).as_deref(), Some("se"));
+
+    picker
+        .navigate(&Location::City("ch".to_string(), "zrh".to_string()))
+        .unwrap();
+    assert_eq!(picker.level(), Level::Cities);
+    assert_eq!(selected(&picker).as_deref(), Some("ch zrh"));
+    assert!(picker
+        .navigate(&Location::Country("xx".to_string()))
+        .is_err());
+    assert_eq!(selected(&picker).as_deref(), Some("ch zrh"));
+
+    // A new tree keeps the selection if it is still there.
+    picker.set_tree(crate::data::parse_relay_list(RELAY_LIST));
+    assert_eq!(selected(&picker).as_deref(), Some("ch zrh"));
+    picker.set_tree(Vec::new());
+    assert_eq!(picker.level(), Level::Countries);
+    assert_eq!(selected(&picker), None);
+}
+
+#[test]
+fn location_picker_tree_mode() {
+    use crate::{
+        data::Location,
+        picker::{Level, LocationPicker},
+    };
+
+    let mut picker = LocationPicker::new(crate::data::parse_relay_list(RELAY_LIST));
+    let relay = Location::Relay(
+        "se".to_string(),
+        "mma".to_string(),
+        "se-mma-wg-001".to_string(),
+    );
+    picker.navigate(&relay).unwrap();
+
+    // Switching keeps the selection, opening the branches above it.
+    picker.set_tree_mode(true);
+    assert_eq!(picker.selected_location(), Some(relay.clone()));
+    assert_eq!(picker.level(), Level::Relays);
+    assert_eq!(picker.len(), 5);
+
+    // A relay has nothing to open; back goes to its city, then closes it.
+    assert!(!picker.enter());
+    assert!(picker.back());
+    assert_eq!(picker.level(), Level::Cities);
+    assert!(picker.back());
+    assert_eq!(picker.len(), 4);
+
+    picker.set_tree_mode(false);
+    assert_eq!(picker.level(), Level::Cities);
+    assert_eq!(picker.len(), 2);
+    assert_eq!(
+        picker.selected_location(),
+        Some(Location::City("se".to_string(), "mma".to_string()))
+    );
+}
+
+#[test]
+fn map_coordinates_and_moves() {
+    use crate::{
+        data::{Coordinates, Location},
+        map::{self, Direction},
+    };
+
+    assert_eq!(
+        Coordinates::parse("33.86785°S, 151.20732°W"),
+        Some(Coordinates {
+            latitude: -33.86785,
+            longitude: -151.20732
+        })
+    );
+    assert_eq!(Coordinates::parse("57.7°X, 11.9°E"), None);
+
+    let mut countries = crate::data::parse_relay_list(RELAY_LIST);
+    assert_eq!(
+        countries[0].cities[0].coordinates.map(|c| c.latitude),
+        Some(57.70887)
+    );
+    // Without coordinates in the list the bundled table fills in.
+    countries[1].cities[0].coordinates = None;
+    let cities = map::cities(&countries);
+    assert_eq!(cities.len(), 3);
+    assert_eq!(
+        cities[2].coordinates,
+        map::known_coordinates("ch", "zrh").unwrap()
+    );
+
+    let at = |i: Option<usize>| i.map(|i| cities[i].location.clone());
+    let city =
+        |country: &str, city: &str| Some(Location::City(country.to_string(), city.to_string()));
+    // Gothenburg, Malmö, Zurich.
+    assert_eq!(
+        at(map::nearest(&cities, 0, Direction::Down)),
+        city("se", "mma")
+    );
+    assert_eq!(
+        at(map::nearest(&cities, 1, Direction::Down)),
+        city("ch", "zrh")
+    );
+    assert_eq!(
+        at(map::nearest(&cities, 2, Direction::Up)),
+        city("se", "mma")
+    );
+    // Nothing straight ahead, so the closest city on that side.
+    assert_eq!(
+        at(map::nearest(&cities, 0, Direction::Right)),
+        city("se", "mma")
+    );
+    assert_eq!(map::nearest(&cities, 0, Direction::Up), None);
+    assert_eq!(map::nearest(&[], 0, Direction::Up), None);
+}
+
+#[test]
+fn profile_settings_and_changes() {
+    use crate::{
+        data::Location,
+        filter::RelayFilter,
+        profile::{self, DaemonSettings, DnsSettings, Profile, Settings},
+    };
+
+    let dns = profile::parse_dns(
+        "Custom DNS: no\nBlock ads: true\nBlock trackers: false\nBlock gambling: true\n",
+    );
+    assert!(dns.block_ads && dns.block_gambling && !dns.block_trackers);
+    assert_eq!(dns.to_string(), "blocks ads, gambling");
+    assert_eq!(
+        dns.args(),
+        vec!["default", "--block-ads", "--block-gambling"]
+    );
+    let custom = profile::parse_dns("Custom DNS: yes\nServers:\n10.64.0.1\nfe80::1\n");
+    assert_eq!(custom.custom, vec!["10.64.0.1", "fe80::1"]);
+    assert_eq!(custom.args(), vec!["custom", "10.64.0.1", "fe80::1"]);
+    assert_eq!(DnsSettings::default().to_string(), "no blocking");
+
+    assert!(profile::parse_toggle(
+        "Block traffic when the VPN is disconnected: on\n"
+    ));
+    assert!(!profile::parse_toggle("Autoconnect: off\n"));
+    let relay_get =
+        "Generic constraints\n\tLocation: city Gothenburg, Sweden\n\tTunnel protocol: WireGuard\n";
+    assert_eq!(
+        profile::parse_tunnel_protocol(relay_get).as_deref(),
+        Some("wireguard")
+    );
+
+    let countries = crate::data::parse_relay_list(RELAY_LIST);
+    let mut favorites = std::collections::BTreeMap::new();
+    favorites.insert("work".to_string(), "ch zrh".to_string());
+    let work: Profile = toml::from_str(
+        "location = \"fav:work\"\ntunnel_protocol = \"WireGuard\"\nlockdown = true\nfilter = { owned = true }\n",
+    )
+    .unwrap();
+    let location = work.location(&countries, &favorites).unwrap();
+    assert_eq!(
+        location,
+        Some(Location::City("ch".to_string(), "zrh".to_string()))
+    );
+
+    let current = Settings {
+        location: Some(Location::Relay(
+            "se".to_string(),
+            "got".to_string(),
+            "se-got-wg-001".to_string(),
+        )),
+        filter: Some(RelayFilter::default()),
+        daemon: DaemonSettings {
+            tunnel_protocol: Some("wireguard".to_string()),
+            lockdown: Some(false),
+            ..DaemonSettings::default()
+        },
+    };
+    let changes: Vec<String> = work
+        .changes(&current, location.as_ref())
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            "Location: se got se-got-wg-001 -> ch zrh",
+            "Filter: none -> owned",
+            "Lockdown mode: off -> on"
+        ]
+    );
+    // Already in Gothenburg, so connecting to Sweden keeps the relay.
+    let sweden = Location::Country("se".to_string());
+    assert!(work.changes(&current, Some(&sweden))[0].setting != "Location");
+    // Outside the TUI the filter is unknown and left out.
+    let cli = Settings {
+        filter: None,
+        ..current
+    };
+    assert_eq!(work.changes(&cli, location.as_ref()).len(), 2);
+
+    let bad = Profile {
+        location: Some("fav:nope".to_string()),
+        ..Profile::default()
+    };
+    assert!(bad.location(&countries, &favorites).is_err());
+}
+
+#[test]
+fn export_round_trip_and_merge() {
+    use crate::{
+        config::Config,
+        export::{Export, ExportFormat},
+        profile::DaemonSettings,
+    };
+
+    let config = Config::load(Some("config-example.toml")).unwrap();
+    let daemon = DaemonSettings {
+        tunnel_protocol: Some("any".to_string()),
+        lockdown: Some(false),
+        bridge_mode: Some("auto".to_string()),
+        ..DaemonSettings::default()
+    };
+    let mut export = Export {
+        daemon: daemon.clone(),
+        favorites: [("work".to_string(), "se got".to_string())].into(),
+        profiles: config.profiles.clone(),
+        theme: Some(config.colors.clone()),
+        history: vec!["connect se".to_string(), "disconnect".to_string()],
+    };
+    for format in [ExportFormat::Toml, ExportFormat::Json] {
+        let text = export.to_string(format).unwrap();
+        assert_eq!(Export::parse(&text, format).unwrap(), export);
+    }
+
+    // Only what differs shows up, and local-only entries are kept.
+    let history = vec!["disconnect".to_string(), "reconnect".to_string()];
+    let changes: Vec<String> = export
+        .changes(&daemon, Some(&config), &history)
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            "Favorite work: unset -> se got",
+            "History: 2 commands -> 3 commands"
+        ]
+    );
+    assert_eq!(export.new_history(&history), vec!["connect se"]);
+    export.daemon.lockdown = Some(true);
+    assert_eq!(
+        export.changes(&daemon, Some(&config), &history)[0].to_string(),
+        "Lockdown mode: off -> on"
+    );
+    let unchanged = export.daemon.without_unchanged(&daemon);
+    assert_eq!(
+        unchanged,
+        DaemonSettings {
+            lockdown: Some(true),
+            ..DaemonSettings::default()
+        }
+    );
+
+    // Merging keeps comments and writes a config that loads with the imported state.
+    let text = std::fs::read_to_string("config-example.toml").unwrap();
+    let merged = export.merge_config(&text, Some(&config)).unwrap();
+    assert!(merged.starts_with(&text[..text.find("# work").unwrap()]));
+    assert!(merged.ends_with(&text[text.find("# The relays").unwrap()..]));
+    assert!(merged.contains("[favorites]\nwork = \"se got\"\n# work = \"se got\"\n"));
+    let merged: Config = toml::from_str(&merged).unwrap();
+    assert_eq!(merged.favorites, export.favorites);
+    assert_eq!(merged.profiles, export.profiles);
+
+    let fresh = export.merge_config("", None).unwrap();
+    assert!(!fresh.contains("[profiles]\n"));
+    let fresh: Config = toml::from_str(&fresh).unwrap();
+    assert_eq!(Some(fresh.colors), export.theme);
+    assert_eq!(fresh.profiles, export.profiles);
+}
@@ -0,0 +1,963 @@
+use std::{path::PathBuf, sync::Arc};
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{backend::TestBackend, Terminal};
+
+use crate::{
+    app::{App, InputMode, View},
+    config::Config,
+    logging::Level,
+    worker::WorkerEvent,
+};
+
+use super::fake::{FakeBackend, FakeClock, FakeNotifier};
+
+const WIDTH: u16 = 100;
+const HEIGHT: u16 = 24;
+
+fn app(backend: FakeBackend) -> (App, Arc<FakeBackend>) {
+    let backend = Arc::new(backend);
+    let app = App::new(Config::default(), backend.clone()).unwrap();
+    (app, backend)
+}
+
+fn press(app: &mut App, codes: &[KeyCode]) {
+    for code in codes {
+        app.handle_key_event(KeyEvent::from(*code)).unwrap();
+    }
+}
+
+fn type_str(app: &mut App, s: &str) {
+    for c in s.chars() {
+        press(app, &[KeyCode::Char(c)]);
+    }
+}
+
+fn render(app: &mut App) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap();
+    terminal.draw(|f| app.draw(f)).unwrap();
+
+    let buffer = terminal.backend().buffer();
+    let mut out = String::new();
+    for y in 0..buffer.area.height {
+        let line: String = (0..buffer.area.width)
+            .map(|x| buffer[(x, y)].symbol())
+            .collect();
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Compares `actual` with `snapshots/<name>.snap`.
+///
+/// Run with `UPDATE_SNAPSHOTS=1` to write the current output instead.
+fn assert_snapshot(name: &str, actual: &str) {
+    let path: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "src",
+        "tests",
+        "snapshots",
+        name,
+    ]
+    .iter()
+    .collect::<PathBuf>()
+    .with_extension("snap");
+
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "missing snapshot {}, run with UPDATE_SNAPSHOTS=1",
+            path.display()
+        )
+    });
+    assert_eq!(expected, actual, "snapshot {} does not match", name);
+}
+
+#[test]
+fn snapshot_countries() {
+    let (mut app, _) = app(FakeBackend::default());
+    assert_snapshot("countries", &render(&mut app));
+}
+
+#[test]
+fn snapshot_cities() {
+    let (mut app, _) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Enter]);
+    assert_snapshot("cities", &render(&mut app));
+}
+
+#[test]
+fn snapshot_relays() {
+    let (mut app, _) = app(FakeBackend::default());
+    press(
+        &mut app,
+        &[KeyCode::Enter, KeyCode::Char('l'), KeyCode::Down],
+    );
+    assert_snapshot("relays", &render(&mut app));
+}
+
+#[test]
+fn snapshot_connection() {
+    let (mut app, _) = app(FakeBackend::connected());
+    press(&mut app, &[KeyCode::Enter, KeyCode::Enter]);
+    assert_snapshot("connection", &render(&mut app));
+}
+
+#[test]
+fn snapshot_search_mode() {
+    let (mut app, _) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Char('/')]);
+    type_str(&mut app, "swi");
+    assert_snapshot("search_mode", &render(&mut app));
+}
+
+#[test]
+fn snapshot_custom_lists() {
+    let backend = FakeBackend::default();
+    *backend.custom_lists.lock().unwrap() =
+        "EU low-latency\n\tSwitzerland (ch)\n\tMalmö (mma), Sweden\n".to_string();
+    let (mut app, _) = app(backend);
+    press(&mut app, &[KeyCode::Char('c')]);
+    assert_snapshot("custom_lists", &render(&mut app));
+}
+
+#[test]
+fn snapshot_error_popup() {
+    let backend = FakeBackend {
+        failing: vec!["connect".to_string()],
+        ..FakeBackend::default()
+    };
+    let (mut app, _) = app(backend);
+    press(&mut app, &[KeyCode::Enter]);
+    // The event loop turns errors from key handlers into the popup.
+    let error = app
+        .handle_key_event(KeyEvent::from(KeyCode::Enter))
+        .unwrap_err();
+    app.show_error(error);
+    assert_snapshot("error_popup", &render(&mut app));
+
+    // Any key dismisses the popup without acting on it.
+    press(&mut app, &[KeyCode::Char('q')]);
+    assert!(!app.should_exit());
+}
+
+#[test]
+fn snapshot_help() {
+    let (mut app, _) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Char('?')]);
+    assert_snapshot("help", &render(&mut app));
+
+    // Any key closes the help without acting on it.
+    press(&mut app, &[KeyCode::Char('q')]);
+    assert!(!app.should_exit());
+    assert!(!render(&mut app).contains("Random favorite"));
+
+    // In search mode `?` is part of the query.
+    press(&mut app, &[KeyCode::Char('/'), KeyCode::Char('?')]);
+    assert!(render(&mut app).contains("Search: ?"));
+}
+
+#[test]
+fn narrow_terminal_hides_details() {
+    let (mut app, _) = app(FakeBackend::default());
+    let mut terminal = Terminal::new(TestBackend::new(60, HEIGHT)).unwrap();
+    terminal.draw(|f| app.draw(f)).unwrap();
+    let buffer = terminal.backend().buffer();
+    let text: String = buffer.content().iter().map(|c| c.symbol()).collect();
+    assert!(text.contains("Sweden (se)"));
+    assert!(!text.contains("Providers"));
+}
+
+#[test]
+fn search_then_connect_to_city() {
+    let (mut app, backend) = app(FakeBackend::default());
+
+    press(&mut app, &[KeyCode::Char('/')]);
+    assert_eq!(*app.input_mode(), InputMode::Search);
+    type_str(&mut app, "swe");
+
+    press(&mut app, &[KeyCode::Enter]);
+    assert_eq!(*app.input_mode(), InputMode::Normal);
+    assert_eq!(*app.view_mode(), View::Cities);
+
+    press(&mut app, &[KeyCode::Down, KeyCode::Enter]);
+    assert_eq!(*app.view_mode(), View::Connection);
+    assert_eq!(
+        backend.calls(),
+        vec!["relay set location se mma", "connect"]
+    );
+}
+
+#[test]
+fn connect_to_relay() {
+    let (mut app, backend) = app(FakeBackend::default());
+    press(
+        &mut app,
+        &[KeyCode::Down, KeyCode::Enter, KeyCode::Char('l')],
+    );
+    assert_eq!(*app.view_mode(), View::Relays);
+
+    press(&mut app, &[KeyCode::Enter]);
+    assert_eq!(
+        backend.calls(),
+        vec!["relay set location ch zrh ch-zrh-wg-001", "connect"]
+    );
+}
+
+#[test]
+fn back_navigation() {
+    let (mut app, _) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Enter, KeyCode::Char('l')]);
+    assert_eq!(*app.view_mode(), View::Relays);
+    press(&mut app, &[KeyCode::Char('h')]);
+    assert_eq!(*app.view_mode(), View::Cities);
+    press(&mut app, &[KeyCode::Char('h')]);
+    assert_eq!(*app.view_mode(), View::Countries);
+}
+
+#[test]
+fn create_custom_list_and_add_location() {
+    let (mut app, backend) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Char('c'), KeyCode::Char('n')]);
+    assert_eq!(*app.input_mode(), InputMode::ListName);
+    type_str(&mut app, "work");
+    press(&mut app, &[KeyCode::Enter, KeyCode::Char('h')]);
+
+    press(
+        &mut app,
+        &[KeyCode::Char('a'), KeyCode::Enter, KeyCode::Esc],
+    );
+    assert_eq!(
+        backend.calls(),
+        vec!["custom-list new work", "custom-list edit add work se"]
+    );
+}
+
+#[test]
+fn add_to_custom_list_from_map_and_connection() {
+    let backend = FakeBackend::connected();
+    *backend.custom_lists.lock().unwrap() = "work\n".to_string();
+    let (mut app, backend) = app(backend);
+
+    // The map starts at the connected city.
+    press(
+        &mut app,
+        &[
+            KeyCode::Char('m'),
+            KeyCode::Char('a'),
+            KeyCode::Enter,
+            KeyCode::Esc,
+            KeyCode::Enter,
+        ],
+    );
+    assert_eq!(*app.view_mode(), View::Connection);
+    press(&mut app, &[KeyCode::Char('a'), KeyCode::Enter]);
+    assert_eq!(
+        backend.calls(),
+        vec![
+            "custom-list edit add work se got",
+            "relay set location se got",
+            "connect",
+            "custom-list edit add work se got se-got-wg-001",
+        ]
+    );
+}
+
+#[test]
+fn disconnect_updates_status() {
+    let (mut app, backend) = app(FakeBackend::connected());
+    press(&mut app, &[KeyCode::Char('D')]);
+    assert_eq!(backend.calls(), vec!["disconnect"]);
+    assert!(render(&mut app).contains("Disconnected"));
+}
+
+#[test]
+fn manual_relay_refresh() {
+    let (mut app, backend) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Char('r')]);
+    assert!(render(&mut app).contains("refreshing relays..."));
+
+    for _ in 0..100 {
+        app.handle_worker_events();
+        if !render(&mut app).contains("refreshing relays...") {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(!render(&mut app).contains("refreshing relays..."));
+    assert_eq!(backend.calls(), vec!["relay update"]);
+}
+
+fn notifying_app(backend: FakeBackend) -> (App, Arc<FakeBackend>, FakeNotifier) {
+    let backend = Arc::new(backend);
+    let mut config = Config::default();
+    config.notifications.enabled = true;
+    config.notifications.connected = false;
+    let mut app = App::new(config, backend.clone()).unwrap();
+    let notifier = FakeNotifier::default();
+    app.set_notifier(Box::new(notifier.clone()));
+    (app, backend, notifier)
+}
+
+#[test]
+fn notify_when_tunnel_drops() {
+    let (mut app, _, notifier) = notifying_app(FakeBackend::connected());
+    let status = crate::data::parse_status("Disconnected\n");
+    app.events_tx()
+        .send(WorkerEvent::Status(Box::new(status)))
+        .unwrap();
+    app.handle_worker_events();
+    assert_eq!(notifier.summaries(), vec!["Mullvad VPN disconnected"]);
+}
+
+#[test]
+fn no_notification_for_requested_or_disabled_events() {
+    let (mut app, _, notifier) = notifying_app(FakeBackend::connected());
+    press(&mut app, &[KeyCode::Char('D')]);
+    // Connecting is turned off in the config.
+    press(&mut app, &[KeyCode::Enter, KeyCode::Enter]);
+    assert!(notifier.summaries().is_empty());
+}
+
+#[test]
+fn hook_output_is_logged() {
+    let backend = Arc::new(FakeBackend::default());
+    let mut config = Config::default();
+    config.hooks.on_connect = Some("echo hello from $MULLVAD_HOSTNAME".to_string());
+    let mut app = App::new(config, backend).unwrap();
+
+    press(&mut app, &[KeyCode::Enter, KeyCode::Enter]);
+    let log = || -> Vec<String> {
+        crate::logging::recent(Level::Info, usize::MAX)
+            .iter()
+            .map(|e| e.to_string())
+            .collect()
+    };
+    for _ in 0..200 {
+        app.handle_worker_events();
+        if log().iter().any(|l| l.contains("on_connect exited")) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    let log = log();
+    assert!(
+        log.iter()
+            .any(|l| l.starts_with("hook: on_connect exited with 0")),
+        "{:?}",
+        log
+    );
+    assert!(log.iter().any(|l| l == "hook:   hello from se-got-wg-001"));
+}
+
+#[test]
+fn log_pane_level_filter() {
+    let (mut app, _) = app(FakeBackend::default());
+    crate::logging::error("test", "log pane error");
+    crate::logging::info("test", "log pane info");
+
+    press(&mut app, &[KeyCode::Char('L')]);
+    assert!(render(&mut app).contains("Log: info and above"));
+
+    // Other tests log too, so only the error filter gives a predictable pane.
+    press(&mut app, &[KeyCode::Char('v'), KeyCode::Char('v')]);
+    let screen = render(&mut app);
+    assert!(screen.contains("Log: error and above"));
+    assert!(screen.contains("test: log pane error"));
+    assert!(!screen.contains("test: log pane info"));
+
+    press(&mut app, &[KeyCode::Char('L')]);
+    assert!(!render(&mut app).contains("Log:"));
+}
+
+#[test]
+fn ctrl_z_is_not_typed() {
+    use crossterm::event::KeyModifiers;
+
+    let (mut app, _) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Char('/')]);
+    app.handle_key_event(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL))
+        .unwrap();
+    assert_eq!(*app.input_mode(), InputMode::Search);
+    assert!(render(&mut app).contains("Search:  |"));
+}
+
+#[test]
+fn command_line_connects() {
+    let (mut app, backend) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Char(':')]);
+    assert_eq!(*app.input_mode(), InputMode::Command);
+    type_str(&mut app, "connect se mma");
+    assert!(render(&mut app).contains(":connect se mma |"));
+    press(&mut app, &[KeyCode::Enter]);
+    assert_eq!(*app.input_mode(), InputMode::Normal);
+    assert_eq!(
+        backend.calls(),
+        vec!["relay set location se mma", "connect"]
+    );
+
+    let (mut app, backend) = app_with_favorites();
+    press(&mut app, &[KeyCode::Char(':')]);
+    type_str(&mut app, "connect fav:work");
+    press(&mut app, &[KeyCode::Enter]);
+    assert_eq!(
+        backend.calls(),
+        vec!["relay set location ch zrh", "connect"]
+    );
+
+    press(&mut app, &[KeyCode::Char(':')]);
+    type_str(&mut app, "connect se-got-wg-001");
+    press(&mut app, &[KeyCode::Enter]);
+    assert_eq!(
+        backend.calls()[2],
+        "relay set location se got se-got-wg-001"
+    );
+}
+
+fn app_with_favorites() -> (App, Arc<FakeBackend>) {
+    let mut config = Config::default();
+    config
+        .favorites
+        .insert("work".to_string(), "ch zrh".to_string());
+    let backend = Arc::new(FakeBackend::default());
+    (App::new(config, backend.clone()).unwrap(), backend)
+}
+
+#[test]
+fn command_line_errors_and_other_commands() {
+    let (mut app, backend) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Char(':')]);
+    type_str(&mut app, "connect xx");
+    let error = app
+        .handle_key_event(KeyEvent::from(KeyCode::Enter))
+        .unwrap_err();
+    assert_eq!(error.to_string(), "Unknown location: xx");
+    assert!(backend.calls().is_empty());
+
+    press(&mut app, &[KeyCode::Char(':')]);
+    type_str(&mut app, "set protocol openvpn");
+    press(&mut app, &[KeyCode::Enter, KeyCode::Char(':')]);
+    type_str(&mut app, "reconnect");
+    press(&mut app, &[KeyCode::Enter]);
+    assert_eq!(
+        backend.calls(),
+        vec!["relay set tunnel-protocol openvpn", "reconnect"]
+    );
+
+    press(&mut app, &[KeyCode::Char(':'), KeyCode::Up]);
+    assert!(render(&mut app).contains(":reconnect |"));
+    press(&mut app, &[KeyCode::Up, KeyCode::Up]);
+    assert!(render(&mut app).contains(":set protocol openvpn |"));
+    press(&mut app, &[KeyCode::Down, KeyCode::Down, KeyCode::Down]);
+    assert!(render(&mut app).contains(": |"));
+    press(&mut app, &[KeyCode::Esc]);
+    assert_eq!(backend.calls().len(), 2);
+}
+
+#[test]
+fn command_line_tab_completion() {
+    let (mut app, _) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Char(':')]);
+    type_str(&mut app, "con");
+    press(&mut app, &[KeyCode::Tab]);
+    type_str(&mut app, "se ");
+    press(&mut app, &[KeyCode::Tab]);
+    let screen = render(&mut app);
+    assert!(screen.contains(":connect se got |"));
+    assert!(screen.contains(" got  mma "));
+
+    press(&mut app, &[KeyCode::Tab]);
+    assert!(render(&mut app).contains(":connect se mma |"));
+    press(&mut app, &[KeyCode::Char(' ')]);
+    assert!(!render(&mut app).contains(" got  mma "));
+}
+
+#[test]
+fn random_relays_honor_the_filter() {
+    let mut config = Config::default();
+    config
+        .favorites
+        .insert("a".to_string(), "se got".to_string());
+    config.favorites.insert("b".to_string(), "ch".to_string());
+    let backend = Arc::new(FakeBackend::connected());
+    let mut app = App::new(config, backend.clone()).unwrap();
+
+    // The only other relay in Gothenburg.
+    press(&mut app, &[KeyCode::Char('s')]);
+    assert_eq!(
+        backend.calls(),
+        vec!["relay set location se got se-got-ovpn-001", "connect"]
+    );
+
+    press(&mut app, &[KeyCode::Char(':')]);
+    type_str(&mut app, "filter wireguard owned");
+    press(&mut app, &[KeyCode::Enter, KeyCode::Char('S')]);
+    assert_eq!(
+        backend.calls()[2],
+        "relay set location se mma se-mma-wg-001"
+    );
+
+    // Switzerland is the favorite in another country, but only has a rented relay.
+    let error = app
+        .handle_key_event(KeyEvent::from(KeyCode::Char('f')))
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "No other relay in this favorite matches the filter (WireGuard, owned)"
+    );
+    press(&mut app, &[KeyCode::Char(':')]);
+    type_str(&mut app, "filter");
+    press(&mut app, &[KeyCode::Enter, KeyCode::Char('f')]);
+    assert_eq!(
+        backend.calls()[4],
+        "relay set location ch zrh ch-zrh-wg-001"
+    );
+}
+
+#[test]
+fn reconnect_confirms_a_new_exit_ip() {
+    let (mut app, backend) = app(FakeBackend::connected());
+    press(
+        &mut app,
+        &[KeyCode::Char('R'), KeyCode::Enter, KeyCode::Enter],
+    );
+    assert_eq!(backend.calls()[0], "reconnect");
+    assert!(render(&mut app).contains("New exit IP  waiting..."));
+
+    let status = |output: &str| WorkerEvent::Status(Box::new(crate::data::parse_status(output)));
+    app.events_tx()
+        .send(status("Connecting to se-got-wg-001\n"))
+        .unwrap();
+    let connected = super::fake::CONNECTED.replace("185.213.154.68", "185.213.154.70");
+    app.events_tx().send(status(&connected)).unwrap();
+    app.handle_worker_events();
+    assert!(render(&mut app).contains("New exit IP  yes, was 185.213.154.68"));
+}
+
+#[test]
+fn scheduled_rotation() {
+    use std::time::Duration;
+
+    let backend = FakeBackend::connected();
+    *backend.custom_lists.lock().unwrap() =
+        "travel\n\tSweden (se)\n\tSwitzerland (ch)\n".to_string();
+    let (mut app, backend) = app(backend);
+    let clock = FakeClock::default();
+    app.set_clock(Box::new(clock.clone()));
+
+    press(&mut app, &[KeyCode::Char(':')]);
+    type_str(&mut app, "rotate 5 round-robin list travel");
+    press(&mut app, &[KeyCode::Enter]);
+    assert!(render(&mut app).contains("next relay in 05:00"));
+
+    clock.advance(Duration::from_secs(299));
+    app.rotate_if_due().unwrap();
+    assert!(render(&mut app).contains("next relay in 00:01"));
+    let calls = backend.calls().len();
+
+    // The current relay, se-got-wg-001, is skipped.
+    clock.advance(Duration::from_secs(1));
+    app.rotate_if_due().unwrap();
+    assert_eq!(
+        backend.calls()[calls..],
+        ["relay set location se got se-got-ovpn-001", "connect"]
+    );
+    assert!(render(&mut app).contains("next relay in 05:00"));
+
+    press(&mut app, &[KeyCode::Char('p')]);
+    clock.advance(Duration::from_secs(600));
+    app.rotate_if_due().unwrap();
+    assert!(render(&mut app).contains("rotation paused"));
+    assert_eq!(backend.calls().len(), calls + 2);
+
+    press(&mut app, &[KeyCode::Char('p')]);
+    clock.advance(Duration::from_secs(300));
+    app.rotate_if_due().unwrap();
+    assert_eq!(
+        backend.calls()[calls + 2],
+        "relay set location se mma se-mma-wg-001"
+    );
+
+    press(&mut app, &[KeyCode::Char(':')]);
+    type_str(&mut app, "rotate stop");
+    press(&mut app, &[KeyCode::Enter]);
+    assert!(!render(&mut app).contains("next relay"));
+}
+
+#[test]
+fn rotation_stops_when_its_list_is_deleted() {
+    use std::time::Duration;
+
+    let backend = FakeBackend::connected();
+    *backend.custom_lists.lock().unwrap() = "travel\n\tSweden (se)\n".to_string();
+    let (mut app, backend) = app(backend);
+    let clock = FakeClock::default();
+    app.set_clock(Box::new(clock.clone()));
+
+    press(&mut app, &[KeyCode::Char(':')]);
+    type_str(&mut app, "rotate 5 list travel");
+    press(
+        &mut app,
+        &[KeyCode::Enter, KeyCode::Char('c'), KeyCode::Char('x')],
+    );
+    assert_eq!(backend.calls(), vec!["custom-list delete travel"]);
+
+    clock.advance(Duration::from_secs(300));
+    let error = app.rotate_if_due().unwrap_err();
+    assert!(error.to_string().contains("travel"), "{}", error);
+    assert!(!render(&mut app).contains("next relay"));
+    // Other tests log to the same buffer, so look for this stop among them.
+    let stopped = format!("Stopped rotating through list travel: {}", error);
+    let entries = crate::logging::recent(Level::Debug, 500);
+    let entry = entries.iter().find(|e| e.message == stopped).unwrap();
+    assert_eq!(entry.level, Level::Warn);
+
+    clock.advance(Duration::from_secs(300));
+    app.rotate_if_due().unwrap();
+    assert_eq!(backend.calls().len(), 1);
+}
+
+#[test]
+fn leak_check_warns_when_not_on_mullvad() {
+    use std::time::{Duration, Instant};
+
+    let body = r#"{"ip": "203.0.113.7", "mullvad_exit_ip": false}"#.to_string();
+    let url = super::fake::http_stub(vec![("/json", body)]);
+    let mut config = Config::default();
+    config.leak_check.enabled = true;
+    config.leak_check.url = format!("{}/json", url);
+    config.leak_check.dns_url = Some(String::new());
+    let backend = Arc::new(FakeBackend::default());
+    let mut app = App::new(config, backend).unwrap();
+
+    // Connecting starts the check.
+    press(&mut app, &[KeyCode::Enter, KeyCode::Enter]);
+    assert!(render(&mut app).contains("Leak check  checking..."));
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while render(&mut app).contains("checking...") && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+        app.handle_worker_events();
+    }
+    let screen = render(&mut app);
+    assert!(screen.contains("NOT Mullvad, exit 203.0.113.7"));
+    assert!(screen.contains("which is not a Mullvad server"));
+    press(&mut app, &[KeyCode::Esc]);
+    assert!(render(&mut app).contains("DNS  not checked"));
+}
+
+#[test]
+fn snapshot_bridges() {
+    let backend = FakeBackend::connected();
+    *backend.status.lock().unwrap() = format!(
+        "{}Obfuscation:            Udp2Tcp\n",
+        super::fake::CONNECTED
+    );
+    let (mut app, _) = app(backend);
+    press(&mut app, &[KeyCode::Char('b')]);
+    assert_snapshot("bridges", &render(&mut app));
+}
+
+#[test]
+fn change_bridge_and_obfuscation_settings() {
+    let (mut app, backend) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Char('b'), KeyCode::Enter]);
+    assert!(render(&mut app).contains("Bridge mode  on"));
+
+    // The location comes from the country and city lists.
+    press(&mut app, &[KeyCode::Down, KeyCode::Enter]);
+    assert!(render(&mut app).contains("Bridge location"));
+    press(&mut app, &[KeyCode::Enter, KeyCode::Down, KeyCode::Enter]);
+    assert!(render(&mut app).contains("Bridge location  se mma"));
+
+    press(
+        &mut app,
+        &[KeyCode::Down, KeyCode::Left, KeyCode::Down, KeyCode::Enter],
+    );
+    type_str(&mut app, "80");
+    press(&mut app, &[KeyCode::Enter, KeyCode::Down, KeyCode::Enter]);
+    type_str(&mut app, "70000");
+    let error = app
+        .handle_key_event(KeyEvent::from(KeyCode::Enter))
+        .unwrap_err();
+    assert_eq!(error.to_string(), "Invalid port: 70000");
+
+    assert_eq!(
+        backend.calls(),
+        vec![
+            "bridge set state on",
+            "bridge set location se mma",
+            "obfuscation set mode shadowsocks",
+            "obfuscation set udp2tcp --port 80",
+        ]
+    );
+    let screen = render(&mut app);
+    assert!(screen.contains("Obfuscation  shadowsocks"));
+    assert!(screen.contains("udp2tcp port  80"));
+}
+
+#[test]
+fn bridge_location_has_its_own_lists() {
+    let (mut app, backend) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Down, KeyCode::Enter]);
+    assert!(render(&mut app).contains("Zurich"));
+
+    // Picking starts from the countries, whatever the lists showed.
+    press(
+        &mut app,
+        &[KeyCode::Char('b'), KeyCode::Down, KeyCode::Enter],
+    );
+    let screen = render(&mut app);
+    assert!(screen.contains("Sweden (se)"));
+    assert!(!screen.contains("Zurich"));
+    press(&mut app, &[KeyCode::Enter, KeyCode::Char(' ')]);
+    assert!(render(&mut app).contains("Bridge location  se got"));
+    assert_eq!(backend.calls(), vec!["bridge set location se got"]);
+}
+
+#[test]
+fn snapshot_api_access() {
+    let backend = FakeBackend::default();
+    backend
+        .access_methods
+        .lock()
+        .unwrap()
+        .push(crate::api_access::AccessMethod {
+            index: 4,
+            name: "office".to_string(),
+            enabled: true,
+            details: vec![("Peer".to_string(), "192.0.2.10:1080".to_string())],
+        });
+    let (mut app, _) = app(backend);
+    press(&mut app, &[KeyCode::Char('M')]);
+    assert_snapshot("api_access", &render(&mut app));
+}
+
+#[test]
+fn manage_api_access_methods() {
+    use std::time::{Duration, Instant};
+
+    let backend = FakeBackend {
+        failing: vec!["api-access test 2".to_string()],
+        ..FakeBackend::default()
+    };
+    let (mut app, backend) = app(backend);
+    press(
+        &mut app,
+        &[
+            KeyCode::Char('M'),
+            KeyCode::Down,
+            KeyCode::Down,
+            KeyCode::Char('e'),
+        ],
+    );
+    assert!(render(&mut app).contains("Encrypted DNS proxy         enabled"));
+
+    press(&mut app, &[KeyCode::Up, KeyCode::Char('t')]);
+    assert!(render(&mut app).contains("testing..."));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while render(&mut app).contains("testing...") && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+        app.handle_worker_events();
+    }
+    assert!(render(&mut app).contains("test failed: mullvad api-access test 2: failed"));
+
+    // Invalid input keeps the form open until it is fixed.
+    press(&mut app, &[KeyCode::Char('n')]);
+    type_str(&mut app, "office");
+    press(&mut app, &[KeyCode::Tab]);
+    type_str(&mut app, "proxy.example.com");
+    press(&mut app, &[KeyCode::Tab]);
+    type_str(&mut app, "1080");
+    let error = app
+        .handle_key_event(KeyEvent::from(KeyCode::Enter))
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Host must be an IP address: proxy.example.com"
+    );
+    app.show_error(error);
+    // The first key only dismisses the error.
+    press(&mut app, &[KeyCode::Esc, KeyCode::Up]);
+    assert!(render(&mut app).contains("New SOCKS5 method"));
+    for _ in 0.."proxy.example.com".len() {
+        press(&mut app, &[KeyCode::Backspace]);
+    }
+    type_str(&mut app, "192.0.2.10");
+    press(&mut app, &[KeyCode::Enter, KeyCode::Enter]);
+
+    assert_eq!(
+        backend.calls(),
+        vec![
+            "api-access enable 3",
+            "api-access test 2",
+            "api-access add socks5 remote office 192.0.2.10 1080",
+        ]
+    );
+    let screen = render(&mut app);
+    assert!(!screen.contains("New SOCKS5 method"));
+    assert!(screen.contains("Peer: 192.0.2.10:1080"));
+}
+
+#[test]
+fn api_access_scrolls_to_selection() {
+    let backend = FakeBackend::default();
+    backend
+        .access_methods
+        .lock()
+        .unwrap()
+        .extend((0..12).map(|i| crate::api_access::AccessMethod {
+            index: 4 + i,
+            name: format!("proxy {}", i),
+            enabled: true,
+            details: vec![("Peer".to_string(), format!("192.0.2.{}:1080", i))],
+        }));
+    let count = backend.access_methods.lock().unwrap().len();
+    let (mut app, _) = app(backend);
+    press(&mut app, &[KeyCode::Char('M')]);
+    for _ in 1..count {
+        press(&mut app, &[KeyCode::Down]);
+    }
+    // The last method scrolls into view together with its details.
+    let screen = render(&mut app);
+    assert!(screen.contains("proxy 11"), "{}", screen);
+    assert!(screen.contains("Peer: 192.0.2.11:1080"), "{}", screen);
+    assert!(!screen.contains("proxy 0 "), "{}", screen);
+}
+
+#[test]
+fn jump_to_end_of_empty_search() {
+    let (mut app, backend) = app(FakeBackend::default());
+    press(&mut app, &[KeyCode::Char('/')]);
+    type_str(&mut app, "atlantis");
+    press(
+        &mut app,
+        &[
+            KeyCode::Esc,
+            KeyCode::Char('G'),
+            KeyCode::Char('g'),
+            KeyCode::Char('g'),
+            KeyCode::Enter,
+        ],
+    );
+    assert_eq!(*app.view_mode(), View::Countries);
+    assert!(backend.calls().is_empty());
+}
+
+#[test]
+fn snapshot_tree_view() {
+    let (mut app, backend) = app(FakeBackend::default());
+    press(
+        &mut app,
+        &[
+            KeyCode::Char('T'),
+            KeyCode::Right,
+            KeyCode::Down,
+            KeyCode::Right,
+        ],
+    );
+    assert_eq!(*app.view_mode(), View::Cities);
+    press(&mut app, &[KeyCode::Right]);
+    assert_eq!(*app.view_mode(), View::Relays);
+    assert_snapshot("tree_view", &render(&mut app));
+
+    // Left goes to the city, closes it, then goes up to the country.
+    press(&mut app, &[KeyCode::Left, KeyCode::Left]);
+    assert_eq!(*app.view_mode(), View::Cities);
+    assert!(render(&mut app).contains("▸ Gothenburg (got) [2]"));
+    press(&mut app, &[KeyCode::Left, KeyCode::Left]);
+    assert_eq!(*app.view_mode(), View::Countries);
+    assert!(render(&mut app).contains("▸ Sweden (se) [3]"));
+
+    // Searching opens the way to the matches and Enter keeps the match selected.
+    press(&mut app, &[KeyCode::Char('/')]);
+    type_str(&mut app, "zrh-wg");
+    let screen = render(&mut app);
+    assert!(screen.contains("▾ Switzerland (ch) [1]"));
+    assert!(screen.contains("ch-zrh-wg-001"));
+    assert!(!screen.contains("Sweden"));
+    press(&mut app, &[KeyCode::Enter]);
+    assert_eq!(*app.view_mode(), View::Relays);
+    assert!(render(&mut app).contains("Sweden"));
+    press(&mut app, &[KeyCode::Enter]);
+    assert_eq!(
+        backend.calls(),
+        vec!["relay set location ch zrh ch-zrh-wg-001", "connect"]
+    );
+}
+
+#[test]
+fn snapshot_map() {
+    let (mut app, backend) = app(FakeBackend::connected());
+    press(&mut app, &[KeyCode::Char('m')]);
+    assert_eq!(*app.view_mode(), View::Map);
+    // Starts at the connected city, Gothenburg; Malmö is south of it.
+    press(&mut app, &[KeyCode::Down]);
+    assert_snapshot("map", &render(&mut app));
+
+    press(&mut app, &[KeyCode::Down, KeyCode::Enter]);
+    assert_eq!(*app.view_mode(), View::Connection);
+    assert_eq!(
+        backend.calls(),
+        vec!["relay set location ch zrh", "connect"]
+    );
+}
+
+#[test]
+fn profile_picker_previews_and_applies() {
+    let config = Config {
+        profiles: toml::from_str(
+            "[work]\nlocation = \"se got\"\ntunnel_protocol = \"wireguard\"\nlockdown = true\n\
+             dns = { block_ads = true, block_trackers = true }\nfilter = { owned = true }\n\
+             [streaming]\nlocation = \"ch zrh\"\ndns = {}\n",
+        )
+        .unwrap(),
+        ..Config::default()
+    };
+    let backend = Arc::new(FakeBackend::connected());
+    let mut app = App::new(config, backend.clone()).unwrap();
+
+    press(&mut app, &[KeyCode::Char('P')]);
+    assert_eq!(*app.view_mode(), View::Profiles);
+    press(&mut app, &[KeyCode::Down]);
+    assert_snapshot("profiles", &render(&mut app));
+
+    // Already on a relay in Gothenburg, so the tunnel stays where it is.
+    press(&mut app, &[KeyCode::Enter]);
+    assert_eq!(*app.view_mode(), View::Connection);
+    assert_eq!(
+        backend.calls(),
+        vec![
+            "relay set tunnel-protocol wireguard",
+            "dns set default --block-ads --block-trackers",
+            "lockdown-mode set on",
+        ]
+    );
+    assert!(render(&mut app).contains("Filter  owned"));
+
+    // Nothing left to change for the same profile.
+    press(&mut app, &[KeyCode::Char('P'), KeyCode::Down]);
+    assert!(render(&mut app).contains("Nothing to change."));
+
+    // Another city does move the tunnel.
+    let calls = backend.calls().len();
+    press(&mut app, &[KeyCode::Up, KeyCode::Enter]);
+    assert_eq!(
+        backend.calls()[calls..],
+        ["dns set default", "relay set location ch zrh", "connect"]
+    );
+}
+
+#[test]
+fn profile_picker_without_profiles() {
+    let (mut app, _) = app(FakeBackend::default());
+    let error = app
+        .handle_key_event(KeyEvent::from(KeyCode::Char('P')))
+        .unwrap_err();
+    assert!(error.to_string().contains("No profiles yet"));
+    assert_eq!(*app.view_mode(), View::Countries);
+}
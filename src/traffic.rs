@@ -0,0 +1,156 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+pub const SYSFS_NET: &str = "/sys/class/net";
+
+/// Interfaces the Mullvad daemon creates, in order of preference.
+const INTERFACES: [&str; 2] = ["wg0-mullvad", "wg-mullvad"];
+
+/// Number of rate samples kept for the graphs.
+const HISTORY: usize = 120;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+/// Tracks throughput of the tunnel interface from its sysfs byte counters.
+#[derive(Debug)]
+pub struct TrafficMonitor {
+    root: PathBuf,
+    interface: Option<String>,
+    last: Option<(Instant, Counters)>,
+    session_start: Option<Counters>,
+    pub rx_rates: VecDeque<u64>,
+    pub tx_rates: VecDeque<u64>,
+}
+
+impl TrafficMonitor {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            interface: None,
+            last: None,
+            session_start: None,
+            rx_rates: VecDeque::with_capacity(HISTORY),
+            tx_rates: VecDeque::with_capacity(HISTORY),
+        }
+    }
+
+    pub fn interface(&self) -> Option<&str> {
+        self.interface.as_deref()
+    }
+
+    /// Finds the tunnel interface: a known Mullvad name, else the first `tun*`.
+    fn find_interface(root: &Path) -> Option<String> {
+        if let Some(name) = INTERFACES.iter().find(|name| root.join(name).is_dir()) {
+            return Some(name.to_string());
+        }
+        let mut tuns: Vec<String> = fs::read_dir(root)
+            .ok()?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.starts_with("tun"))
+            .collect();
+        tuns.sort();
+        tuns.into_iter().next()
+    }
+
+    fn read_counters(&self, interface: &str) -> Option<Counters> {
+        let read = |name: &str| -> Option<u64> {
+            let path = self.root.join(interface).join("statistics").join(name);
+            fs::read_to_string(path).ok()?.trim().parse().ok()
+        };
+        Some(Counters {
+            rx_bytes: read("rx_bytes")?,
+            tx_bytes: read("tx_bytes")?,
+        })
+    }
+
+    /// Samples the counters, recording a rate once per second.
+    pub fn tick(&mut self, now: Instant) {
+        if let Some((at, _)) = self.last {
+            if now.duration_since(at) < SAMPLE_INTERVAL {
+                return;
+            }
+        }
+
+        if self.interface.is_none() {
+            self.interface = Self::find_interface(&self.root);
+        }
+        let Some(counters) = self
+            .interface
+            .as_deref()
+            .and_then(|i| self.read_counters(i))
+        else {
+            self.reset();
+            return;
+        };
+
+        if let Some((at, last)) = self.last {
+            let secs = now.duration_since(at).as_secs_f64();
+            let rate = |new: u64, old: u64| (new.saturating_sub(old) as f64 / secs) as u64;
+            push(&mut self.rx_rates, rate(counters.rx_bytes, last.rx_bytes));
+            push(&mut self.tx_rates, rate(counters.tx_bytes, last.tx_bytes));
+        }
+        self.session_start.get_or_insert(counters);
+        self.last = Some((now, counters));
+    }
+
+    /// Forgets the interface and the session, e.g. after disconnecting.
+    pub fn reset(&mut self) {
+        self.interface = None;
+        self.last = None;
+        self.session_start = None;
+        self.rx_rates.clear();
+        self.tx_rates.clear();
+    }
+
+    /// Bytes received and sent since the session started.
+    pub fn session_totals(&self) -> Counters {
+        match (self.session_start, self.last) {
+            (Some(start), Some((_, last))) => Counters {
+                rx_bytes: last.rx_bytes.saturating_sub(start.rx_bytes),
+                tx_bytes: last.tx_bytes.saturating_sub(start.tx_bytes),
+            },
+            _ => Counters::default(),
+        }
+    }
+
+    pub fn rx_rate(&self) -> u64 {
+        self.rx_rates.back().copied().unwrap_or_default()
+    }
+
+    pub fn tx_rate(&self) -> u64 {
+        self.tx_rates.back().copied().unwrap_or_default()
+    }
+}
+
+fn push(history: &mut VecDeque<u64>, value: u64) {
+    if history.len() == HISTORY {
+        history.pop_front();
+    }
+    history.push_back(value);
+}
+
+/// Formats a byte count as `12.3 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    process::Command,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    backend::Backend,
+    control::Request,
+    data::{self, Country, TunnelStatus},
+    hooks::HookResult,
+    leak::LeakCheck,
+};
+
+/// Messages sent from background threads to the UI loop.
+#[derive(Debug)]
+pub enum WorkerEvent {
+    Status(Box<TunnelStatus>),
+    StatusError(String),
+    /// A freshly fetched relay list; `manual` when the user asked for it.
+    Relays {
+        result: Result<Vec<Country>, String>,
+        manual: bool,
+    },
+    /// Round trip time to a relay, `None` if it did not answer.
+    Latency(String, Option<Duration>),
+    /// A state change hook finished.
+    Hook(HookResult),
+    LeakCheck(Result<LeakCheck, String>),
+    /// Whether the API could be reached with the named access method.
+    AccessMethodTest {
+        name: String,
+        result: Result<(), String>,
+    },
+    /// A request from a control socket client; answers go to `reply`.
+    Control {
+        request: Request,
+        reply: mpsc::Sender<String>,
+    },
+}
+
+/// Polls `mullvad status` every `interval` until the receiving end is dropped.
+pub fn spawn_status_worker(
+    backend: Arc<dyn Backend>,
+    interval: Duration,
+    tx: mpsc::Sender<WorkerEvent>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || loop {
+        let event = match backend.status() {
+            Ok(status) => WorkerEvent::Status(Box::new(status)),
+            Err(e) => WorkerEvent::StatusError(e.to_string()),
+        };
+        if tx.send(event).is_err() {
+            break;
+        }
+        thread::sleep(interval);
+    })
+}
+
+/// Pings `ip` once in the background and reports the result for `hostname`.
+pub fn spawn_latency_probe(hostname: String, ip: String, tx: mpsc::Sender<WorkerEvent>) {
+    thread::spawn(move || {
+        let latency = Command::new("ping")
+            .args(["-c", "1", "-W", "1", &ip])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| data::parse_ping(&String::from_utf8_lossy(&output.stdout)));
+        let _ = tx.send(WorkerEvent::Latency(hostname, latency));
+    });
+}
+
+/// Tests an API access method in the background; it can take a while to time out.
+pub fn spawn_access_method_test(
+    backend: Arc<dyn Backend>,
+    index: usize,
+    name: String,
+    tx: mpsc::Sender<WorkerEvent>,
+) {
+    thread::spawn(move || {
+        let result = backend.test_access_method(index).map_err(|e| e.to_string());
+        let _ = tx.send(WorkerEvent::AccessMethodTest { name, result });
+    });
+}
+
+/// Fetches the relay list, running `mullvad relay update` first if `update` is set.
+pub fn spawn_relay_refresh(backend: Arc<dyn Backend>, update: bool, tx: mpsc::Sender<WorkerEvent>) {
+    thread::spawn(move || {
+        let result = if update {
+            backend.update_relays()
+        } else {
+            Ok(())
+        }
+        .and_then(|_| backend.relay_list())
+        .map_err(|e| e.to_string());
+        let _ = tx.send(WorkerEvent::Relays {
+            result,
+            manual: update,
+        });
+    });
+}
@@ -0,0 +1,493 @@
+mod common;
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixListener,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{stderr, stdout, FakeMullvad};
+
+#[test]
+fn status_disconnected() {
+    let fake = FakeMullvad::new();
+    let output = fake.run(&["status"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "State: Disconnected\n");
+    assert_eq!(fake.calls(), vec!["status -v"]);
+}
+
+#[test]
+fn list_countries_and_cities() {
+    let fake = FakeMullvad::new();
+
+    let output = fake.run(&["list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Sweden (se)\nSwitzerland (ch)\n");
+
+    let output = fake.run(&["list", "se"]);
+    assert_eq!(stdout(&output), "Gothenburg (got)\nMalmö (mma)\n");
+
+    let output = fake.run(&["list", "xx"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Unknown country: xx"));
+}
+
+#[test]
+fn connect_then_status_then_disconnect() {
+    let fake = FakeMullvad::new();
+
+    let output = fake.run(&["connect", "se", "got"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Connecting to se got\n");
+
+    let output = fake.run(&["status"]);
+    let status = stdout(&output);
+    assert!(status.contains("State: Connected"));
+    assert!(status.contains("Relay: se-got-wg-001"));
+    assert!(status.contains("Exit IPv4: 185.213.154.68"));
+
+    let output = fake.run(&["disconnect"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    assert_eq!(
+        fake.calls(),
+        vec![
+            "relay set location se got",
+            "connect",
+            "status -v",
+            "disconnect"
+        ]
+    );
+}
+
+#[test]
+fn connect_to_relay() {
+    let fake = FakeMullvad::new();
+    let output = fake.run(&["connect", "ch", "zrh", "ch-zrh-wg-001"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fake.calls()[0], "relay set location ch zrh ch-zrh-wg-001");
+}
+
+#[test]
+fn failing_command_reports_stderr() {
+    let fake = FakeMullvad::new();
+    fake.fail("relay set location");
+
+    let output = fake.run(&["connect", "se"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("relay set location se failed"));
+    // Connecting is not attempted after the location could not be set.
+    assert_eq!(fake.calls(), vec!["relay set location se"]);
+}
+
+#[test]
+fn mullvad_bin_from_environment() {
+    let fake = FakeMullvad::new();
+    let output = fake
+        .command()
+        .env("MULLVAD_BIN", &fake.bin)
+        .arg("list")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fake.calls(), vec!["relay list"]);
+}
+
+#[test]
+fn missing_binary() {
+    let fake = FakeMullvad::new();
+    let output = fake
+        .command()
+        .arg("--mullvad-bin")
+        .arg(fake.dir().join("does-not-exist"))
+        .arg("status")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Failed to run"));
+}
+
+#[test]
+fn bar_waybar() {
+    let fake = FakeMullvad::new();
+    fake.run(&["connect", "se", "got"]);
+    let output = fake.run(&["bar", "--format", "waybar"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let line: serde_json::Value = serde_json::from_str(stdout(&output).trim()).unwrap();
+    assert_eq!(line["class"], "connected");
+    assert!(line["text"].as_str().unwrap().contains("se-got-wg-001"));
+}
+
+/// Answers one request on a socket in `dir` with `response`, returning the request.
+fn control_stub(dir: &Path, response: &'static str) -> (PathBuf, thread::JoinHandle<String>) {
+    let path = dir.join("mullvad-tui.sock");
+    let listener = UnixListener::bind(&path).unwrap();
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request).unwrap();
+        writeln!(&stream, "{}", response).unwrap();
+        request
+    });
+    (path, handle)
+}
+
+#[test]
+fn ctl_sends_request() {
+    let fake = FakeMullvad::new();
+    let (socket, stub) = control_stub(fake.dir(), r#"{"ok":true}"#);
+    let output = fake
+        .command()
+        .arg("--socket")
+        .arg(&socket)
+        .args(["ctl", "connect", "se", "got"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let request: serde_json::Value = serde_json::from_str(&stub.join().unwrap()).unwrap();
+    assert_eq!(
+        request,
+        serde_json::json!({"cmd": "connect", "location": ["se", "got"]})
+    );
+    assert_eq!(stdout(&output).trim(), r#"{"ok":true}"#);
+}
+
+#[test]
+fn ctl_reports_errors() {
+    let fake = FakeMullvad::new();
+    let (socket, _stub) =
+        control_stub(fake.dir(), r#"{"ok":false,"error":"Unknown location: xx"}"#);
+    let output = fake
+        .command()
+        .env("MULLVAD_TUI_SOCKET", &socket)
+        .args(["ctl", "navigate", "xx"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Unknown location: xx"));
+}
+
+#[test]
+fn verbose_logs_backend_commands() {
+    let fake = FakeMullvad::new();
+    fake.fail("relay set");
+    fake.run(&["status"]);
+    // Successful commands are only logged at debug level.
+    assert!(!fake.log().contains("status -v"));
+
+    fake.run(&["-vv", "status"]);
+    let log = fake.log();
+    assert!(log.contains("DEBUG backend: "), "{}", log);
+    assert!(log.contains("status -v exit=0 duration_ms="));
+
+    fake.run(&["connect", "se"]);
+    assert!(fake.log().contains("WARN  backend: "));
+    assert!(fake
+        .log()
+        .contains("relay set location se exit=1 duration_ms="));
+    assert!(fake
+        .log()
+        .contains("stderr=\"Error: relay set location se failed\""));
+}
+
+#[test]
+fn profile_apply() {
+    let fake = FakeMullvad::new();
+    let config = concat!(env!("CARGO_MANIFEST_DIR"), "/config-example.toml");
+
+    let output = fake.run(&["--config", config, "profile", "list"]);
+    assert_eq!(stdout(&output), "streaming\nwork\n");
+
+    let output = fake.run(&["--config", config, "profile", "apply", "work"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "Location: not connected -> se got\n\
+         Tunnel protocol: any -> wireguard\n\
+         DNS: no blocking -> blocks ads, trackers, malware\n\
+         Lockdown mode: off -> on\n\
+         Auto-connect: off -> on\n\
+         Skipped the filter, it only applies in the TUI\n\
+         Applied profile work\n"
+    );
+    let calls = fake.calls();
+    let applied = calls
+        .iter()
+        .position(|c| c == "relay set tunnel-protocol wireguard")
+        .unwrap();
+    assert_eq!(
+        calls[applied..],
+        [
+            "relay set tunnel-protocol wireguard",
+            "dns set default --block-ads --block-trackers --block-malware",
+            "lockdown-mode set on",
+            "auto-connect set on",
+            "relay set location se got",
+            "connect",
+        ]
+    );
+
+    // Connected in Gothenburg already, the tunnel is left alone.
+    fake.write("status", common::CONNECTED);
+    let output = fake.run(&["--config", config, "profile", "apply", "work"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with("Tunnel protocol"));
+    let calls = fake.calls();
+    assert_eq!(calls.last().unwrap(), "auto-connect set on");
+
+    let output = fake.run(&["--config", config, "profile", "apply", "gaming"]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("Unknown profile: gaming"));
+}
+
+#[test]
+fn export_then_import() {
+    let fake = FakeMullvad::new();
+    let example = concat!(env!("CARGO_MANIFEST_DIR"), "/config-example.toml");
+
+    let output = fake.run(&["--config", example, "export", "--format", "json"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json["daemon"]["lockdown"], false);
+    assert_eq!(json["daemon"]["bridge_mode"], "auto");
+    assert_eq!(json["profiles"]["work"]["location"], "se got");
+    assert_eq!(json["theme"]["connected"], "#859900");
+
+    // Another machine with lockdown mode on and a config of its own.
+    let export = fake.dir().join("export.toml");
+    let output = fake.run(&["--config", example, "export", export.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    fake.write(
+        "lockdown",
+        "Block traffic when the VPN is disconnected: on\n",
+    );
+    let config = fake.dir().join("config.toml");
+    let theme = std::fs::read_to_string(example).unwrap();
+    let theme = &theme[theme.find("[colors]").unwrap()..theme.find("[notifications]").unwrap()];
+    let mine = format!("# Mine\n{}", theme.replace("#859900", "#00ff00"));
+    std::fs::write(&config, &mine).unwrap();
+    let args = [
+        "--config",
+        config.to_str().unwrap(),
+        "import",
+        export.to_str().unwrap(),
+    ];
+
+    let output = fake.run(&[&args[..], &["--dry-run"]].concat());
+    assert!(output.status.success(), "{}", stderr(&output));
+    let diff = stdout(&output);
+    assert!(
+        diff.starts_with("Lockdown mode: on -> off\nProfile streaming: unset -> {"),
+        "{}",
+        diff
+    );
+    assert!(diff.contains(
+        "Color connected: #00ff00 -> #859900\nColor items_selected: #00ff00 -> #859900\n"
+    ));
+    assert!(!fake.calls().iter().any(|c| c.contains(" set ")));
+    assert_eq!(std::fs::read_to_string(&config).unwrap(), mine);
+
+    let output = fake.run(&args);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let sets: Vec<String> = fake
+        .calls()
+        .into_iter()
+        .filter(|c| c.contains(" set "))
+        .collect();
+    assert_eq!(sets, vec!["lockdown-mode set off"]);
+    let merged = std::fs::read_to_string(&config).unwrap();
+    assert!(
+        merged.starts_with("# Mine\n[colors]\nconnected = \"#859900\"\n"),
+        "{}",
+        merged
+    );
+    assert!(merged.contains("[profiles.work]\n"), "{}", merged);
+    assert!(merged.contains("dns = { block_ads = true"), "{}", merged);
+
+    // The merged config works and leaves nothing else to import.
+    fake.write(
+        "lockdown",
+        "Block traffic when the VPN is disconnected: off\n",
+    );
+    let output = fake.run(&args);
+    assert_eq!(stdout(&output), "Nothing to change\n");
+    let output = fake.run(&["--config", config.to_str().unwrap(), "profile", "list"]);
+    assert_eq!(stdout(&output), "streaming\nwork\n");
+}
+
+#[test]
+fn import_without_config() {
+    let fake = FakeMullvad::new();
+    let export = fake.dir().join("export.toml");
+    std::fs::write(
+        &export,
+        "history = []\n\n[daemon]\n\n[favorites]\nwork = \"se got\"\n",
+    )
+    .unwrap();
+    let config = fake.dir().join("new").join("config.toml");
+    let output = fake.run(&[
+        "--config",
+        config.to_str().unwrap(),
+        "import",
+        export.to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        std::fs::read_to_string(&config).unwrap(),
+        "[favorites]\nwork = \"se got\"\n"
+    );
+
+    // The written config loads even though the export had no theme.
+    let output = fake.run(&["--config", config.to_str().unwrap(), "profile", "list"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn export_then_import_without_colors() {
+    let fake = FakeMullvad::new();
+    let config = fake.dir().join("config.toml");
+    std::fs::write(&config, "[favorites]\nwork = \"se got\"\n").unwrap();
+    let export = fake.dir().join("export.toml");
+    let output = fake.run(&[
+        "--config",
+        config.to_str().unwrap(),
+        "export",
+        export.to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let text = std::fs::read_to_string(&export).unwrap();
+    assert!(!text.contains("theme"), "{}", text);
+
+    // Another machine without a config takes the favorite and no colors.
+    let other = fake.dir().join("other").join("config.toml");
+    let args = [
+        "--config",
+        other.to_str().unwrap(),
+        "import",
+        export.to_str().unwrap(),
+    ];
+    let output = fake.run(&[&args[..], &["--dry-run"]].concat());
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Favorite work: unset -> se got\n");
+    let output = fake.run(&args);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        std::fs::read_to_string(&other).unwrap(),
+        "[favorites]\nwork = \"se got\"\n"
+    );
+}
+
+#[test]
+fn startup_error_leaves_terminal_alone() {
+    let fake = FakeMullvad::new();
+    fake.fail("relay list");
+    let config = concat!(env!("CARGO_MANIFEST_DIR"), "/config-example.toml");
+    let output = fake.run(&["--config", config]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("Error: mullvad relay list"),
+        "{}",
+        stderr(&output)
+    );
+    // The alternate screen was never entered.
+    assert!(!stdout(&output).contains("\x1b[?1049h"));
+}
+
+/// Starts the TUI on a pseudo terminal through util-linux `script`, returning
+/// `script` and the TUI's pid once the TUI handles SIGHUP, SIGTERM and SIGTSTP.
+fn spawn_tui(fake: &FakeMullvad) -> (Child, u32) {
+    let pid_file = fake.dir().join("pid");
+    let tui = format!(
+        "echo $$ > '{}'; exec '{}' --mullvad-bin '{}' --config '{}/config-example.toml'",
+        pid_file.display(),
+        env!("CARGO_BIN_EXE_mullvad-tui"),
+        fake.bin.display(),
+        env!("CARGO_MANIFEST_DIR"),
+    );
+    let inner = fake.command();
+    let mut command = Command::new("script");
+    command
+        .args(["--quiet", "--return", "--command", &tui, "/dev/null"])
+        .envs(inner.get_envs().filter_map(|(k, v)| Some((k, v?))))
+        // Open stdin keeps `script` from ending the session.
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let child = command.spawn().unwrap();
+
+    // SIGHUP, SIGTERM and SIGTSTP in the `SigCgt` mask.
+    let handled: u64 = [1, 15, 20].iter().map(|signal| 1 << (signal - 1)).sum();
+    let pid = wait_for(|| {
+        let pid: u32 = fs::read_to_string(&pid_file).ok()?.trim().parse().ok()?;
+        let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+        let caught = status.lines().find_map(|l| l.strip_prefix("SigCgt:"))?;
+        let caught = u64::from_str_radix(caught.trim(), 16).ok()?;
+        (caught & handled == handled).then_some(pid)
+    });
+    (child, pid)
+}
+
+/// Polls `check` for up to five seconds.
+fn wait_for<T>(mut check: impl FnMut() -> Option<T>) -> T {
+    let start = Instant::now();
+    loop {
+        if let Some(value) = check() {
+            return value;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn kill(signal: &str, pid: u32) {
+    let status = Command::new("kill")
+        .args([&format!("-{}", signal), &pid.to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+/// The state letter from `/proc/<pid>/stat`, e.g. `T` when stopped.
+fn process_state(pid: u32) -> Option<char> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    stat[stat.rfind(')')? + 1..].trim_start().chars().next()
+}
+
+#[test]
+fn signals_exit_and_suspend() {
+    let fake = FakeMullvad::new();
+    // Leaving the alternate screen.
+    let restored = "\x1b[?1049l";
+
+    for signal in ["TERM", "HUP"] {
+        let (child, pid) = spawn_tui(&fake);
+        kill(signal, pid);
+        let output = child.wait_with_output().unwrap();
+        assert!(
+            output.status.success(),
+            "SIG{}: {}",
+            signal,
+            stderr(&output)
+        );
+        assert!(stdout(&output).contains(restored), "SIG{}", signal);
+    }
+
+    let (child, pid) = spawn_tui(&fake);
+    kill("TSTP", pid);
+    wait_for(|| (process_state(pid)? == 'T').then_some(()));
+    // `script` stops itself along with the TUI.
+    kill("CONT", pid);
+    kill("CONT", child.id());
+    wait_for(|| (process_state(pid)? != 'T').then_some(()));
+    kill("TERM", pid);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output).matches(restored).count(), 2);
+}
@@ -0,0 +1,161 @@
+//! A fake `mullvad` executable for driving the real CLI code path.
+//!
+//! `FakeMullvad` writes a shell script into a temporary directory that answers
+//! the subset of `mullvad` subcommands mullvad-tui uses with canned output,
+//! records every invocation and fails on demand.
+
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use tempfile::TempDir;
+
+pub const RELAY_LIST: &str = "\
+Sweden (se)
+\tGothenburg (got) @ 57.70887°N, 11.97456°E
+\t\tse-got-wg-001 (185.213.154.66, 2a03:1b20:5:f011::a01f) - WireGuard, hosted by 31173 (Mullvad-owned)
+\tMalmö (mma) @ 55.60587°N, 13.00073°E
+\t\tse-mma-wg-001 (193.138.218.220, 2a03:1b20:1:f011::a01f) - WireGuard, hosted by 31173 (Mullvad-owned)
+Switzerland (ch)
+\tZurich (zrh) @ 47.36667°N, 8.55000°E
+\t\tch-zrh-wg-001 (193.32.127.66, 2a03:1b20:9:f011::a01f) - WireGuard, hosted by M247 (rented)
+";
+
+pub const CONNECTED: &str = "\
+Connected to se-got-wg-001 in Gothenburg, Sweden
+Relay:                  se-got-wg-001 (185.213.154.66:51820/UDP)
+Tunnel type:            WireGuard
+Visible location:       Sweden, Gothenburg. IPv4: 185.213.154.68
+";
+
+const SCRIPT: &str = r#"#!/bin/sh
+dir="$(dirname "$0")"
+echo "$*" >> "$dir/calls"
+
+if [ -f "$dir/fail" ]; then
+    while read -r failing; do
+        case "$*" in
+            "$failing"*) echo "Error: $* failed" >&2; exit 1 ;;
+        esac
+    done < "$dir/fail"
+fi
+
+case "$*" in
+    "relay list") cat "$dir/relay_list" ;;
+    "status" | "status -v") cat "$dir/status" ;;
+    "connect" | "reconnect") cp "$dir/connected" "$dir/status" ;;
+    "disconnect") echo "Disconnected" > "$dir/status" ;;
+    "relay set "* | "relay update") ;;
+    "relay get") cat "$dir/relay_get" ;;
+    "dns get") cat "$dir/dns" ;;
+    "lockdown-mode get") cat "$dir/lockdown" ;;
+    "auto-connect get") cat "$dir/auto_connect" ;;
+    "bridge get") printf 'Bridge mode: auto\nBridge constraints: any\n' ;;
+    "obfuscation get") printf 'Obfuscation mode: auto\n' ;;
+    "dns set "* | "lockdown-mode set "* | "auto-connect set "*) ;;
+    "bridge set "* | "obfuscation set "*) ;;
+    "custom-list list") cat "$dir/custom_lists" ;;
+    "custom-list "*) ;;
+    *) echo "fake mullvad: unsupported command: $*" >&2; exit 1 ;;
+esac
+"#;
+
+pub struct FakeMullvad {
+    dir: TempDir,
+    pub bin: PathBuf,
+}
+
+impl FakeMullvad {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("mullvad");
+        fs::write(&bin, SCRIPT).unwrap();
+        fs::set_permissions(&bin, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let fake = Self { dir, bin };
+        fake.write("relay_list", RELAY_LIST);
+        fake.write("status", "Disconnected\n");
+        fake.write("connected", CONNECTED);
+        fake.write("custom_lists", "");
+        fake.write(
+            "relay_get",
+            "Generic constraints\n\tLocation: any\n\tTunnel protocol: any\n",
+        );
+        fake.write(
+            "dns",
+            "Custom DNS: no\nBlock ads: false\nBlock trackers: false\n",
+        );
+        fake.write(
+            "lockdown",
+            "Block traffic when the VPN is disconnected: off\n",
+        );
+        fake.write("auto_connect", "Autoconnect: off\n");
+        fake
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn write(&self, file: &str, contents: &str) {
+        fs::write(self.dir.path().join(file), contents).unwrap();
+    }
+
+    /// Makes every command line starting with `prefix` exit with an error.
+    pub fn fail(&self, prefix: &str) {
+        let path = self.dir.path().join("fail");
+        let mut failing = fs::read_to_string(&path).unwrap_or_default();
+        failing.push_str(prefix);
+        failing.push('\n');
+        fs::write(path, failing).unwrap();
+    }
+
+    /// Every command line the fake has been invoked with, in order.
+    pub fn calls(&self) -> Vec<String> {
+        fs::read_to_string(self.dir.path().join("calls"))
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// mullvad-tui with its home, log file and caches kept in the fake's
+    /// directory, so the developer's own config is never read.
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_mullvad-tui"));
+        command
+            .env("HOME", self.dir.path())
+            .env("XDG_CONFIG_HOME", self.dir.path().join("config"))
+            .env("XDG_STATE_HOME", self.dir.path().join("state"))
+            .env("XDG_CACHE_HOME", self.dir.path().join("cache"));
+        command
+    }
+
+    /// Runs mullvad-tui against this fake.
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.command()
+            .arg("--mullvad-bin")
+            .arg(&self.bin)
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    pub fn log(&self) -> String {
+        fs::read_to_string(self.dir.path().join("state/mullvad-tui/mullvad-tui.log"))
+            .unwrap_or_default()
+    }
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}